@@ -3,7 +3,7 @@
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
-use syn::{Error, FnArg, ItemFn, ItemTrait, ReturnType, TraitItem};
+use syn::{Error, FnArg, ItemFn, ItemTrait, ReturnType, TraitItem, Type};
 
 fn compiler_error(err: Error) -> TokenStream {
     err.to_compile_error().into()
@@ -42,6 +42,40 @@ fn common_main(item: TokenStream, arg_num: usize, export_name: &str, err_msg: &s
     }
 }
 
+/// Generates an entry that passes the boot information to the main function,
+/// if its signature is `fn(cpu_id: usize, boot_info: &BootInfo) -> !`.
+fn main_with_boot_info(item: TokenStream, export_name: &str) -> Option<TokenStream> {
+    let main = syn::parse::<ItemFn>(item).ok()?;
+    if !matches!(&main.sig.output, ReturnType::Type(_, ty) if quote! { #ty }.to_string() == "!") {
+        return None;
+    }
+    let args = &main.sig.inputs;
+    let (Some(FnArg::Typed(cpu_id)), Some(FnArg::Typed(boot_info)), 2) =
+        (args.first(), args.last(), args.len())
+    else {
+        return None;
+    };
+    let cpu_id_ty = &cpu_id.ty;
+    if quote! { #cpu_id_ty }.to_string() != "usize" || !matches!(*boot_info.ty, Type::Reference(_))
+    {
+        return None;
+    }
+
+    let name = &main.sig.ident;
+    Some(
+        quote! {
+            #main
+
+            #[doc(hidden)]
+            #[unsafe(export_name = #export_name)]
+            fn __axplat_main_entry(cpu_id: usize, _arg: usize) -> ! {
+                #name(cpu_id, ::axplat::boot::boot_info())
+            }
+        }
+        .into(),
+    )
+}
+
 /// Marks a function to be called on the primary core after the platform
 /// initialization.
 ///
@@ -50,6 +84,10 @@ fn common_main(item: TokenStream, arg_num: usize, export_name: &str, err_msg: &s
 /// cores on the platform), and `arg` is passed from the bootloader (typically
 /// the device tree blob address).
 ///
+/// The function can also take the boot information parsed by the platform
+/// instead of `arg`, with the signature
+/// `fn(cpu_id: usize, boot_info: &'static axplat::boot::BootInfo) -> !`.
+///
 /// # Example
 ///
 /// ```rust
@@ -66,11 +104,15 @@ pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
             "expect an empty attribute or `#[axplat::main]`",
         ));
     };
+    if let Some(output) = main_with_boot_info(item.clone(), "__axplat_main") {
+        return output;
+    }
     common_main(
         item,
         2,
         "__axplat_main",
-        "expect a function with type `fn(cpu_id: usize, arg: usize) -> !`",
+        "expect a function with type `fn(cpu_id: usize, arg: usize) -> !` or \
+        `fn(cpu_id: usize, boot_info: &'static BootInfo) -> !`",
     )
 }
 
@@ -0,0 +1,165 @@
+//! Boot information passed from the bootloader.
+
+use lazyinit::LazyInit;
+
+use crate::mem::{PhysAddr, RawRange, ranges_difference};
+
+/// The maximum number of regions in [`BootInfo::memory_map`].
+pub const MAX_MEMORY_REGIONS: usize = 64;
+
+static BOOT_INFO: LazyInit<BootInfo> = LazyInit::new();
+
+/// Type of a memory region in the memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemRegionKind {
+    /// Free RAM that can be used by the kernel.
+    Usable,
+    /// Memory that is reserved by the firmware or the bootloader.
+    Reserved,
+    /// Memory holding the ACPI tables, which can be reused after the tables
+    /// are parsed.
+    AcpiReclaimable,
+    /// Memory that should be preserved across ACPI sleep states.
+    AcpiNvs,
+    /// Defective RAM.
+    Bad,
+}
+
+/// A physical memory region reported by the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemRegion {
+    /// Start physical address of the region.
+    pub paddr: PhysAddr,
+    /// Size in bytes of the region.
+    pub size: usize,
+    /// Type of the region.
+    pub kind: MemRegionKind,
+}
+
+/// A linear framebuffer set up by the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    /// Start physical address of the framebuffer.
+    pub paddr: PhysAddr,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Number of bytes per line.
+    pub pitch: u32,
+    /// Number of bits per pixel.
+    pub bpp: u8,
+}
+
+/// Information passed from the bootloader, in a platform-independent form.
+///
+/// Fields that are not provided by the bootloader are `None` (or empty).
+#[derive(Debug, Clone)]
+pub struct BootInfo {
+    /// The raw argument passed from the bootloader, i.e., the `arg` of
+    /// [`call_main`](crate::call_main).
+    pub arg: usize,
+    /// Kernel command line.
+    pub cmdline: Option<&'static str>,
+    /// Physical memory range of the initial ramdisk, with format (`paddr`,
+    /// `size`).
+    pub initrd: Option<RawRange>,
+    /// Physical address of the flattened device tree blob.
+    pub fdt: Option<PhysAddr>,
+    /// Physical address of the ACPI RSDP (Root System Description Pointer).
+    pub acpi_rsdp: Option<PhysAddr>,
+    /// Physical address of the multiboot information structure.
+    pub multiboot: Option<PhysAddr>,
+    /// Framebuffer set up by the bootloader.
+    pub framebuffer: Option<FramebufferInfo>,
+    memory_map: [MemRegion; MAX_MEMORY_REGIONS],
+    memory_map_len: usize,
+}
+
+impl BootInfo {
+    /// Creates a new `BootInfo` with only the raw argument.
+    pub const fn new(arg: usize) -> Self {
+        const EMPTY: MemRegion = MemRegion {
+            paddr: PhysAddr::from_usize(0),
+            size: 0,
+            kind: MemRegionKind::Reserved,
+        };
+        Self {
+            arg,
+            cmdline: None,
+            initrd: None,
+            fdt: None,
+            acpi_rsdp: None,
+            multiboot: None,
+            framebuffer: None,
+            memory_map: [EMPTY; MAX_MEMORY_REGIONS],
+            memory_map_len: 0,
+        }
+    }
+
+    /// Returns the memory map reported by the bootloader.
+    pub fn memory_map(&self) -> &[MemRegion] {
+        &self.memory_map[..self.memory_map_len]
+    }
+
+    /// Appends a region to the memory map.
+    ///
+    /// Returns `false` if the memory map is full (has [`MAX_MEMORY_REGIONS`]
+    /// regions).
+    pub fn add_memory_region(&mut self, region: MemRegion) -> bool {
+        if self.memory_map_len == MAX_MEMORY_REGIONS {
+            return false;
+        }
+        self.memory_map[self.memory_map_len] = region;
+        self.memory_map_len += 1;
+        true
+    }
+
+    /// Fills the memory map with the given RAM ranges and reserved ranges,
+    /// which are usually the ones returned by [`MemIf`](crate::mem::MemIf).
+    ///
+    /// The reserved parts of RAM ranges are not reported as
+    /// [`MemRegionKind::Usable`]. `reserved` must be sorted and
+    /// non-overlapping. The resulting memory map is sorted by address.
+    pub fn fill_memory_map(&mut self, ram: &[RawRange], reserved: &[RawRange]) {
+        let mut add = |(start, size): RawRange, kind| {
+            self.add_memory_region(MemRegion {
+                paddr: PhysAddr::from_usize(start),
+                size,
+                kind,
+            });
+        };
+        reserved
+            .iter()
+            .for_each(|&r| add(r, MemRegionKind::Reserved));
+        ranges_difference(ram, reserved, |r| add(r, MemRegionKind::Usable)).ok();
+        self.memory_map[..self.memory_map_len].sort_unstable_by_key(|r| r.paddr);
+    }
+}
+
+/// Boot information interface.
+#[def_plat_interface]
+pub trait BootIf {
+    /// Parses the boot information from the argument passed by the bootloader.
+    ///
+    /// It is called by [`call_main`](crate::call_main) on the primary core,
+    /// before the main function and [`init_early`](crate::init::init_early).
+    /// So it should not rely on the console, the trap handlers, or any
+    /// other platform state initialized later. Problems found here should be
+    /// logged later, e.g., in [`init_later`](crate::init::init_later).
+    fn parse_boot_info(arg: usize) -> BootInfo;
+}
+
+/// Parses and saves the boot information.
+pub(crate) fn init(arg: usize) {
+    BOOT_INFO.init_once(parse_boot_info(arg));
+}
+
+/// Returns the boot information passed from the bootloader.
+///
+/// # Panics
+///
+/// Panics if it is called before [`call_main`](crate::call_main).
+pub fn boot_info() -> &'static BootInfo {
+    &BOOT_INFO
+}
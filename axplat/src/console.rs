@@ -1,6 +1,9 @@
 //! Console input and output.
 
 use core::fmt::{Arguments, Result, Write};
+#[cfg(feature = "irq")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 
 /// Console input and output interface.
 #[def_plat_interface]
@@ -14,16 +17,421 @@ pub trait ConsoleIf {
     fn read_bytes(bytes: &mut [u8]) -> usize;
 
     /// Returns the IRQ number for the console, if applicable.
+    ///
+    /// Platforms that buffer input by interrupts handle this IRQ themselves.
     #[cfg(feature = "irq")]
     fn irq_number() -> Option<u32> {
         None
     }
+
+    /// Waits until all bytes written to the console are sent.
+    ///
+    /// Platforms that buffer output must implement it.
+    fn flush() {}
+
+    /// Writes given bytes to the console synchronously, without taking any
+    /// lock.
+    ///
+    /// It is used for panic output, which must not wait for a lock held by
+    /// the panicked CPU, or by the CPU itself in NMI-like contexts. The output
+    /// may be mixed with that of the lock holder.
+    ///
+    /// Platforms whose [`write_bytes`] takes a lock should implement it. The
+    /// default falls back to [`write_bytes`].
+    fn write_bytes_nolock(bytes: &[u8]) {
+        Self::write_bytes(bytes)
+    }
+}
+
+/// A lock-free ring buffer of console bytes.
+///
+/// For input, it is filled by the console IRQ handler of the platform, and
+/// drained by [`ConsoleIf::read_bytes`]. Bytes received when the buffer is
+/// full are dropped. For output, see [`TxRing`].
+#[cfg(feature = "irq")]
+pub struct ByteRing<const N: usize> {
+    buf: [AtomicU8; N],
+    /// Total number of bytes read.
+    head: AtomicUsize,
+    /// Total number of bytes written.
+    tail: AtomicUsize,
+}
+
+#[cfg(feature = "irq")]
+impl<const N: usize> ByteRing<N> {
+    /// Creates an empty ring buffer.
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { AtomicU8::new(0) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of buffered bytes.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    /// Returns `true` if there are no buffered bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a byte to the buffer.
+    ///
+    /// It must not be called concurrently, i.e., there is only one producer.
+    /// Returns `false` if the buffer is full and the byte is dropped.
+    pub fn push(&self, byte: u8) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) >= N {
+            return false;
+        }
+        self.buf[tail % N].store(byte, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest byte from the buffer.
+    pub fn pop(&self) -> Option<u8> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            if head == self.tail.load(Ordering::Acquire) {
+                return None;
+            }
+            // The slot is not overwritten until `head` moves past it.
+            let byte = self.buf[head % N].load(Ordering::Relaxed);
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(byte),
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    /// Moves the buffered bytes into the given mutable slice.
+    ///
+    /// Returns the number of bytes read.
+    pub fn read(&self, bytes: &mut [u8]) -> usize {
+        let mut read_len = 0;
+        while read_len < bytes.len() {
+            match self.pop() {
+                Some(c) => bytes[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        read_len
+    }
+}
+
+#[cfg(feature = "irq")]
+impl<const N: usize> Default for ByteRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What to do when a byte is written but the console TX buffer is full.
+#[cfg(feature = "irq")]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOverflow {
+    /// Drops the byte.
+    Drop = 0,
+    /// Waits until the oldest buffered byte is sent.
+    Block = 1,
+}
+
+#[cfg(feature = "irq")]
+static TX_OVERFLOW: AtomicU8 = AtomicU8::new(TxOverflow::Block as u8);
+
+/// Sets what to do when the console TX buffer is full.
+///
+/// The default is [`TxOverflow::Block`].
+#[cfg(feature = "irq")]
+pub fn set_tx_overflow(policy: TxOverflow) {
+    TX_OVERFLOW.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns what to do when the console TX buffer is full.
+#[cfg(feature = "irq")]
+pub fn tx_overflow() -> TxOverflow {
+    match TX_OVERFLOW.load(Ordering::Relaxed) {
+        0 => TxOverflow::Drop,
+        _ => TxOverflow::Block,
+    }
+}
+
+/// A UART transmitter, which sends the bytes of a [`TxRing`] or the panic
+/// output.
+pub trait Transmitter {
+    /// Returns `true` if the transmitter can accept a byte.
+    fn is_ready(&mut self) -> bool;
+
+    /// Writes a byte to the transmitter.
+    ///
+    /// It is called only if [`is_ready`](Self::is_ready) returns `true`.
+    fn send(&mut self, byte: u8);
+
+    /// Waits until the transmitter is ready, and writes a byte to it.
+    fn send_blocking(&mut self, byte: u8) {
+        while !self.is_ready() {
+            core::hint::spin_loop();
+        }
+        self.send(byte);
+    }
+}
+
+/// A buffer of console bytes to be sent by the UART.
+///
+/// A byte is sent directly if the transmitter has room and nothing is
+/// buffered, otherwise it is buffered and sent later by [`TxRing::drain`] in
+/// the TX interrupt handler of the platform. Before [`TxRing::enable`], bytes
+/// are sent synchronously.
+///
+/// The methods must be called with the UART locked, except
+/// [`TxRing::flush`].
+#[cfg(feature = "irq")]
+pub struct TxRing<const N: usize> {
+    ring: ByteRing<N>,
+    enabled: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+#[cfg(feature = "irq")]
+impl<const N: usize> TxRing<N> {
+    /// Creates an empty buffer, which is not enabled.
+    pub const fn new() -> Self {
+        Self {
+            ring: ByteRing::new(),
+            enabled: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts buffering bytes.
+    ///
+    /// It is called by the platform after the TX interrupt handler is
+    /// registered.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Returns the number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Returns `true` if there are no buffered bytes.
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Returns the number of bytes dropped because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes a byte, according to [`tx_overflow`] if the buffer is full.
+    ///
+    /// Returns `true` if there are buffered bytes, and the TX interrupt should
+    /// be enabled.
+    pub fn write(&self, tx: &mut impl Transmitter, byte: u8) -> bool {
+        if !self.enabled.load(Ordering::Acquire) {
+            tx.send_blocking(byte);
+            return false;
+        }
+        if self.ring.is_empty() && tx.is_ready() {
+            tx.send(byte);
+            return false;
+        }
+        while !self.ring.push(byte) {
+            match tx_overflow() {
+                TxOverflow::Drop => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                TxOverflow::Block => {
+                    if let Some(c) = self.ring.pop() {
+                        tx.send_blocking(c);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Sends the buffered bytes while the transmitter has room.
+    ///
+    /// It is called by the TX interrupt handler. Returns `true` if there are
+    /// still buffered bytes, otherwise the TX interrupt should be disabled.
+    pub fn drain(&self, tx: &mut impl Transmitter) -> bool {
+        while tx.is_ready() {
+            match self.ring.pop() {
+                Some(c) => tx.send(c),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Sends all buffered bytes, waiting for the transmitter.
+    ///
+    /// It can be called without the UART locked, e.g., before panic output.
+    pub fn flush(&self, tx: &mut impl Transmitter) {
+        while let Some(c) = self.ring.pop() {
+            tx.send_blocking(c);
+        }
+    }
+}
+
+#[cfg(feature = "irq")]
+impl<const N: usize> Default for TxRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The function called when console input arrives, or 0 if not set.
+#[cfg(feature = "irq")]
+static INPUT_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function called when console input arrives, usually to wake up
+/// the tasks waiting for input.
+///
+/// It is called in the IRQ context, after the input is buffered and can be
+/// read by [`read_bytes`]. `None` removes the function.
+#[cfg(feature = "irq")]
+pub fn set_input_handler(handler: Option<fn()>) {
+    INPUT_HANDLER.store(handler.map_or(0, |f| f as usize), Ordering::Release);
 }
 
-struct EarlyConsole;
+/// Calls the function set by [`set_input_handler`], if any.
+///
+/// It is called by the console IRQ handler of the platform.
+#[cfg(feature = "irq")]
+pub fn notify_input() {
+    let handler = INPUT_HANDLER.load(Ordering::Acquire);
+    if handler != 0 {
+        // SAFETY: `handler` is stored from a `fn()` by `set_input_handler`.
+        unsafe { core::mem::transmute::<usize, fn()>(handler)() };
+    }
+}
+
+/// A buffer that keeps the latest console output, e.g., for a dmesg-style
+/// kernel log.
+///
+/// Bytes are addressed by their positions in the whole output, i.e., the
+/// number of bytes written before them. When the buffer is full, the oldest
+/// bytes are overwritten.
+pub struct LogBuffer<const N: usize> {
+    buf: [AtomicU8; N],
+    /// Number of bytes that have started being written.
+    reserved: AtomicUsize,
+    /// Number of bytes written.
+    committed: AtomicUsize,
+}
+
+impl<const N: usize> LogBuffer<N> {
+    /// Creates an empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { AtomicU8::new(0) }; N],
+            reserved: AtomicUsize::new(0),
+            committed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends bytes to the buffer, overwriting the oldest ones if it is full.
+    ///
+    /// It must not be called concurrently, e.g., it is called with
+    /// [`CONSOLE_LOCK`] held.
+    pub fn write(&self, bytes: &[u8]) {
+        // Only the last `N` bytes are kept.
+        let skip = bytes.len().saturating_sub(N);
+        let start = self.committed.load(Ordering::Relaxed) + skip;
+        let bytes = &bytes[skip..];
+        self.reserved.store(start + bytes.len(), Ordering::Relaxed);
+        core::sync::atomic::fence(Ordering::Release);
+        for (i, &c) in bytes.iter().enumerate() {
+            self.buf[(start + i) % N].store(c, Ordering::Relaxed);
+        }
+        self.committed.store(start + bytes.len(), Ordering::Release);
+    }
+
+    /// Returns the position after the last written byte, i.e., the number of
+    /// bytes written since the buffer is created.
+    pub fn end(&self) -> usize {
+        self.committed.load(Ordering::Acquire)
+    }
+
+    /// Returns the position of the oldest byte kept in the buffer.
+    pub fn start(&self) -> usize {
+        self.end().saturating_sub(N)
+    }
+
+    /// Reads the bytes from position `pos` into the given mutable slice, and
+    /// advances `pos` past them.
+    ///
+    /// If the bytes at `pos` are overwritten, it starts from the oldest byte
+    /// kept instead. Returns the number of bytes read.
+    pub fn read(&self, pos: &mut usize, bytes: &mut [u8]) -> usize {
+        let end = self.end();
+        let mut start = (*pos).clamp(end.saturating_sub(N), end);
+        let mut len = (end - start).min(bytes.len());
+        for (i, c) in bytes[..len].iter_mut().enumerate() {
+            *c = self.buf[(start + i) % N].load(Ordering::Relaxed);
+        }
+        // Discard the bytes that are overwritten while reading.
+        core::sync::atomic::fence(Ordering::Acquire);
+        let valid_start = self.reserved.load(Ordering::Relaxed).saturating_sub(N);
+        if valid_start > start {
+            let skip = (valid_start - start).min(len);
+            bytes.copy_within(skip..len, 0);
+            start += skip;
+            len -= skip;
+        }
+        *pos = start + len;
+        len
+    }
+}
+
+impl<const N: usize> Default for LogBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Size of [`LOG_BUFFER`] in bytes.
+#[cfg(feature = "log-buffer")]
+pub const LOG_BUFFER_SIZE: usize = 0x4000;
+
+/// The latest output printed by [`console_print!`] and the other console
+/// macros.
+///
+/// It keeps the output even if there is no console device. It is enabled by
+/// the `log-buffer` feature.
+#[cfg(feature = "log-buffer")]
+pub static LOG_BUFFER: LogBuffer<LOG_BUFFER_SIZE> = LogBuffer::new();
+
+struct EarlyConsole {
+    /// Collects the output into records of the persistent log.
+    #[cfg(feature = "pstore")]
+    pstore: crate::pstore::RecordWriter,
+}
 
 impl Write for EarlyConsole {
     fn write_str(&mut self, s: &str) -> Result {
+        #[cfg(feature = "log-buffer")]
+        LOG_BUFFER.write(s.as_bytes());
+        #[cfg(feature = "pstore")]
+        self.pstore.write(s.as_bytes());
         write_bytes(s.as_bytes());
         Ok(())
     }
@@ -52,6 +460,200 @@ macro_rules! console_println {
 #[doc(hidden)]
 pub fn __simple_print(fmt: Arguments) {
     let _guard = CONSOLE_LOCK.lock();
-    EarlyConsole.write_fmt(fmt).unwrap();
+    let mut console = EarlyConsole {
+        #[cfg(feature = "pstore")]
+        pstore: crate::pstore::RecordWriter::new(),
+    };
+    console.write_fmt(fmt).unwrap();
+    #[cfg(feature = "pstore")]
+    console.pstore.flush();
     drop(_guard);
 }
+
+/// Number of attempts to take [`CONSOLE_LOCK`] before the panic output
+/// bypasses it.
+const PANIC_LOCK_ATTEMPTS: usize = 0x10_0000;
+
+/// The panic output, with whether [`CONSOLE_LOCK`] is held.
+struct PanicConsole {
+    #[cfg(feature = "log-buffer")]
+    locked: bool,
+    /// Collects the output into records of the persistent log.
+    #[cfg(feature = "pstore")]
+    pstore: crate::pstore::RecordWriter,
+}
+
+impl Write for PanicConsole {
+    fn write_str(&mut self, s: &str) -> Result {
+        // `LOG_BUFFER` must not be written concurrently, so the output is not
+        // kept there if the lock is bypassed.
+        #[cfg(feature = "log-buffer")]
+        if self.locked {
+            LOG_BUFFER.write(s.as_bytes());
+        }
+        #[cfg(feature = "pstore")]
+        self.pstore.write(s.as_bytes());
+        write_bytes_nolock(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Console print operation for panics, with a newline.
+///
+/// The output is tagged with the given CPU ID. It waits for [`CONSOLE_LOCK`]
+/// for a bounded time, and then bypasses it, so it does not deadlock if the
+/// lock is held by a panicked CPU or by the current CPU itself. It can be used
+/// in NMI-like contexts. If the lock is bypassed, the output is still written
+/// to the persistent log, but not to `LOG_BUFFER`.
+#[macro_export]
+macro_rules! console_panic_println {
+    ($cpu_id:expr) => {
+        $crate::console::__panic_print($cpu_id, format_args!("\n"))
+    };
+    ($cpu_id:expr, $($arg:tt)*) => {
+        $crate::console::__panic_print(
+            $cpu_id,
+            format_args!("{}\n", format_args!($($arg)*)),
+        )
+    };
+}
+
+#[doc(hidden)]
+pub fn __panic_print(cpu_id: usize, fmt: Arguments) {
+    let guard = (0..PANIC_LOCK_ATTEMPTS).find_map(|_| {
+        let guard = CONSOLE_LOCK.try_lock();
+        if guard.is_none() {
+            core::hint::spin_loop();
+        }
+        guard
+    });
+    // Ignore the error, as there is nothing to do in a panic.
+    let mut console = PanicConsole {
+        #[cfg(feature = "log-buffer")]
+        locked: guard.is_some(),
+        #[cfg(feature = "pstore")]
+        pstore: crate::pstore::RecordWriter::new(),
+    };
+    let _ = console.write_fmt(format_args!("[CPU {cpu_id}] {fmt}"));
+    #[cfg(feature = "pstore")]
+    console.pstore.flush();
+    drop(guard);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogBuffer;
+    #[cfg(feature = "irq")]
+    use super::{ByteRing, Transmitter, TxOverflow, TxRing, set_tx_overflow};
+
+    #[test]
+    fn log_buffer() {
+        let log = LogBuffer::<8>::new();
+        let mut buf = [0; 8];
+        let mut pos = 0;
+        assert_eq!(log.read(&mut pos, &mut buf), 0);
+        log.write(b"hello");
+        assert_eq!(log.read(&mut pos, &mut buf[..3]), 3);
+        assert_eq!(&buf[..3], b"hel");
+        assert_eq!(log.read(&mut pos, &mut buf), 2);
+        assert_eq!(&buf[..2], b"lo");
+        assert_eq!(pos, 5);
+
+        // Overwrite "hello" except "o".
+        log.write(b" world!");
+        assert_eq!((log.start(), log.end()), (4, 12));
+        let mut pos = 0;
+        assert_eq!(log.read(&mut pos, &mut buf), 8);
+        assert_eq!(&buf, b"o world!");
+        assert_eq!(pos, 12);
+
+        // Only the end of a long write is kept.
+        log.write(b"0123456789");
+        assert_eq!(log.read(&mut pos, &mut buf), 8);
+        assert_eq!(&buf, b"23456789");
+        assert_eq!(pos, 22);
+    }
+
+    #[cfg(feature = "irq")]
+    #[test]
+    fn byte_ring() {
+        let ring = ByteRing::<4>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+        for c in b"abcd" {
+            assert!(ring.push(*c));
+        }
+        assert!(!ring.push(b'e'));
+        assert_eq!(ring.len(), 4);
+
+        let mut buf = [0; 3];
+        assert_eq!(ring.read(&mut buf), 3);
+        assert_eq!(&buf, b"abc");
+        // Wrap around the end of the buffer.
+        assert!(ring.push(b'f') && ring.push(b'g'));
+        assert_eq!(ring.read(&mut buf), 3);
+        assert_eq!(&buf, b"dfg");
+        assert_eq!(ring.read(&mut buf), 0);
+    }
+
+    /// A transmitter that accepts a byte after `ready` polls.
+    #[cfg(feature = "irq")]
+    struct MockTx {
+        sent: [u8; 16],
+        len: usize,
+        polls: usize,
+        ready: usize,
+    }
+
+    #[cfg(feature = "irq")]
+    impl Transmitter for MockTx {
+        fn is_ready(&mut self) -> bool {
+            self.polls += 1;
+            self.polls > self.ready
+        }
+
+        fn send(&mut self, byte: u8) {
+            self.sent[self.len] = byte;
+            self.len += 1;
+            self.polls = 0;
+        }
+    }
+
+    #[cfg(feature = "irq")]
+    #[test]
+    fn tx_ring() {
+        let mut tx = MockTx {
+            sent: [0; 16],
+            len: 0,
+            polls: 0,
+            ready: 0,
+        };
+        let ring = TxRing::<2>::new();
+        // Sent synchronously before enabled.
+        tx.ready = 2;
+        assert!(!ring.write(&mut tx, b'a'));
+        assert_eq!(&tx.sent[..tx.len], b"a");
+
+        ring.enable();
+        assert!(ring.write(&mut tx, b'b'));
+        assert!(ring.write(&mut tx, b'c'));
+        // The oldest byte is sent when the buffer is full.
+        assert!(ring.write(&mut tx, b'd'));
+        assert_eq!(&tx.sent[..tx.len], b"ab");
+        set_tx_overflow(TxOverflow::Drop);
+        assert!(ring.write(&mut tx, b'e'));
+        assert_eq!(ring.dropped(), 1);
+        set_tx_overflow(TxOverflow::Block);
+
+        tx.ready = 0;
+        assert!(!ring.drain(&mut tx));
+        assert!(ring.is_empty());
+        assert_eq!(&tx.sent[..tx.len], b"abcd");
+        // Sent directly if nothing is buffered.
+        assert!(!ring.write(&mut tx, b'f'));
+        tx.ready = 2;
+        assert!(ring.write(&mut tx, b'g'));
+        ring.flush(&mut tx);
+        assert_eq!(&tx.sent[..tx.len], b"abcdfg");
+    }
+}
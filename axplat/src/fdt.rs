@@ -0,0 +1,1106 @@
+//! Flattened device tree (FDT) parsing.
+//!
+//! It provides a minimal, allocation-free parser of the device tree blob (DTB)
+//! passed by the bootloader, which is mainly used by platforms to discover the
+//! physical memory layout and the boot information at boot time.
+//!
+//! See the [Devicetree Specification](https://www.devicetree.org/specifications/)
+//! for details of the format.
+
+use core::ops::Range;
+
+use lazyinit::LazyInit;
+
+use crate::boot::{BootInfo, FramebufferInfo};
+use crate::mem::{
+    PAGE_SIZE_4K, PhysAddr, RawRange, phys_ram_ranges, phys_to_virt, ranges_difference,
+    reserved_phys_ram_ranges,
+};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+const FDT_MIN_VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// The maximum depth of nodes that can be handled.
+const MAX_DEPTH: usize = 16;
+
+/// Errors that can occur when parsing a device tree blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    /// The blob does not start with the FDT magic number.
+    BadMagic,
+    /// The blob version is not supported (older than 17).
+    BadVersion,
+    /// The blob is truncated, or some block is out of its bounds.
+    Truncated,
+}
+
+fn be32(data: &[u8], off: usize) -> Option<u32> {
+    let bytes = data.get(off..off.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn be64(data: &[u8], off: usize) -> Option<u64> {
+    let bytes = data.get(off..off.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+const fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Reads a number of `cells` 32-bit big-endian cells from the front of `data`,
+/// and advances `data`.
+///
+/// Numbers longer than 64 bits are truncated to the lower 64 bits.
+fn read_cells(data: &mut &[u8], cells: u32) -> Option<u64> {
+    let len = cells as usize * 4;
+    if data.len() < len {
+        return None;
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Some(value.chunks_exact(4).fold(0u64, |acc, c| {
+        (acc << 32) | u32::from_be_bytes(c.try_into().unwrap()) as u64
+    }))
+}
+
+/// A token in the structure block.
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    BeginNode(&'a [u8]),
+    EndNode,
+    Prop(&'a [u8], &'a [u8]),
+}
+
+/// An iterator over the tokens in the structure block.
+///
+/// It stops at the `FDT_END` token, or at the first malformed token.
+struct Tokens<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+    off: usize,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let token = be32(self.structs, self.off)?;
+            self.off += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let rest = self.structs.get(self.off..)?;
+                    let len = rest.iter().position(|&b| b == 0)?;
+                    self.off += align4(len + 1);
+                    return Some(Token::BeginNode(&rest[..len]));
+                }
+                FDT_END_NODE => return Some(Token::EndNode),
+                FDT_PROP => {
+                    let len = be32(self.structs, self.off)? as usize;
+                    let name_off = be32(self.structs, self.off + 4)? as usize;
+                    let value = self.structs.get(self.off + 8..self.off + 8 + len)?;
+                    self.off += 8 + align4(len);
+                    let name = self.strings.get(name_off..)?;
+                    let name = &name[..name.iter().position(|&b| b == 0)?];
+                    return Some(Token::Prop(name, value));
+                }
+                FDT_NOP => continue,
+                FDT_END => return None,
+                _ => return None, // unknown tokens
+            }
+        }
+    }
+}
+
+/// A parsed flattened device tree blob.
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    data: &'a [u8],
+    structs: &'a [u8],
+    strings: &'a [u8],
+    mem_rsvmap: usize,
+}
+
+impl<'a> Fdt<'a> {
+    /// Parses the device tree blob from the given byte slice.
+    ///
+    /// Only the header is validated, malformed contents in the structure block
+    /// are ignored.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |idx: usize| be32(data, idx * 4).ok_or(FdtError::Truncated);
+        if header(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        if header(5)? < FDT_MIN_VERSION {
+            return Err(FdtError::BadVersion);
+        }
+        let total_size = header(1)? as usize;
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let block = |off: usize, size: usize| {
+            off.checked_add(size)
+                .and_then(|end| data.get(off..end))
+                .ok_or(FdtError::Truncated)
+        };
+        let structs = block(header(2)? as usize, header(9)? as usize)?;
+        let strings = block(header(3)? as usize, header(8)? as usize)?;
+        Ok(Self {
+            data,
+            structs,
+            strings,
+            mem_rsvmap: header(4)? as usize,
+        })
+    }
+
+    /// Parses the device tree blob at the given address.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to readable memory that contains at least the FDT
+    /// header, and the whole blob if the header is valid.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Fdt<'static>, FdtError> {
+        let header = unsafe { core::slice::from_raw_parts(ptr, FDT_HEADER_SIZE) };
+        if be32(header, 0) != Some(FDT_MAGIC) {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = be32(header, 4).unwrap() as usize;
+        if total_size < FDT_HEADER_SIZE {
+            return Err(FdtError::Truncated);
+        }
+        Fdt::from_bytes(unsafe { core::slice::from_raw_parts(ptr, total_size) })
+    }
+
+    /// Returns the total size in bytes of the blob.
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns an iterator over the entries of the memory reservation block
+    /// (`/memreserve/`).
+    pub fn memory_reservations(&self) -> impl Iterator<Item = RawRange> + 'a {
+        let data = self.data;
+        let mut off = self.mem_rsvmap;
+        core::iter::from_fn(move || {
+            let addr = be64(data, off)?;
+            let size = be64(data, off + 8)?;
+            off += 16;
+            if addr == 0 && size == 0 {
+                None
+            } else {
+                Some((addr as usize, size as usize))
+            }
+        })
+    }
+
+    fn tokens(&self) -> Tokens<'a> {
+        Tokens {
+            structs: self.structs,
+            strings: self.strings,
+            off: 0,
+        }
+    }
+
+    /// Finds the value of the property `name` in the node at `path`.
+    ///
+    /// `path` is an absolute path like `/chosen` or `/soc/serial@10000000`. A
+    /// path component without the unit address (e.g., `serial`) matches the
+    /// first node with that name regardless of its unit address.
+    pub fn find_property(&self, path: &str, name: &str) -> Option<&'a [u8]> {
+        let mut comps = [""; MAX_DEPTH];
+        let mut ncomps = 0;
+        for comp in path.split('/').filter(|c| !c.is_empty()) {
+            *comps.get_mut(ncomps)? = comp;
+            ncomps += 1;
+        }
+
+        // `depth` is the depth of the current node (root = 1), `matched` is
+        // the depth of the deepest node on the current branch that matches.
+        let (mut depth, mut matched) = (0, 0);
+        for token in self.tokens() {
+            match token {
+                Token::BeginNode(node_name) => {
+                    depth += 1;
+                    if depth == 1 {
+                        matched = 1;
+                    } else if matched == depth - 1
+                        && depth - 1 <= ncomps
+                        && node_name_matches(node_name, comps[depth - 2])
+                    {
+                        matched = depth;
+                    }
+                }
+                Token::EndNode => {
+                    if depth == 0 {
+                        break;
+                    }
+                    if matched == depth {
+                        matched -= 1;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Token::Prop(prop_name, value) => {
+                    if matched == depth && depth == ncomps + 1 && prop_name == name.as_bytes() {
+                        return Some(value);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+fn node_name_matches(node_name: &[u8], comp: &str) -> bool {
+    let comp = comp.as_bytes();
+    if comp.contains(&b'@') {
+        node_name == comp
+    } else {
+        node_name.split(|&b| b == b'@').next() == Some(comp)
+    }
+}
+
+/// A fixed-capacity list of sorted, non-overlapping ranges.
+struct RangeList<const N: usize> {
+    ranges: [RawRange; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> RangeList<N> {
+    const fn new() -> Self {
+        Self {
+            ranges: [(0, 0); N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    fn as_slice(&self) -> &[RawRange] {
+        &self.ranges[..self.len]
+    }
+
+    /// Adds a range, the list needs to be [`normalize`]d afterwards.
+    ///
+    /// Empty ranges are ignored, and so are the malformed ones that would
+    /// overflow the address space once aligned to page boundaries, so the
+    /// ranges in the list never overflow.
+    fn push(&mut self, range: RawRange) {
+        let aligned_end = (range.0.checked_add(range.1))
+            .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE_4K));
+        if range.1 == 0 || aligned_end.is_none() {
+            return;
+        }
+        if self.len == N {
+            self.normalize();
+        }
+        if self.len == N {
+            self.truncated = true;
+        } else {
+            self.ranges[self.len] = range;
+            self.len += 1;
+        }
+    }
+
+    /// Sorts the ranges and merges the overlapping or adjacent ones.
+    fn normalize(&mut self) {
+        let ranges = &mut self.ranges[..self.len];
+        ranges.sort_unstable_by_key(|r| r.0);
+        let mut len = 0;
+        for i in 0..ranges.len() {
+            let (start, size) = ranges[i];
+            if len > 0 {
+                let last = &mut ranges[len - 1];
+                if start <= last.0 + last.1 {
+                    last.1 = last.1.max(start + size - last.0);
+                    continue;
+                }
+            }
+            ranges[len] = (start, size);
+            len += 1;
+        }
+        self.len = len;
+    }
+
+    /// Shrinks each range to page boundaries, the ranges becoming empty are
+    /// removed.
+    fn align_inward(&mut self) {
+        let mut len = 0;
+        for i in 0..self.len {
+            let (start, size) = self.ranges[i];
+            let end = (start + size) & !(PAGE_SIZE_4K - 1);
+            let start = start.next_multiple_of(PAGE_SIZE_4K);
+            if start < end {
+                self.ranges[len] = (start, end - start);
+                len += 1;
+            }
+        }
+        self.len = len;
+    }
+
+    /// Extends each range to page boundaries, and merges the ranges that
+    /// become overlapping.
+    fn align_outward(&mut self) {
+        for range in &mut self.ranges[..self.len] {
+            let start = range.0 & !(PAGE_SIZE_4K - 1);
+            let end = (range.0 + range.1).next_multiple_of(PAGE_SIZE_4K);
+            *range = (start, end - start);
+        }
+        self.normalize();
+    }
+}
+
+/// Per-node states kept while walking the tree.
+#[derive(Clone, Copy)]
+struct NodeState<'a> {
+    name: &'a [u8],
+    reg: Option<&'a [u8]>,
+    ranges: Option<&'a [u8]>,
+    /// `#address-cells` for children.
+    addr_cells: u32,
+    /// `#size-cells` for children.
+    size_cells: u32,
+    is_memory: bool,
+    is_pci: bool,
+    no_map: bool,
+}
+
+impl NodeState<'_> {
+    const fn new() -> Self {
+        Self {
+            name: &[],
+            reg: None,
+            ranges: None,
+            addr_cells: 2,
+            size_cells: 1,
+            is_memory: false,
+            is_pci: false,
+            no_map: false,
+        }
+    }
+}
+
+/// Translates an address in the address space of `nodes.last()`'s children
+/// to the CPU physical address, through the `ranges` properties of the
+/// ancestors.
+///
+/// Returns `None` if the address is not memory-mapped.
+fn translate_addr(nodes: &[NodeState], mut addr: u64) -> Option<u64> {
+    for i in (1..nodes.len()).rev() {
+        let (node, parent) = (&nodes[i], &nodes[i - 1]);
+        if node.is_pci || node.addr_cells > 2 {
+            return None;
+        }
+        let mut ranges = node.ranges?;
+        if ranges.is_empty() {
+            continue; // identity mapping
+        }
+        let mut translated = None;
+        while !ranges.is_empty() {
+            let child = read_cells(&mut ranges, node.addr_cells)?;
+            let parent_addr = read_cells(&mut ranges, parent.addr_cells)?;
+            let size = read_cells(&mut ranges, node.size_cells)?;
+            if addr >= child && addr - child < size {
+                translated = Some(addr - child + parent_addr);
+                break;
+            }
+        }
+        addr = translated?;
+    }
+    Some(addr)
+}
+
+/// Calls `f` for each entry in the `reg` property of `nodes.last()`, with the
+/// address translated to the CPU physical address.
+fn for_each_reg(nodes: &[NodeState], mut f: impl FnMut(RawRange)) {
+    let (node, parents) = nodes.split_last().unwrap();
+    let Some(parent) = parents.last() else {
+        return;
+    };
+    let Some(mut reg) = node.reg else {
+        return;
+    };
+    if parent.addr_cells > 2 || parent.size_cells == 0 || parent.size_cells > 2 {
+        return;
+    }
+    while let (Some(addr), Some(size)) = (
+        read_cells(&mut reg, parent.addr_cells),
+        read_cells(&mut reg, parent.size_cells),
+    ) {
+        if let Some(paddr) = translate_addr(parents, addr) {
+            f((paddr as usize, size as usize));
+        }
+    }
+}
+
+/// Calls `f` for each memory window (in CPU physical address) described by
+/// the `ranges` property of the PCI host bridge `nodes.last()`.
+fn for_each_pci_window(nodes: &[NodeState], mut f: impl FnMut(RawRange)) {
+    let (node, parents) = nodes.split_last().unwrap();
+    let (Some(parent), Some(mut ranges)) = (parents.last(), node.ranges) else {
+        return;
+    };
+    while !ranges.is_empty() {
+        let (Some(_), Some(cpu_addr), Some(size)) = (
+            read_cells(&mut ranges, node.addr_cells),
+            read_cells(&mut ranges, parent.addr_cells),
+            read_cells(&mut ranges, node.size_cells),
+        ) else {
+            break;
+        };
+        if let Some(paddr) = translate_addr(parents, cpu_addr) {
+            f((paddr as usize, size as usize));
+        }
+    }
+}
+
+/// Physical memory layout of the platform, discovered from the device tree.
+///
+/// It consists of three kinds of ranges, each of which can hold at most `N`
+/// entries after merging:
+///
+/// - RAM ranges: from the `/memory` nodes, excluding the `no-map` regions in
+///   `/reserved-memory`.
+/// - Reserved ranges: from the memory reservation block, the other regions in
+///   `/reserved-memory`, and the device tree blob itself.
+/// - MMIO ranges: from the `reg` properties of device nodes, and the memory
+///   windows of PCI host bridges.
+///
+/// All ranges are sorted, non-overlapping and page-aligned (RAM ranges are
+/// shrunk, others are extended), so they can be returned by
+/// [`MemIf`](crate::mem::MemIf) directly.
+pub struct MemLayout<const N: usize> {
+    ram: RangeList<N>,
+    reserved: RangeList<N>,
+    mmio: RangeList<N>,
+}
+
+impl<const N: usize> MemLayout<N> {
+    /// Discovers the memory layout from the device tree.
+    ///
+    /// `fdt_paddr` is the physical address of the device tree blob, which is
+    /// reported as a reserved range.
+    pub fn from_fdt(fdt: &Fdt, fdt_paddr: usize) -> Self {
+        let mut ram = RangeList::<N>::new();
+        let mut reserved = RangeList::<N>::new();
+        let mut no_map = RangeList::<N>::new();
+        let mut mmio = RangeList::<N>::new();
+
+        reserved.push((fdt_paddr, fdt.total_size()));
+        fdt.memory_reservations().for_each(|r| reserved.push(r));
+
+        let mut nodes = [NodeState::new(); MAX_DEPTH];
+        let mut depth = 0;
+        for token in fdt.tokens() {
+            match token {
+                Token::BeginNode(name) => {
+                    depth += 1;
+                    if depth <= MAX_DEPTH {
+                        nodes[depth - 1] = NodeState {
+                            name,
+                            ..NodeState::new()
+                        };
+                    }
+                }
+                Token::Prop(name, value) if depth > 0 && depth <= MAX_DEPTH => {
+                    let node = &mut nodes[depth - 1];
+                    let cells = || be32(value, 0).unwrap_or(0);
+                    match name {
+                        b"reg" => node.reg = Some(value),
+                        b"ranges" => node.ranges = Some(value),
+                        b"#address-cells" => node.addr_cells = cells(),
+                        b"#size-cells" => node.size_cells = cells(),
+                        b"no-map" => node.no_map = true,
+                        b"device_type" => {
+                            node.is_memory = value == b"memory\0";
+                            node.is_pci = value == b"pci\0";
+                        }
+                        _ => {}
+                    }
+                }
+                Token::Prop(..) => {}
+                Token::EndNode => {
+                    if depth == 0 {
+                        break;
+                    }
+                    if depth <= MAX_DEPTH && depth > 1 {
+                        let nodes = &nodes[..depth];
+                        let node = &nodes[depth - 1];
+                        let parent = &nodes[depth - 2];
+                        if depth == 2 && node_name_matches(node.name, "memory") || node.is_memory {
+                            for_each_reg(nodes, |r| ram.push(r));
+                        } else if depth == 3 && parent.name == b"reserved-memory" {
+                            if node.no_map {
+                                for_each_reg(nodes, |r| no_map.push(r));
+                            } else {
+                                for_each_reg(nodes, |r| reserved.push(r));
+                            }
+                        } else if node.name != b"reserved-memory" {
+                            for_each_reg(nodes, |r| mmio.push(r));
+                            if node.is_pci {
+                                for_each_pci_window(nodes, |r| mmio.push(r));
+                            }
+                        }
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+
+        ram.normalize();
+        no_map.normalize();
+        let mut layout = Self {
+            ram: RangeList::new(),
+            reserved,
+            mmio,
+        };
+        layout.ram.truncated = ram.truncated || no_map.truncated;
+        ranges_difference(ram.as_slice(), no_map.as_slice(), |r| layout.ram.push(r)).ok();
+        layout.ram.align_inward();
+        layout.reserved.align_outward();
+        layout.mmio.align_outward();
+        layout
+    }
+
+    /// Adds a reserved range that is not described in the device tree (e.g.,
+    /// boot information left by the firmware).
+    pub fn add_reserved(&mut self, range: RawRange) {
+        self.reserved.push(range);
+        self.reserved.align_outward();
+    }
+
+    /// Adds a MMIO range that is not described in the device tree.
+    pub fn add_mmio(&mut self, range: RawRange) {
+        self.mmio.push(range);
+        self.mmio.align_outward();
+    }
+
+    /// Removes a range from the RAM ranges, it is useful for the memory used
+    /// by the firmware but not reported in the device tree.
+    pub fn exclude_ram(&mut self, range: RawRange) {
+        if range.0.checked_add(range.1).is_none() {
+            return;
+        }
+        let old = core::mem::replace(&mut self.ram, RangeList::new());
+        self.ram.truncated = old.truncated;
+        ranges_difference(old.as_slice(), &[range], |r| self.ram.push(r)).ok();
+        self.ram.align_inward();
+    }
+
+    /// Returns the RAM ranges.
+    pub fn ram_ranges(&self) -> &[RawRange] {
+        self.ram.as_slice()
+    }
+
+    /// Returns the reserved ranges.
+    pub fn reserved_ranges(&self) -> &[RawRange] {
+        self.reserved.as_slice()
+    }
+
+    /// Returns the MMIO ranges.
+    pub fn mmio_ranges(&self) -> &[RawRange] {
+        self.mmio.as_slice()
+    }
+
+    /// Returns `true` if some ranges are dropped because there are more than
+    /// `N` ranges of a kind.
+    pub fn is_truncated(&self) -> bool {
+        self.ram.truncated || self.reserved.truncated || self.mmio.truncated
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for MemLayout<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        struct Ranges<'a>(&'a [RawRange]);
+        impl core::fmt::Debug for Ranges<'_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_list()
+                    .entries(self.0.iter().map(|&(start, size)| {
+                        let r: Range<usize> = start..start + size;
+                        r
+                    }))
+                    .finish()
+            }
+        }
+        f.debug_struct("MemLayout")
+            .field("ram", &Ranges(self.ram_ranges()))
+            .field("reserved", &Ranges(self.reserved_ranges()))
+            .field("mmio", &Ranges(self.mmio_ranges()))
+            .finish()
+    }
+}
+
+/// Static memory configuration of a platform, see [`PlatformMem`].
+pub struct MemConfig {
+    /// RAM ranges used if the device tree is not available.
+    pub ram: &'static [RawRange],
+    /// Reserved ranges that are not described in the device tree.
+    pub reserved: &'static [RawRange],
+    /// MMIO ranges used by the platform, which are kept even if they are
+    /// missing in the device tree.
+    pub mmio: &'static [RawRange],
+    /// Ranges that are never used as RAM, e.g., the memory used by the
+    /// firmware but not reported in the device tree.
+    pub excluded_ram: &'static [RawRange],
+    /// Physical addresses where the device tree blob is expected to be placed
+    /// by the bootloader, or `None` if it may be anywhere. A blob at other
+    /// addresses (or at 0) is not touched.
+    pub fdt_range: Option<Range<usize>>,
+}
+
+/// Memory layout of a platform, which is discovered from the device tree at
+/// boot, or is the static configuration if the device tree is not available.
+///
+/// It is meant to be a static of the platform that backs its
+/// [`MemIf`](crate::mem::MemIf) implementation.
+pub struct PlatformMem<const N: usize> {
+    config: MemConfig,
+    layout: LazyInit<MemLayout<N>>,
+    /// Error in parsing the device tree, with the address of the blob.
+    error: LazyInit<(usize, FdtError)>,
+}
+
+impl<const N: usize> PlatformMem<N> {
+    /// Creates a memory layout with the given static configuration.
+    pub const fn new(config: MemConfig) -> Self {
+        Self {
+            config,
+            layout: LazyInit::new(),
+            error: LazyInit::new(),
+        }
+    }
+
+    /// Discovers the memory layout from the device tree at `fdt_paddr`, and
+    /// returns the parsed device tree.
+    ///
+    /// The ranges in the configuration are applied to the discovered layout.
+    /// It runs before the logger is ready, so the problems are logged later by
+    /// [`log_warnings`](Self::log_warnings).
+    pub fn init(&self, fdt_paddr: usize) -> Option<Fdt<'static>> {
+        let expected = match &self.config.fdt_range {
+            Some(range) => range.contains(&fdt_paddr),
+            None => fdt_paddr != 0,
+        };
+        if !expected {
+            return None;
+        }
+        let ptr = phys_to_virt(PhysAddr::from_usize(fdt_paddr)).as_ptr();
+        let fdt = match unsafe { Fdt::from_ptr(ptr) } {
+            Ok(fdt) => fdt,
+            Err(e) => {
+                self.error.init_once((fdt_paddr, e));
+                return None;
+            }
+        };
+        let mut layout = MemLayout::from_fdt(&fdt, fdt_paddr);
+        for &r in self.config.excluded_ram {
+            layout.exclude_ram(r);
+        }
+        for &r in self.config.reserved {
+            layout.add_reserved(r);
+        }
+        for &r in self.config.mmio {
+            layout.add_mmio(r);
+        }
+        self.layout.init_once(layout);
+        Some(fdt)
+    }
+
+    /// Logs the problems in the device tree found by [`init`](Self::init).
+    pub fn log_warnings(&self) {
+        if let Some((paddr, e)) = self.error.get() {
+            log::warn!("Failed to parse the device tree at {paddr:#x}: {e:?}");
+        }
+        if self.layout.get().is_some_and(|l| l.is_truncated()) {
+            log::warn!("Too many memory ranges in the device tree, some are ignored");
+        }
+    }
+
+    /// Returns the RAM ranges, for
+    /// [`MemIf::phys_ram_ranges`](crate::mem::MemIf::phys_ram_ranges).
+    pub fn ram_ranges(&self) -> &[RawRange] {
+        match self.layout.get() {
+            Some(layout) => layout.ram_ranges(),
+            None => self.config.ram,
+        }
+    }
+
+    /// Returns the reserved ranges, for
+    /// [`MemIf::reserved_phys_ram_ranges`](crate::mem::MemIf::reserved_phys_ram_ranges).
+    pub fn reserved_ranges(&self) -> &[RawRange] {
+        match self.layout.get() {
+            Some(layout) => layout.reserved_ranges(),
+            None => self.config.reserved,
+        }
+    }
+
+    /// Returns the MMIO ranges, for
+    /// [`MemIf::mmio_ranges`](crate::mem::MemIf::mmio_ranges).
+    pub fn mmio_ranges(&self) -> &[RawRange] {
+        match self.layout.get() {
+            Some(layout) => layout.mmio_ranges(),
+            None => self.config.mmio,
+        }
+    }
+}
+
+impl BootInfo {
+    /// Creates a `BootInfo` from the device tree blob passed by the
+    /// bootloader, for [`BootIf::parse_boot_info`](crate::boot::BootIf).
+    ///
+    /// `fdt` is the parsed blob at `fdt_paddr`, or `None` if it is not
+    /// available, then only the raw argument is kept. It reads the command line
+    /// (`bootargs`), the initial ramdisk (`linux,initrd-start` and
+    /// `linux,initrd-end`) and the simple framebuffer from the `/chosen` node.
+    ///
+    /// The memory map is filled with the ranges returned by
+    /// [`MemIf`](crate::mem::MemIf), so the platform should have discovered its
+    /// memory layout before.
+    pub fn from_fdt(fdt_paddr: usize, fdt: Option<&Fdt<'static>>) -> Self {
+        let mut info = match fdt {
+            Some(fdt) => Self::from_chosen(fdt_paddr, fdt),
+            None => Self::new(fdt_paddr),
+        };
+        info.fill_memory_map(phys_ram_ranges(), reserved_phys_ram_ranges());
+        info
+    }
+
+    fn from_chosen(fdt_paddr: usize, fdt: &Fdt<'static>) -> Self {
+        fn read_u64(value: &[u8]) -> Option<u64> {
+            match value.len() {
+                4 => Some(u32::from_be_bytes(value.try_into().unwrap()) as u64),
+                8 => Some(u64::from_be_bytes(value.try_into().unwrap())),
+                _ => None,
+            }
+        }
+        let read_u32 = |path, name| {
+            let value = fdt.find_property(path, name)?;
+            Some(u32::from_be_bytes(value.get(..4)?.try_into().unwrap()))
+        };
+
+        let mut info = Self::new(fdt_paddr);
+        info.fdt = Some(PhysAddr::from_usize(fdt_paddr));
+        info.cmdline = fdt
+            .find_property("/chosen", "bootargs")
+            .and_then(|v| core::str::from_utf8(v.strip_suffix(b"\0").unwrap_or(v)).ok())
+            .filter(|s| !s.is_empty());
+
+        let initrd_start = fdt.find_property("/chosen", "linux,initrd-start");
+        let initrd_end = fdt.find_property("/chosen", "linux,initrd-end");
+        if let (Some(start), Some(end)) = (
+            initrd_start.and_then(read_u64),
+            initrd_end.and_then(read_u64),
+        ) && start < end
+        {
+            info.initrd = Some((start as usize, (end - start) as usize));
+        }
+
+        const FB: &str = "/chosen/framebuffer";
+        if let Some(reg) = fdt.find_property(FB, "reg") {
+            let addr_cells = read_u32("/chosen", "#address-cells").unwrap_or(2) as usize;
+            let format = fdt.find_property(FB, "format").unwrap_or_default();
+            let bpp = match format.strip_suffix(b"\0").unwrap_or(format) {
+                b"r5g6b5" => Some(16),
+                b"r8g8b8" => Some(24),
+                b"a8r8g8b8" | b"x8r8g8b8" | b"a8b8g8r8" | b"x8b8g8r8" => Some(32),
+                _ => None,
+            };
+            if let (Some(paddr), Some(width), Some(height), Some(pitch), Some(bpp)) = (
+                reg.get(..addr_cells * 4).and_then(read_u64),
+                read_u32(FB, "width"),
+                read_u32(FB, "height"),
+                read_u32(FB, "stride"),
+                bpp,
+            ) {
+                info.framebuffer = Some(FramebufferInfo {
+                    paddr: PhysAddr::from_usize(paddr as usize),
+                    width,
+                    height,
+                    pitch,
+                    bpp,
+                });
+            }
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal device tree blob builder for tests.
+    #[derive(Default)]
+    struct Builder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+        rsvmap: Vec<(u64, u64)>,
+    }
+
+    impl Builder {
+        fn u32(&mut self, v: u32) {
+            self.structs.extend_from_slice(&v.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            while self.structs.len() % 4 != 0 {
+                self.structs.push(0);
+            }
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.u32(FDT_BEGIN_NODE);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.u32(FDT_END_NODE);
+            self
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.u32(FDT_PROP);
+            self.u32(value.len() as u32);
+            self.u32(name_off);
+            self.structs.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        fn build(&mut self) -> Vec<u8> {
+            self.u32(FDT_END);
+            let rsvmap_off = FDT_HEADER_SIZE;
+            let structs_off = rsvmap_off + (self.rsvmap.len() + 1) * 16;
+            let strings_off = structs_off + self.structs.len();
+            let total = strings_off + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total as u32,
+                structs_off as u32,
+                strings_off as u32,
+                rsvmap_off as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|v| v.to_be_bytes()).collect();
+            for &(addr, size) in self.rsvmap.iter().chain([(0, 0)].iter()) {
+                blob.extend_from_slice(&addr.to_be_bytes());
+                blob.extend_from_slice(&size.to_be_bytes());
+            }
+            blob.extend_from_slice(&self.structs);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn sample_fdt() -> Vec<u8> {
+        let mut b = Builder::default();
+        b.rsvmap.push((0x8000_0000, 0x1000));
+        b.begin("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2]);
+        {
+            b.begin("chosen").prop("bootargs", b"console=ttyS0\0").end();
+            b.begin("memory@80000000")
+                .prop("device_type", b"memory\0")
+                .cells("reg", &[0, 0x8000_0000, 0, 0x1000_0000])
+                .end();
+            b.begin("memory@100000000")
+                .prop("device_type", b"memory\0")
+                .cells("reg", &[0x1, 0, 0, 0x800_0800])
+                .end();
+            b.begin("reserved-memory")
+                .cells("#address-cells", &[2])
+                .cells("#size-cells", &[2])
+                .prop("ranges", &[]);
+            {
+                b.begin("mmode_resv0@80000000")
+                    .cells("reg", &[0, 0x8000_0000, 0, 0x4_0000])
+                    .prop("no-map", &[])
+                    .end();
+                b.begin("shmem@88000000")
+                    .cells("reg", &[0, 0x8800_0000, 0, 0x10_0000])
+                    .end();
+            }
+            b.end();
+            b.begin("cpus")
+                .cells("#address-cells", &[1])
+                .cells("#size-cells", &[0]);
+            b.begin("cpu@0").cells("reg", &[0]).end();
+            b.end();
+            b.begin("soc")
+                .cells("#address-cells", &[1])
+                .cells("#size-cells", &[1])
+                .cells("ranges", &[0x0, 0, 0x1000_0000, 0x100_0000]);
+            {
+                b.begin("serial@0").cells("reg", &[0x0, 0x100]).end();
+                b.begin("virtio_mmio@1000")
+                    .cells("reg", &[0x1000, 0x1000])
+                    .end();
+                b.begin("virtio_mmio@2000")
+                    .cells("reg", &[0x2000, 0x1000])
+                    .end();
+                b.begin("i2c@3000")
+                    .cells("reg", &[0x3000, 0x1000])
+                    .cells("#address-cells", &[1])
+                    .cells("#size-cells", &[0]);
+                b.begin("rtc@68").cells("reg", &[0x68]).end();
+                b.end();
+            }
+            b.end();
+            b.begin("pci@30000000")
+                .prop("device_type", b"pci\0")
+                .cells("#address-cells", &[3])
+                .cells("#size-cells", &[2])
+                .cells("reg", &[0, 0x3000_0000, 0, 0x1000_0000])
+                .cells(
+                    "ranges",
+                    &[
+                        [0x0100_0000, 0, 0, 0, 0x0300_0000, 0, 0x1_0000], // PIO
+                        [0x0200_0000, 0, 0x4000_0000, 0, 0x4000_0000, 0, 0x4000_0000], // MMIO
+                    ]
+                    .concat(),
+                )
+                .end();
+        }
+        b.end();
+        b.build()
+    }
+
+    #[test]
+    fn header() {
+        let mut blob = sample_fdt();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert_eq!(fdt.total_size(), blob.len());
+        assert_eq!(
+            fdt.memory_reservations().collect::<Vec<_>>(),
+            [(0x8000_0000, 0x1000)]
+        );
+        assert!(Fdt::from_bytes(&blob[..blob.len() - 1]).is_err());
+        blob[0] = 0;
+        assert_eq!(Fdt::from_bytes(&blob).err(), Some(FdtError::BadMagic));
+    }
+
+    #[test]
+    fn find_property() {
+        let blob = sample_fdt();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert_eq!(
+            fdt.find_property("/chosen", "bootargs"),
+            Some(&b"console=ttyS0\0"[..])
+        );
+        assert_eq!(
+            fdt.find_property("/soc/serial", "reg"),
+            Some(&[0, 0, 0, 0, 0, 0, 1, 0][..])
+        );
+        assert!(fdt.find_property("/soc/serial@0", "reg").is_some());
+        assert!(fdt.find_property("/soc/serial@1", "reg").is_none());
+        assert!(fdt.find_property("/soc", "bootargs").is_none());
+        assert!(fdt.find_property("/", "#size-cells").is_some());
+    }
+
+    #[test]
+    fn malformed_structure() {
+        // A property before the root node is ignored.
+        let blob = Builder::default()
+            .prop("bootargs", b"x\0")
+            .begin("")
+            .prop("model", b"m\0")
+            .end()
+            .build();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert_eq!(fdt.find_property("/", "model"), Some(&b"m\0"[..]));
+        let layout = MemLayout::<8>::from_fdt(&fdt, 0x8000_0000);
+        assert!(layout.ram_ranges().is_empty());
+
+        // An extra end of node stops the walk.
+        let blob = Builder::default()
+            .end()
+            .begin("")
+            .prop("model", b"m\0")
+            .end()
+            .build();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert!(fdt.find_property("/", "model").is_none());
+        let layout = MemLayout::<8>::from_fdt(&fdt, 0x8000_0000);
+        assert!(layout.ram_ranges().is_empty());
+    }
+
+    #[test]
+    fn mem_layout() {
+        let blob = sample_fdt();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        let mut layout = MemLayout::<8>::from_fdt(&fdt, 0x8100_0000);
+        assert!(!layout.is_truncated());
+        assert_eq!(
+            layout.ram_ranges(),
+            [(0x8004_0000, 0x0ffc_0000), (0x1_0000_0000, 0x800_0000)]
+        );
+        assert_eq!(
+            layout.reserved_ranges(),
+            [
+                (0x8000_0000, 0x1000),
+                (0x8100_0000, 0x1000),
+                (0x8800_0000, 0x10_0000)
+            ]
+        );
+        assert_eq!(
+            layout.mmio_ranges(),
+            [
+                (0x0300_0000, 0x1_0000),
+                (0x1000_0000, 0x4000),
+                (0x3000_0000, 0x5000_0000),
+            ]
+        );
+
+        layout.exclude_ram((0x8000_0000, 0x20_0000));
+        layout.add_reserved((0, 0x800));
+        layout.add_mmio((0x1000_8000, 0x1000));
+        assert_eq!(
+            layout.ram_ranges(),
+            [(0x8020_0000, 0x0fe0_0000), (0x1_0000_0000, 0x800_0000)]
+        );
+        assert_eq!(layout.reserved_ranges()[0], (0, 0x1000));
+        assert_eq!(layout.mmio_ranges()[1], (0x1000_0000, 0x4000));
+        assert_eq!(layout.mmio_ranges()[2], (0x1000_8000, 0x1000));
+
+        // Malformed ranges that overflow are dropped.
+        layout.add_mmio((usize::MAX - 0xfff, 0x2000));
+        layout.add_reserved((usize::MAX - 0x7ff, 0x400));
+        layout.exclude_ram((0x8000_0000, usize::MAX));
+        assert_eq!(layout.mmio_ranges().len(), 4);
+        assert_eq!(layout.reserved_ranges().len(), 4);
+        assert_eq!(layout.ram_ranges().len(), 2);
+    }
+
+    #[test]
+    fn mem_layout_truncated() {
+        let blob = sample_fdt();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        let layout = MemLayout::<2>::from_fdt(&fdt, 0x8100_0000);
+        assert!(layout.is_truncated());
+        assert_eq!(layout.mmio_ranges(), [(0x1000_0000, 0x2000)]);
+    }
+}
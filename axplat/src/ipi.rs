@@ -0,0 +1,201 @@
+//! Cross-CPU function calls over inter-processor interrupts (IPIs).
+//!
+//! A call is queued in the mailbox of each target CPU, and the IPI is sent by
+//! [`send_ipi`](crate::irq::send_ipi). The platform runs the pending calls by
+//! [`handle_calls`] when it receives the IPI, before the IPI handler registered
+//! by the kernel (if any).
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use lazyinit::LazyInit;
+
+use crate::irq::{CpuMask, IpiTarget};
+
+/// The type of a function called on other CPUs.
+///
+/// The argument is the one given to [`call`].
+pub type IpiCallback = fn(usize);
+
+/// The maximum number of pending calls of a CPU.
+pub const MAX_PENDING_CALLS: usize = 8;
+
+const SLOT_EMPTY: usize = 0;
+const SLOT_WRITING: usize = 1;
+const SLOT_READY: usize = 2;
+
+/// A pending call in a mailbox.
+struct CallSlot {
+    state: AtomicUsize,
+    func: AtomicUsize,
+    arg: AtomicUsize,
+    /// Number of CPUs that have not finished the call, or null if the caller
+    /// does not wait.
+    pending: AtomicPtr<AtomicUsize>,
+}
+
+impl CallSlot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(SLOT_EMPTY),
+            func: AtomicUsize::new(0),
+            arg: AtomicUsize::new(0),
+            pending: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+}
+
+type Mailbox = [CallSlot; MAX_PENDING_CALLS];
+
+static MAILBOXES: [Mailbox; CpuMask::MAX_CPUS] =
+    [const { [const { CallSlot::new() }; MAX_PENDING_CALLS] }; CpuMask::MAX_CPUS];
+
+static IPI_IRQ: LazyInit<usize> = LazyInit::new();
+
+/// Sets the IPI number used to deliver the calls.
+///
+/// It is called by the platform during initialization, which also calls
+/// [`handle_calls`] when it receives this IPI.
+pub fn init(ipi_irq: usize) {
+    IPI_IRQ.init_once(ipi_irq);
+}
+
+/// Returns the IPI number used to deliver the calls, or `None` if cross-CPU
+/// calls are not supported by the platform.
+pub fn ipi_irq() -> Option<usize> {
+    IPI_IRQ.get().copied()
+}
+
+/// Puts a call into the mailbox of the given CPU.
+///
+/// If the mailbox is full, it waits for the CPU to run some calls.
+fn queue(cpu_id: usize, func: IpiCallback, arg: usize, pending: *const AtomicUsize) {
+    let mailbox = &MAILBOXES[cpu_id];
+    let slot = loop {
+        let free = mailbox.iter().find(|slot| {
+            slot.state
+                .compare_exchange(
+                    SLOT_EMPTY,
+                    SLOT_WRITING,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        });
+        match free {
+            Some(slot) => break slot,
+            None => core::hint::spin_loop(),
+        }
+    };
+    slot.func.store(func as usize, Ordering::Relaxed);
+    slot.arg.store(arg, Ordering::Relaxed);
+    slot.pending.store(pending as *mut _, Ordering::Relaxed);
+    slot.state.store(SLOT_READY, Ordering::Release);
+}
+
+/// Runs the pending calls of the given CPU, in no particular order.
+///
+/// It is called by the platform on the CPU when it receives the IPI. Returns
+/// `true` if any call is run.
+pub fn handle_calls(cpu_id: usize) -> bool {
+    let Some(mailbox) = MAILBOXES.get(cpu_id) else {
+        return false;
+    };
+    let mut called = false;
+    for slot in mailbox {
+        if slot.state.load(Ordering::Acquire) != SLOT_READY {
+            continue;
+        }
+        let func = slot.func.load(Ordering::Relaxed);
+        let arg = slot.arg.load(Ordering::Relaxed);
+        let pending = slot.pending.load(Ordering::Relaxed);
+        slot.state.store(SLOT_EMPTY, Ordering::Release);
+
+        // SAFETY: `func` is stored from an `IpiCallback` by `queue`.
+        let func = unsafe { core::mem::transmute::<usize, IpiCallback>(func) };
+        func(arg);
+        if !pending.is_null() {
+            // SAFETY: The caller waits until the counter drops to zero.
+            unsafe { (*pending).fetch_sub(1, Ordering::Release) };
+        }
+        called = true;
+    }
+    called
+}
+
+/// Calls `func(arg)` on the target CPUs.
+///
+/// The function is run in the IPI handler of each target CPU, or directly for
+/// [`IpiTarget::Current`]. If `wait` is `true`, it returns after all target
+/// CPUs finished the call.
+///
+/// It must be called with IRQs enabled if `wait` is `true` or the mailboxes
+/// may be full, otherwise it may deadlock when two CPUs call each other.
+///
+/// # Panics
+///
+/// Panics if the platform does not support cross-CPU calls (see [`ipi_irq`]),
+/// or any target CPU ID is not less than [`CpuMask::MAX_CPUS`].
+pub fn call(target: IpiTarget, func: IpiCallback, arg: usize, wait: bool) {
+    if let IpiTarget::Current { .. } = target {
+        func(arg);
+        return;
+    }
+    let targets = target.cpu_mask();
+    if targets.is_empty() {
+        return;
+    }
+    let ipi_irq = ipi_irq().expect("cross-CPU calls are not supported");
+
+    let pending = AtomicUsize::new(targets.len());
+    let pending_ptr = if wait {
+        &pending as *const _
+    } else {
+        core::ptr::null()
+    };
+    for cpu_id in targets.iter() {
+        queue(cpu_id, func, arg, pending_ptr);
+    }
+    crate::irq::send_ipi(ipi_irq, target);
+
+    if wait {
+        while pending.load(Ordering::Acquire) != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{MAX_PENDING_CALLS, handle_calls, queue};
+
+    #[test]
+    fn mailbox() {
+        static SUM: AtomicUsize = AtomicUsize::new(0);
+        fn add(arg: usize) {
+            SUM.fetch_add(arg, Ordering::Relaxed);
+        }
+
+        let pending = AtomicUsize::new(MAX_PENDING_CALLS);
+        for i in 0..MAX_PENDING_CALLS {
+            queue(3, add, i + 1, &pending);
+        }
+        assert!(!handle_calls(2) && !handle_calls(usize::MAX));
+        assert!(handle_calls(3));
+        assert!(!handle_calls(3));
+        assert_eq!(pending.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            SUM.load(Ordering::Relaxed),
+            MAX_PENDING_CALLS * (MAX_PENDING_CALLS + 1) / 2
+        );
+
+        // The slots are reused after the calls are run.
+        queue(3, add, 100, core::ptr::null());
+        assert!(handle_calls(3));
+        assert_eq!(
+            SUM.load(Ordering::Relaxed),
+            MAX_PENDING_CALLS * (MAX_PENDING_CALLS + 1) / 2 + 100
+        );
+    }
+}
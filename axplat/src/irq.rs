@@ -1,24 +1,232 @@
 //! Interrupt request (IRQ) handling.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "irq-stats")]
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::mem::PhysAddr;
 
 /// The type of an event handler.
 ///
-/// Currently no arguments and return values are supported.
+/// The argument is the index (IRQ number) of the event.
 pub type IrqHandler = fn(usize);
 
+/// The type of an event handler with a context pointer.
+///
+/// The second argument is the context pointer given at registration, which
+/// usually points to the state of a device instance. It allows one function to
+/// serve multiple instances of the same device.
+pub type IrqContextHandler = fn(usize, *mut ());
+
+/// The type of an event handler in the form of a boxed closure.
+#[cfg(feature = "alloc")]
+pub type BoxedIrqHandler = Box<dyn Fn(usize) + Send + Sync>;
+
+/// The type of an event handler on a shared IRQ line.
+///
+/// The second argument is the context pointer given at registration. It
+/// returns `true` if the event comes from its device and is handled, `false`
+/// otherwise.
+pub type SharedIrqHandler = fn(usize, *mut ()) -> bool;
+
+/// Lower bits of the slot state: the form of the registered handler.
+const KIND_MASK: usize = 0b111;
+const KIND_EMPTY: usize = 0;
+/// The slot is being registered or unregistered.
+const KIND_BUSY: usize = 1;
+const KIND_PLAIN: usize = 2;
+const KIND_CONTEXT: usize = 3;
+#[cfg(feature = "alloc")]
+const KIND_BOXED: usize = 4;
+/// A handler in the shared pool.
+const KIND_SHARED: usize = 5;
+/// The index is a shared line, whose handlers are in the shared pool.
+const KIND_SHARED_LINE: usize = 6;
+/// Upper bits of the slot state: the number of running handlers.
+const RUNNING_ONE: usize = KIND_MASK + 1;
+
+/// A handler slot in [`HandlerTable`].
+struct Slot {
+    state: AtomicUsize,
+    handler: AtomicUsize,
+    context: AtomicUsize,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(KIND_EMPTY),
+            handler: AtomicUsize::new(0),
+            context: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the kind of the handler, with the fields visible.
+    fn kind(&self) -> usize {
+        self.state.load(Ordering::Acquire) & KIND_MASK
+    }
+
+    /// Makes the empty slot busy, so that its fields can be written.
+    fn try_claim(&self) -> bool {
+        self.state
+            .compare_exchange(KIND_EMPTY, KIND_BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Makes the claimed slot visible to [`Slot::enter`].
+    fn publish(&self, kind: usize, handler: usize, context: usize) {
+        self.handler.store(handler, Ordering::Relaxed);
+        self.context.store(context, Ordering::Relaxed);
+        self.state.store(kind, Ordering::SeqCst);
+    }
+
+    fn register(&self, kind: usize, handler: usize, context: usize) -> bool {
+        if !self.try_claim() {
+            return false;
+        }
+        self.publish(kind, handler, context);
+        true
+    }
+
+    /// Makes the slot busy if its kind is accepted by `filter`, and waits for
+    /// all running handlers to return.
+    ///
+    /// Returns the kind of the handler.
+    fn begin_unregister(&self, filter: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut state = self.state.load(Ordering::Acquire);
+        let kind = loop {
+            let kind = state & KIND_MASK;
+            if kind == KIND_EMPTY || kind == KIND_BUSY || !filter(kind) {
+                return None;
+            }
+            // New calls to `enter` fail once the slot becomes busy.
+            match self.state.compare_exchange_weak(
+                state,
+                (state & !KIND_MASK) | KIND_BUSY,
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break kind,
+                Err(s) => state = s,
+            }
+        };
+        // Wait for the running handlers to return.
+        while self.state.load(Ordering::Acquire) != KIND_BUSY {
+            core::hint::spin_loop();
+        }
+        Some(kind)
+    }
+
+    /// Empties the slot if its kind is accepted by `filter`, and returns the
+    /// kind, handler and context of the removed handler.
+    fn unregister(&self, filter: impl Fn(usize) -> bool) -> Option<(usize, usize, usize)> {
+        let kind = self.begin_unregister(filter)?;
+        let handler = self.handler.load(Ordering::Relaxed);
+        let context = self.context.load(Ordering::Relaxed);
+        self.state.store(KIND_EMPTY, Ordering::Release);
+        Some((kind, handler, context))
+    }
+
+    /// Marks the handler as running, and returns its kind.
+    ///
+    /// Returns `None` if no handler is registered. [`Slot::exit`] must be
+    /// called after the handler returns.
+    fn enter(&self) -> Option<usize> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let kind = state & KIND_MASK;
+            if kind == KIND_EMPTY || kind == KIND_BUSY {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + RUNNING_ONE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(kind),
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn exit(&self) {
+        self.state.fetch_sub(RUNNING_ONE, Ordering::Release);
+    }
+
+    /// Calls the handler of the given kind, and returns whether the event is
+    /// handled.
+    fn call(&self, kind: usize, idx: usize) -> bool {
+        let handler = self.handler.load(Ordering::Relaxed);
+        let context = self.context.load(Ordering::Relaxed);
+        match kind {
+            KIND_PLAIN => {
+                let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
+                handler(idx);
+            }
+            KIND_CONTEXT => {
+                let handler: IrqContextHandler = unsafe { core::mem::transmute(handler) };
+                handler(idx, context as *mut ());
+            }
+            #[cfg(feature = "alloc")]
+            KIND_BOXED => {
+                let handler = unsafe { &*(context as *const BoxedIrqHandler) };
+                handler(idx);
+            }
+            KIND_SHARED => {
+                let handler: SharedIrqHandler = unsafe { core::mem::transmute(handler) };
+                return handler(idx, context as *mut ());
+            }
+            _ => unreachable!(),
+        }
+        true
+    }
+}
+
+/// A handler slot in the shared pool of [`HandlerTable`].
+struct SharedSlot {
+    idx: AtomicUsize,
+    slot: Slot,
+}
+
+impl SharedSlot {
+    const fn new() -> Self {
+        Self {
+            idx: AtomicUsize::new(0),
+            slot: Slot::new(),
+        }
+    }
+
+    /// Returns `true` if it holds a handler of the given index.
+    fn is_for(&self, idx: usize) -> bool {
+        self.slot.state.load(Ordering::SeqCst) & KIND_MASK == KIND_SHARED
+            && self.idx.load(Ordering::Relaxed) == idx
+    }
+}
+
 /// A lock-free table of event handlers.
 ///
-/// It internally uses an array of `AtomicUsize` to store the handlers.
-pub struct HandlerTable<const N: usize> {
-    handlers: [AtomicUsize; N],
+/// Each entry is a slot with an atomic state, which records the form of the
+/// handler and the number of running handlers. Handlers can be registered in
+/// three forms: plain functions ([`IrqHandler`]), functions with a context
+/// pointer ([`IrqContextHandler`]), and boxed closures ([`BoxedIrqHandler`],
+/// requires the `alloc` feature).
+///
+/// An index can also be a shared line with multiple [`SharedIrqHandler`]s,
+/// which are stored in a pool of `S` slots shared by all indices.
+pub struct HandlerTable<const N: usize, const S: usize = 16> {
+    slots: [Slot; N],
+    shared: [SharedSlot; S],
 }
 
-impl<const N: usize> HandlerTable<N> {
+impl<const N: usize, const S: usize> HandlerTable<N, S> {
     /// Creates a new handler table with all entries empty.
     pub const fn new() -> Self {
         Self {
-            handlers: [const { AtomicUsize::new(0) }; N],
+            slots: [const { Slot::new() }; N],
+            shared: [const { SharedSlot::new() }; S],
         }
     }
 
@@ -27,55 +235,488 @@ impl<const N: usize> HandlerTable<N> {
     /// Returns `true` if the registration succeeds, `false` if the index is out
     /// of bounds or the handler is already registered.
     pub fn register_handler(&self, idx: usize, handler: IrqHandler) -> bool {
+        idx < N && self.slots[idx].register(KIND_PLAIN, handler as usize, 0)
+    }
+
+    /// Registers a handler with a context pointer for the given index.
+    ///
+    /// `context` is passed to the handler each time it is invoked, so it must
+    /// stay valid until the handler is unregistered.
+    ///
+    /// Returns `true` if the registration succeeds, `false` if the index is out
+    /// of bounds or the handler is already registered.
+    pub fn register_handler_with_context(
+        &self,
+        idx: usize,
+        handler: IrqContextHandler,
+        context: *mut (),
+    ) -> bool {
+        idx < N && self.slots[idx].register(KIND_CONTEXT, handler as usize, context as usize)
+    }
+
+    /// Registers a boxed closure as the handler for the given index.
+    ///
+    /// Returns `true` if the registration succeeds, `false` if the index is out
+    /// of bounds or the handler is already registered. The closure is dropped
+    /// if the registration fails.
+    #[cfg(feature = "alloc")]
+    pub fn register_boxed_handler(&self, idx: usize, handler: BoxedIrqHandler) -> bool {
         if idx >= N {
             return false;
         }
-        self.handlers[idx]
-            .compare_exchange(0, handler as usize, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
+        // Box it again to get a thin pointer.
+        let context = Box::into_raw(Box::new(handler));
+        if self.slots[idx].register(KIND_BOXED, 0, context as usize) {
+            true
+        } else {
+            drop(unsafe { Box::from_raw(context) });
+            false
+        }
+    }
+
+    /// Adds a handler to the shared line with the given index.
+    ///
+    /// `context` is passed to the handler each time it is invoked, and
+    /// identifies the handler in [`unregister_shared_handler`]. It must stay
+    /// valid until the handler is unregistered.
+    ///
+    /// Returns `true` if the registration succeeds, `false` if the index is out
+    /// of bounds, a non-shared handler is registered for the index, or the
+    /// shared pool is full.
+    ///
+    /// [`unregister_shared_handler`]: HandlerTable::unregister_shared_handler
+    pub fn register_shared_handler(
+        &self,
+        idx: usize,
+        handler: SharedIrqHandler,
+        context: *mut (),
+    ) -> bool {
+        if idx >= N {
+            return false;
+        }
+        let Some(entry) = self.shared.iter().find(|s| s.slot.try_claim()) else {
+            return false;
+        };
+        entry.idx.store(idx, Ordering::Relaxed);
+        entry
+            .slot
+            .publish(KIND_SHARED, handler as usize, context as usize);
+
+        // Mark the index as a shared line, unless it has a non-shared handler.
+        let line = &self.slots[idx].state;
+        loop {
+            match line.compare_exchange(
+                KIND_EMPTY,
+                KIND_SHARED_LINE,
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(state) => match state & KIND_MASK {
+                    KIND_SHARED_LINE => return true,
+                    KIND_BUSY => core::hint::spin_loop(),
+                    _ => {
+                        entry.slot.unregister(|_| true);
+                        return false;
+                    }
+                },
+            }
+        }
     }
 
     /// Unregisters the handler for the given index.
     ///
-    /// Returns the existing handler if it is registered, `None` otherwise.
+    /// If the handler is running on other CPUs, it waits for the handler to
+    /// return, so it must not be called in the handler itself. A boxed
+    /// closure is dropped after that. Handlers on a shared line are not
+    /// affected, see [`unregister_shared_handler`] instead.
+    ///
+    /// Returns the existing handler if it is a plain [`IrqHandler`], `None`
+    /// otherwise.
+    ///
+    /// [`unregister_shared_handler`]: HandlerTable::unregister_shared_handler
     pub fn unregister_handler(&self, idx: usize) -> Option<IrqHandler> {
         if idx >= N {
             return None;
         }
-        let handler = self.handlers[idx].swap(0, Ordering::Acquire);
-        if handler != 0 {
-            Some(unsafe { core::mem::transmute::<usize, IrqHandler>(handler) })
-        } else {
-            None
+        match self.slots[idx].unregister(|kind| kind != KIND_SHARED_LINE)? {
+            (KIND_PLAIN, handler, _) => {
+                Some(unsafe { core::mem::transmute::<usize, IrqHandler>(handler) })
+            }
+            #[cfg(feature = "alloc")]
+            (KIND_BOXED, _, context) => {
+                drop(unsafe { Box::from_raw(context as *mut BoxedIrqHandler) });
+                None
+            }
+            _ => None,
         }
     }
 
+    /// Removes the handler registered with the given context pointer from the
+    /// shared line with the given index.
+    ///
+    /// Like [`unregister_handler`], it waits for the handler to return if it
+    /// is running on other CPUs. The index is no longer a shared line after
+    /// its last handler is removed.
+    ///
+    /// Returns `true` if the handler is found and removed.
+    ///
+    /// [`unregister_handler`]: HandlerTable::unregister_handler
+    pub fn unregister_shared_handler(&self, idx: usize, context: *mut ()) -> bool {
+        if idx >= N {
+            return false;
+        }
+        let removed = self.shared.iter().any(|s| {
+            s.slot
+                .unregister(|kind| {
+                    kind == KIND_SHARED
+                        && s.idx.load(Ordering::Relaxed) == idx
+                        && s.slot.context.load(Ordering::Relaxed) == context as usize
+                })
+                .is_some()
+        });
+        if !removed {
+            return false;
+        }
+
+        let line = &self.slots[idx];
+        if line
+            .begin_unregister(|kind| kind == KIND_SHARED_LINE)
+            .is_some()
+        {
+            // Keep it shared if a handler is added concurrently.
+            let kind = if self.shared.iter().any(|s| s.is_for(idx)) {
+                KIND_SHARED_LINE
+            } else {
+                KIND_EMPTY
+            };
+            line.state.store(kind, Ordering::SeqCst);
+        }
+        true
+    }
+
+    /// Returns `true` if any handler (including shared ones) is registered for
+    /// the given index.
+    pub fn has_handler(&self, idx: usize) -> bool {
+        idx < N && !matches!(self.slots[idx].kind(), KIND_EMPTY | KIND_BUSY)
+    }
+
     /// Handles the event with the given index.
     ///
+    /// For a shared line, all its handlers are called in turn.
+    ///
     /// Returns `true` if the event is handled, `false` if no handler is
-    /// registered for the given index.
+    /// registered for the given index, or no handler on the shared line
+    /// handles it.
     pub fn handle(&self, idx: usize) -> bool {
         if idx >= N {
             return false;
         }
-        let handler = self.handlers[idx].load(Ordering::Acquire);
-        if handler != 0 {
-            let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
-            handler(idx);
-            true
+        let line = &self.slots[idx];
+        let Some(kind) = line.enter() else {
+            return false;
+        };
+        let handled = if kind == KIND_SHARED_LINE {
+            let mut handled = false;
+            for s in &self.shared {
+                if let Some(kind) = s.slot.enter() {
+                    if s.idx.load(Ordering::Relaxed) == idx {
+                        handled |= s.slot.call(kind, idx);
+                    }
+                    s.slot.exit();
+                }
+            }
+            handled
         } else {
-            false
+            line.call(kind, idx)
+        };
+        line.exit();
+        handled
+    }
+}
+
+impl<const N: usize, const S: usize> Default for HandlerTable<N, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Interrupt counters of an IRQ on a CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IrqCounters {
+    /// Number of interrupts handled by the registered handlers.
+    pub handled: u64,
+    /// Number of interrupts that no handler handled.
+    pub unhandled: u64,
+}
+
+/// Lock-free interrupt statistics of `N` IRQs on `C` CPUs.
+///
+/// It is usually updated by the platform along with [`HandlerTable::handle`].
+/// Records of out-of-bounds IRQs or CPUs are ignored.
+///
+/// Without the `irq-stats` feature, it is zero-sized and records nothing, so
+/// that platforms and drivers can use it unconditionally.
+pub struct IrqStats<const N: usize, const C: usize> {
+    #[cfg(feature = "irq-stats")]
+    handled: [[AtomicU64; C]; N],
+    #[cfg(feature = "irq-stats")]
+    unhandled: [[AtomicU64; C]; N],
+    #[cfg(feature = "irq-stats")]
+    spurious: [AtomicU64; C],
+}
+
+impl<const N: usize, const C: usize> IrqStats<N, C> {
+    /// Creates a new statistics table with all counters zero.
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature = "irq-stats")]
+            handled: [const { [const { AtomicU64::new(0) }; C] }; N],
+            #[cfg(feature = "irq-stats")]
+            unhandled: [const { [const { AtomicU64::new(0) }; C] }; N],
+            #[cfg(feature = "irq-stats")]
+            spurious: [const { AtomicU64::new(0) }; C],
+        }
+    }
+
+    /// Records an interrupt of the given IRQ on the given CPU, with whether it
+    /// is handled.
+    pub fn record(&self, _irq: usize, _cpu_id: usize, _handled: bool) {
+        #[cfg(feature = "irq-stats")]
+        if _irq < N && _cpu_id < C {
+            let counters = if _handled {
+                &self.handled
+            } else {
+                &self.unhandled
+            };
+            counters[_irq][_cpu_id].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a spurious interrupt on the given CPU, i.e., an interrupt
+    /// without a valid IRQ number.
+    pub fn record_spurious(&self, _cpu_id: usize) {
+        #[cfg(feature = "irq-stats")]
+        if _cpu_id < C {
+            self.spurious[_cpu_id].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the counters of the given IRQ on the given CPU.
+    pub fn counters(&self, _irq: usize, _cpu_id: usize) -> IrqCounters {
+        #[cfg(feature = "irq-stats")]
+        if _irq < N && _cpu_id < C {
+            return IrqCounters {
+                handled: self.handled[_irq][_cpu_id].load(Ordering::Relaxed),
+                unhandled: self.unhandled[_irq][_cpu_id].load(Ordering::Relaxed),
+            };
+        }
+        IrqCounters::default()
+    }
+
+    /// Returns the number of spurious interrupts on the given CPU.
+    pub fn spurious(&self, _cpu_id: usize) -> u64 {
+        #[cfg(feature = "irq-stats")]
+        if let Some(c) = self.spurious.get(_cpu_id) {
+            return c.load(Ordering::Relaxed);
+        }
+        0
+    }
+
+    /// Calls `f` with (`irq`, `cpu_id`, counters) for each IRQ on each CPU with
+    /// non-zero counters.
+    pub fn for_each(&self, f: &mut dyn FnMut(usize, usize, IrqCounters)) {
+        for irq in 0..N {
+            for cpu_id in 0..C {
+                let counters = self.counters(irq, cpu_id);
+                if counters != IrqCounters::default() {
+                    f(irq, cpu_id, counters);
+                }
+            }
         }
     }
 }
 
-impl<const N: usize> Default for HandlerTable<N> {
+impl<const N: usize, const C: usize> Default for IrqStats<N, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Trigger mode of an IRQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// Edge-triggered.
+    Edge,
+    /// Level-triggered.
+    Level,
+}
+
+/// Polarity of an IRQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// Active high for level-triggered IRQs, or rising edge for
+    /// edge-triggered IRQs.
+    High,
+    /// Active low for level-triggered IRQs, or falling edge for
+    /// edge-triggered IRQs.
+    Low,
+}
+
+/// A set of CPUs, represented as a bitmap of CPU IDs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CpuMask(u64);
+
+impl CpuMask {
+    /// The maximum number of CPUs that can be represented.
+    pub const MAX_CPUS: usize = u64::BITS as usize;
+
+    /// Creates an empty mask.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a mask from the raw bitmap.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Creates a mask with only the given CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_id` is not less than [`Self::MAX_CPUS`]. Platforms
+    /// should check that their maximum number of CPUs does not exceed it.
+    pub const fn one(cpu_id: usize) -> Self {
+        assert!(cpu_id < Self::MAX_CPUS);
+        Self(1 << cpu_id)
+    }
+
+    /// Creates a mask with CPUs `0..cpu_num`.
+    pub const fn full(cpu_num: usize) -> Self {
+        if cpu_num >= Self::MAX_CPUS {
+            Self(u64::MAX)
+        } else {
+            Self((1 << cpu_num) - 1)
+        }
+    }
+
+    /// Returns the raw bitmap.
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if the mask contains no CPUs.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the number of CPUs in the mask.
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Returns `true` if the mask contains the given CPU.
+    pub const fn contains(self, cpu_id: usize) -> bool {
+        cpu_id < Self::MAX_CPUS && self.0 & (1 << cpu_id) != 0
+    }
+
+    /// Adds the given CPU to the mask.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_id` is not less than [`Self::MAX_CPUS`].
+    pub fn insert(&mut self, cpu_id: usize) {
+        self.0 |= Self::one(cpu_id).0;
+    }
+
+    /// Removes the given CPU from the mask.
+    pub fn remove(&mut self, cpu_id: usize) {
+        if cpu_id < Self::MAX_CPUS {
+            self.0 &= !(1 << cpu_id);
+        }
+    }
+
+    /// Returns the smallest CPU ID in the mask.
+    pub const fn first(self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize)
+        }
+    }
+
+    /// Returns an iterator over the CPU IDs in the mask, in ascending order.
+    pub fn iter(self) -> impl Iterator<Item = usize> {
+        let mut bits = self.0;
+        core::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let cpu_id = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(cpu_id)
+        })
+    }
+}
+
+/// A message-signalled interrupt (MSI) message.
+///
+/// A device raises the interrupt by writing `data` (as a 32-bit value) to the
+/// physical address `address`. It is programmed into the MSI capability or
+/// the MSI-X table entry of a PCI device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    /// The physical address to write.
+    pub address: PhysAddr,
+    /// The value to write.
+    pub data: u32,
+}
+
+/// A lock-free allocator of `N` MSI vectors, indexed by `0..N`.
+///
+/// It is usually used by the platform to manage the vectors (or interrupt
+/// identities) that are reserved for MSIs.
+pub struct MsiVectorAllocator<const N: usize> {
+    used: [AtomicBool; N],
+}
+
+impl<const N: usize> MsiVectorAllocator<N> {
+    /// Creates a new allocator with all vectors free.
+    pub const fn new() -> Self {
+        Self {
+            used: [const { AtomicBool::new(false) }; N],
+        }
+    }
+
+    /// Allocates a free vector among the first `limit` ones, and returns its
+    /// index.
+    pub fn alloc(&self, limit: usize) -> Option<usize> {
+        self.used[..limit.min(N)].iter().position(|used| {
+            used.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    /// Frees the vector at the given index.
+    ///
+    /// Returns `false` if the vector is not allocated.
+    pub fn free(&self, idx: usize) -> bool {
+        idx < N && self.used[idx].swap(false, Ordering::Release)
+    }
+}
+
+impl<const N: usize> Default for MsiVectorAllocator<N> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 /// Target specification for inter-processor interrupts (IPIs).
+///
+/// More kinds of targets may be added, so platforms should fall back to
+/// [`IpiTarget::cpu_mask`] for the ones they do not handle specially.
+#[non_exhaustive]
 pub enum IpiTarget {
     /// Send to the current CPU.
     Current {
@@ -94,26 +735,118 @@ pub enum IpiTarget {
         /// The total number of CPUs.
         cpu_num: usize,
     },
+    /// Send to all CPUs in the mask, which may include the current CPU.
+    Mask {
+        /// The CPU IDs of the target CPUs.
+        cpu_mask: CpuMask,
+    },
+}
+
+impl IpiTarget {
+    /// Returns the mask of the target CPUs.
+    pub fn cpu_mask(&self) -> CpuMask {
+        match *self {
+            Self::Current { cpu_id } | Self::Other { cpu_id } => CpuMask::one(cpu_id),
+            Self::AllExceptCurrent { cpu_id, cpu_num } => {
+                let mut mask = CpuMask::full(cpu_num);
+                mask.remove(cpu_id);
+                mask
+            }
+            Self::Mask { cpu_mask } => cpu_mask,
+        }
+    }
 }
 
 /// IRQ management interface.
+///
+/// The IRQ numbers are defined by the platform. For example, they are the
+/// interrupt IDs of the GIC on AArch64, and the interrupt vectors on x86, where
+/// the IO APIC pin `n` is the vector `0x20 + n`.
 #[def_plat_interface]
 pub trait IrqIf {
     /// Enables or disables the given IRQ.
     fn set_enable(irq: usize, enabled: bool);
 
+    /// Sets the trigger mode and polarity of the given IRQ.
+    ///
+    /// It should be called before the IRQ is enabled. It returns `false` if
+    /// the IRQ or the configuration is not supported by the interrupt
+    /// controller.
+    fn set_trigger(irq: usize, mode: TriggerMode, polarity: Polarity) -> bool;
+
+    /// Sets the priority of the given IRQ.
+    ///
+    /// A larger value means a higher priority, which is scaled to the priority
+    /// levels of the interrupt controller. It returns `false` if the interrupt
+    /// controller does not support per-IRQ priorities.
+    fn set_priority(irq: usize, priority: u8) -> bool;
+
+    /// Sets the CPUs that the given IRQ can be delivered to.
+    ///
+    /// Where the CPU IDs in `cpu_mask` are logical CPU IDs. If the interrupt
+    /// controller can only route an IRQ to a single CPU, the first CPU in the
+    /// mask is used. It returns `false` if the IRQ cannot be routed to the
+    /// given CPUs.
+    fn set_affinity(irq: usize, cpu_mask: CpuMask) -> bool;
+
     /// Registers an IRQ handler for the given IRQ.
     ///
     /// It also enables the IRQ if the registration succeeds. It returns `false`
     /// if the registration failed.
     fn register(irq: usize, handler: IrqHandler) -> bool;
 
+    /// Registers an IRQ handler with a context pointer for the given IRQ.
+    ///
+    /// `context` is passed to the handler each time it is invoked, so it must
+    /// stay valid until the handler is unregistered. It also enables the IRQ if
+    /// the registration succeeds. It returns `false` if the registration
+    /// failed.
+    fn register_with_context(irq: usize, handler: IrqContextHandler, context: *mut ()) -> bool;
+
+    /// Registers a boxed closure as the IRQ handler for the given IRQ.
+    ///
+    /// It also enables the IRQ if the registration succeeds. It returns `false`
+    /// if the registration failed.
+    #[cfg(feature = "alloc")]
+    fn register_boxed(irq: usize, handler: BoxedIrqHandler) -> bool;
+
     /// Unregisters the IRQ handler for the given IRQ.
     ///
     /// It also disables the IRQ if the unregistration succeeds. It returns the
-    /// existing handler if it is registered, `None` otherwise.
+    /// existing handler if it is a plain [`IrqHandler`], `None` otherwise.
     fn unregister(irq: usize) -> Option<IrqHandler>;
 
+    /// Adds a handler to the shared IRQ line.
+    ///
+    /// All handlers on a shared line are called in turn when the IRQ occurs,
+    /// and each returns whether its device raised the interrupt. `context`
+    /// identifies the handler in [`unregister_shared`]. It also enables the IRQ
+    /// if the registration succeeds. It returns `false` if the registration
+    /// failed, e.g., a non-shared handler is registered for the IRQ.
+    fn register_shared(irq: usize, handler: SharedIrqHandler, context: *mut ()) -> bool;
+
+    /// Removes the handler registered with the given context pointer from the
+    /// shared IRQ line.
+    ///
+    /// It also disables the IRQ if no handlers remain. It returns `false` if
+    /// the handler is not found.
+    fn unregister_shared(irq: usize, context: *mut ()) -> bool;
+
+    /// Allocates an MSI vector that is delivered to the given CPU.
+    ///
+    /// Where `cpu_id` is the logical CPU ID. It returns the IRQ number of the
+    /// vector, which is used to register handlers as other IRQs, and the
+    /// message to program into the device. The IRQ is enabled after handlers
+    /// are registered. It returns `None` if MSIs are not supported by the
+    /// platform or no vectors are available.
+    fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)>;
+
+    /// Frees an MSI vector allocated by [`alloc_msi`].
+    ///
+    /// The handlers of the IRQ should be unregistered before. It returns
+    /// `false` if the IRQ is not an allocated MSI vector.
+    fn free_msi(irq: usize) -> bool;
+
     /// Handles the IRQ.
     ///
     /// It is called by the common interrupt handler. It should look up in the
@@ -123,4 +856,198 @@ pub trait IrqIf {
 
     /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
     fn send_ipi(irq_num: usize, target: IpiTarget);
+
+    /// Takes a snapshot of the interrupt statistics.
+    ///
+    /// `f` is called with (`irq`, `cpu_id`, counters) for each IRQ on each CPU
+    /// with non-zero counters, where `cpu_id` is the logical CPU ID.
+    #[cfg(feature = "irq-stats")]
+    fn irq_stats(f: &mut dyn FnMut(usize, usize, IrqCounters));
+
+    /// Returns the number of spurious interrupts on the given CPU.
+    #[cfg(feature = "irq-stats")]
+    fn spurious_irq_count(cpu_id: usize) -> u64;
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{CpuMask, HandlerTable, IpiTarget, MsiVectorAllocator};
+
+    #[test]
+    fn handler_table() {
+        static PLAIN_COUNT: AtomicUsize = AtomicUsize::new(0);
+        fn plain(idx: usize) {
+            PLAIN_COUNT.fetch_add(idx, Ordering::Relaxed);
+        }
+        fn with_context(idx: usize, context: *mut ()) {
+            let count = unsafe { &*(context as *const AtomicUsize) };
+            count.fetch_add(idx, Ordering::Relaxed);
+        }
+
+        let table = HandlerTable::<4>::new();
+        assert!(!table.handle(0));
+        assert!(table.register_handler(1, plain));
+        assert!(!table.register_handler(1, plain));
+        assert!(!table.register_handler(4, plain));
+
+        let counts = [AtomicUsize::new(0), AtomicUsize::new(0)];
+        for (idx, count) in [2, 3].into_iter().zip(&counts) {
+            let context = count as *const _ as *mut ();
+            assert!(table.register_handler_with_context(idx, with_context, context));
+        }
+        assert!(table.handle(1) && table.handle(2) && table.handle(3) && table.handle(3));
+        assert_eq!(PLAIN_COUNT.load(Ordering::Relaxed), 1);
+        assert_eq!(counts[0].load(Ordering::Relaxed), 2);
+        assert_eq!(counts[1].load(Ordering::Relaxed), 6);
+
+        assert!(table.unregister_handler(1).is_some());
+        assert!(table.unregister_handler(2).is_none());
+        assert!(!table.handle(1) && !table.handle(2));
+        assert!(table.register_handler(2, plain));
+    }
+
+    #[test]
+    fn shared_handlers() {
+        fn shared(idx: usize, context: *mut ()) -> bool {
+            let count = unsafe { &*(context as *const AtomicUsize) };
+            // Only handle the events when the count is even.
+            count.fetch_add(idx, Ordering::Relaxed) % 2 == 0
+        }
+        fn plain(_idx: usize) {}
+
+        let table = HandlerTable::<2, 2>::new();
+        let counts = [
+            AtomicUsize::new(0),
+            AtomicUsize::new(1),
+            AtomicUsize::new(0),
+        ];
+        let context = |i: usize| &counts[i] as *const _ as *mut ();
+        assert!(table.register_shared_handler(1, shared, context(0)));
+        assert!(table.register_shared_handler(1, shared, context(1)));
+        // The shared pool is full.
+        assert!(!table.register_shared_handler(1, shared, context(2)));
+        assert!(!table.register_handler(1, plain));
+        assert!(table.unregister_handler(1).is_none());
+        assert!(table.has_handler(1));
+
+        assert!(table.handle(1));
+        assert!(table.handle(1));
+        assert_eq!(counts[0].load(Ordering::Relaxed), 2);
+        assert_eq!(counts[1].load(Ordering::Relaxed), 3);
+
+        assert!(table.register_handler(0, plain));
+        assert!(!table.register_shared_handler(0, shared, context(2)));
+
+        assert!(!table.unregister_shared_handler(1, context(2)));
+        assert!(table.unregister_shared_handler(1, context(0)));
+        assert!(!table.unregister_shared_handler(1, context(0)));
+        // The remaining handler does not handle odd counts.
+        assert!(!table.handle(1));
+        assert_eq!(counts[1].load(Ordering::Relaxed), 4);
+        assert!(table.unregister_shared_handler(1, context(1)));
+        assert!(!table.has_handler(1));
+        assert!(!table.handle(1));
+        assert!(table.register_handler(1, plain));
+    }
+
+    #[cfg(feature = "irq-stats")]
+    #[test]
+    fn irq_stats() {
+        use super::{IrqCounters, IrqStats};
+
+        let stats = IrqStats::<4, 2>::new();
+        stats.record(1, 0, true);
+        stats.record(1, 0, false);
+        stats.record(3, 1, true);
+        stats.record(4, 0, true);
+        stats.record(1, 2, true);
+        stats.record_spurious(1);
+        stats.record_spurious(2);
+
+        let counters = |handled, unhandled| IrqCounters { handled, unhandled };
+        assert_eq!(stats.counters(1, 0), counters(1, 1));
+        assert_eq!(stats.counters(4, 0), counters(0, 0));
+        assert_eq!((stats.spurious(0), stats.spurious(1)), (0, 1));
+
+        let mut records = [(0, 0, IrqCounters::default()); 2];
+        let mut n = 0;
+        stats.for_each(&mut |irq, cpu_id, c| {
+            records[n] = (irq, cpu_id, c);
+            n += 1;
+        });
+        assert_eq!(n, 2);
+        assert_eq!(records, [(1, 0, counters(1, 1)), (3, 1, counters(1, 0))]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn boxed_handler() {
+        use alloc::sync::Arc;
+
+        let table = HandlerTable::<1>::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let cloned = count.clone();
+        let handler = move |_| {
+            cloned.fetch_add(1, Ordering::Relaxed);
+        };
+        assert!(table.register_boxed_handler(0, alloc::boxed::Box::new(handler)));
+        assert!(table.handle(0) && table.handle(0));
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert_eq!(Arc::strong_count(&count), 2);
+        assert!(table.unregister_handler(0).is_none());
+        assert_eq!(Arc::strong_count(&count), 1);
+    }
+
+    #[test]
+    fn msi_vector_allocator() {
+        let vectors = MsiVectorAllocator::<3>::new();
+        assert_eq!(vectors.alloc(2), Some(0));
+        assert_eq!(vectors.alloc(2), Some(1));
+        assert_eq!(vectors.alloc(2), None);
+        assert_eq!(vectors.alloc(8), Some(2));
+        assert_eq!(vectors.alloc(8), None);
+
+        assert!(vectors.free(1));
+        assert!(!vectors.free(1) && !vectors.free(3));
+        assert_eq!(vectors.alloc(3), Some(1));
+    }
+
+    #[test]
+    fn ipi_target_cpu_mask() {
+        let all_except_current = IpiTarget::AllExceptCurrent {
+            cpu_id: 1,
+            cpu_num: 4,
+        };
+        assert!(all_except_current.cpu_mask().iter().eq([0, 2, 3]));
+        assert_eq!(IpiTarget::Current { cpu_id: 2 }.cpu_mask(), CpuMask::one(2));
+        assert_eq!(IpiTarget::Other { cpu_id: 5 }.cpu_mask(), CpuMask::one(5));
+        let cpu_mask = CpuMask::from_bits(0b1010);
+        assert_eq!(IpiTarget::Mask { cpu_mask }.cpu_mask(), cpu_mask);
+    }
+
+    #[test]
+    fn cpu_mask() {
+        let mut mask = CpuMask::new();
+        assert!(mask.is_empty());
+        assert_eq!(mask.first(), None);
+
+        mask.insert(3);
+        mask.insert(1);
+        mask.insert(63);
+        assert_eq!(mask.len(), 3);
+        assert!(mask.contains(1) && mask.contains(63));
+        assert!(!mask.contains(2) && !mask.contains(64));
+        assert_eq!(mask.first(), Some(1));
+        assert!(mask.iter().eq([1, 3, 63]));
+
+        mask.remove(1);
+        mask.remove(100);
+        assert_eq!(mask.bits(), (1 << 3) | (1 << 63));
+
+        assert_eq!(CpuMask::full(4).bits(), 0b1111);
+        assert_eq!(CpuMask::full(64).bits(), u64::MAX);
+        assert_eq!(CpuMask::one(2), CpuMask::from_bits(0b100));
+    }
 }
@@ -4,13 +4,24 @@
 
 #[macro_use]
 extern crate axplat_macros;
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
+pub mod boot;
 pub mod console;
+#[cfg(feature = "fdt")]
+pub mod fdt;
 pub mod init;
 #[cfg(feature = "irq")]
+pub mod ipi;
+#[cfg(feature = "irq")]
 pub mod irq;
+#[cfg(feature = "log")]
+pub mod logger;
 pub mod mem;
 pub mod power;
+#[cfg(feature = "pstore")]
+pub mod pstore;
 pub mod time;
 
 pub use axplat_macros::main;
@@ -58,8 +69,12 @@ macro_rules! assert_str_eq {
 
 /// Call the function decorated by [`axplat::main`][main] for the primary core.
 ///
+/// The boot information is parsed from `arg` by [`BootIf`](boot::BootIf)
+/// before that.
+///
 /// This function should only be called by the platform implementer, not the kernel.
 pub fn call_main(cpu_id: usize, arg: usize) -> ! {
+    boot::init(arg);
     unsafe { __axplat_main(cpu_id, arg) }
 }
 
@@ -0,0 +1,77 @@
+//! A [`log`] backend that prints to the console.
+//!
+//! Each record is printed with the monotonic time, the CPU ID and the level,
+//! e.g., `[  1.234567 0 INFO  axplat_x86_pc::apic] Initialize Local APIC...`.
+//!
+//! It only uses [`ConsoleIf`](crate::console::ConsoleIf) and
+//! [`TimeIf`](crate::time::TimeIf), so it can be initialized before
+//! [`init_early`](crate::init::init_early). The time is zero until the
+//! platform timer is initialized, as the platforms convert ticks to zero
+//! nanoseconds before the timer frequency is known.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+/// The function to get the current CPU ID, or 0 if not set.
+static CPU_ID_FN: AtomicUsize = AtomicUsize::new(0);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let nanos = crate::time::monotonic_time_nanos();
+        let cpu_id = match CPU_ID_FN.load(Ordering::Acquire) {
+            0 => 0,
+            // SAFETY: it is stored from a `fn() -> usize` by `set_cpu_id_fn`.
+            f => unsafe { core::mem::transmute::<usize, fn() -> usize>(f)() },
+        };
+        crate::console::__simple_print(format_args!(
+            "[{:>4}.{:06} {} {:<5} {}] {}\n",
+            nanos / crate::time::NANOS_PER_SEC,
+            nanos % crate::time::NANOS_PER_SEC / crate::time::NANOS_PER_MICROS,
+            cpu_id,
+            record.level(),
+            record.target(),
+            record.args(),
+        ));
+    }
+
+    fn flush(&self) {
+        crate::console::flush();
+    }
+}
+
+/// Installs the logger, and sets the maximum log level.
+///
+/// Returns `false` if another logger is already installed.
+pub fn init(max_level: LevelFilter) -> bool {
+    if log::set_logger(&LOGGER).is_err() {
+        return false;
+    }
+    log::set_max_level(max_level);
+    true
+}
+
+/// Sets the maximum log level.
+pub fn set_max_level(max_level: LevelFilter) {
+    log::set_max_level(max_level);
+}
+
+/// Sets the function to get the current CPU ID, which is printed in each
+/// record.
+///
+/// It is 0 before this function is called, e.g., before per-CPU data is set
+/// up by the kernel.
+pub fn set_cpu_id_fn(cpu_id: fn() -> usize) {
+    CPU_ID_FN.store(cpu_id as usize, Ordering::Release);
+}
@@ -1,5 +1,98 @@
 //! Power management.
 
+#[cfg(feature = "smp")]
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Type of the system reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Cold reset, which power cycles the whole system.
+    Cold,
+    /// Warm reset, which resets the CPUs and some peripherals without power
+    /// cycling, and may preserve the memory contents.
+    ///
+    /// Platforms that do not support warm reset perform a cold reset instead.
+    Warm,
+}
+
+/// State of a CPU core.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    /// The CPU core is online.
+    On,
+    /// The CPU core is powered off (or parked), and can be booted again by
+    /// [`cpu_boot`].
+    Off,
+    /// The CPU core is being powered on or off.
+    Pending,
+    /// The state cannot be determined on the platform.
+    Unknown,
+}
+
+/// States of `N` CPU cores, for platforms that have no firmware interface to
+/// query them, so they are tracked by the kernel itself.
+///
+/// All the CPU cores are [`CpuState::Off`] initially.
+#[cfg(feature = "smp")]
+pub struct CpuStates<const N: usize> {
+    states: [AtomicU8; N],
+}
+
+#[cfg(feature = "smp")]
+impl<const N: usize> CpuStates<N> {
+    /// Creates the states with all the CPU cores powered off.
+    pub const fn new() -> Self {
+        Self {
+            states: [const { AtomicU8::new(CpuState::Off as u8) }; N],
+        }
+    }
+
+    /// Records the state of the given CPU core. It is ignored if `cpu_id` is
+    /// out of range.
+    pub fn set(&self, cpu_id: usize, state: CpuState) {
+        if let Some(s) = self.states.get(cpu_id) {
+            s.store(state as u8, Ordering::Release);
+        }
+    }
+
+    /// Returns the state of the given CPU core, or [`CpuState::Unknown`] if
+    /// `cpu_id` is out of range.
+    pub fn get(&self, cpu_id: usize) -> CpuState {
+        let Some(s) = self.states.get(cpu_id) else {
+            return CpuState::Unknown;
+        };
+        match s.load(Ordering::Acquire) {
+            s if s == CpuState::On as u8 => CpuState::On,
+            s if s == CpuState::Off as u8 => CpuState::Off,
+            s if s == CpuState::Pending as u8 => CpuState::Pending,
+            _ => CpuState::Unknown,
+        }
+    }
+}
+
+#[cfg(feature = "smp")]
+impl<const N: usize> Default for CpuStates<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Low-power state of a CPU core, used by [`cpu_suspend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspendState {
+    /// Retentive standby state, where the CPU context is preserved by the
+    /// hardware. It has the shortest wake-up latency.
+    Standby,
+    /// Non-retentive power-down state, which saves more power but has a
+    /// longer wake-up latency.
+    ///
+    /// The CPU context (including the trap and interrupt controller states)
+    /// is lost by the hardware, but it is saved and restored by the platform,
+    /// so it is transparent to the caller.
+    PowerDown,
+}
+
 /// Power management interface.
 #[def_plat_interface]
 pub trait PowerIf {
@@ -8,9 +101,43 @@ pub trait PowerIf {
     ///
     /// Where `cpu_id` is the logical CPU ID (0, 1, ..., N-1, N is the number of
     /// CPU cores on the platform).
+    ///
+    /// A CPU core that has been powered off by [`cpu_off`] can be booted again
+    /// in the same way.
     #[cfg(feature = "smp")]
     fn cpu_boot(cpu_id: usize, stack_top_paddr: usize);
 
+    /// Powers off the calling CPU core.
+    ///
+    /// The caller should have disabled interrupts and moved all the work away
+    /// from the current CPU core. It can be brought online again by calling
+    /// [`cpu_boot`] on another CPU core, which starts from the secondary entry
+    /// (i.e., [`axplat::secondary_main`](crate::secondary_main)) with the new
+    /// stack.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> !;
+
+    /// Returns the state of the given CPU core.
+    ///
+    /// Where `cpu_id` is the logical CPU ID (0, 1, ..., N-1, N is the number of
+    /// CPU cores on the platform).
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState;
+
+    /// Suspends the calling CPU core in the given low-power state, until an
+    /// interrupt or other wake-up event arrives.
+    ///
+    /// It should be called with interrupts disabled, and the pending interrupt
+    /// is handled after interrupts are enabled again.
+    ///
+    /// Returns `false` if the state is not supported by the platform, or the
+    /// CPU core fails to enter it. The CPU core may also return immediately if
+    /// there is a pending wake-up event.
+    fn cpu_suspend(state: SuspendState) -> bool;
+
     /// Shutdown the whole system.
     fn system_off() -> !;
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> !;
 }
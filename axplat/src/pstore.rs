@@ -0,0 +1,347 @@
+//! Persistent log across warm reboots.
+//!
+//! The platform reserves a RAM region that is not cleared on warm reboots,
+//! and calls [`init`] on boot. The console output is then also written to the
+//! region, and the output of the previous boot is available by
+//! [`previous_log`], e.g., to find out why it crashed. It is enabled by the
+//! `pstore` feature.
+//!
+//! The region is split into two halves, which are used by consecutive boots
+//! alternately. Each half is a ring of fixed-size records, and each record
+//! carries the boot and sequence numbers and a checksum of its own, so a
+//! record that is torn by a reset or by a concurrent write (e.g., by the panic
+//! output) only loses itself. Note that the latest output may be lost if it is
+//! still in the CPU caches when the system resets.
+
+use core::sync::atomic::{AtomicU32, Ordering, compiler_fence};
+
+use lazyinit::LazyInit;
+
+use crate::mem::VirtAddr;
+
+const MAGIC: u32 = 0x5253_4F50; // "POSR"
+
+/// Maximum number of bytes in a record.
+const RECORD_DATA_SIZE: usize = 48;
+
+/// A record of the log.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    /// Boot sequence number, which increases on each boot.
+    boot: u32,
+    /// Sequence number of the record in the boot.
+    seq: u32,
+    /// Number of bytes in `data`.
+    len: u32,
+    /// Checksum of the fields above and the bytes.
+    check: u32,
+    data: [u8; RECORD_DATA_SIZE],
+}
+
+impl Record {
+    const SIZE: usize = size_of::<Self>();
+
+    /// Returns the CRC-32 of the record, which is seeded by [`MAGIC`], so
+    /// zeroed memory is not valid.
+    fn checksum(&self) -> u32 {
+        let header = [MAGIC, self.boot, self.seq, self.len];
+        let len = (self.len as usize).min(RECORD_DATA_SIZE);
+        let bytes = header
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .chain(self.data[..len].iter().copied());
+        !bytes.fold(!0u32, |mut crc, c| {
+            crc ^= c as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            }
+            crc
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        self.len as usize <= RECORD_DATA_SIZE && self.check == self.checksum()
+    }
+}
+
+/// A log in a persistent memory region.
+pub struct PersistentLog {
+    /// The records written by this boot.
+    records: *mut Record,
+    count: usize,
+    boot: u32,
+    /// Sequence number of the next record.
+    next: AtomicU32,
+    /// The log of the previous boot.
+    previous: Option<(*const u8, usize)>,
+}
+
+unsafe impl Send for PersistentLog {}
+unsafe impl Sync for PersistentLog {}
+
+/// Returns `true` if boot sequence number `a` is later than `b`.
+fn is_later(a: u32, b: u32) -> bool {
+    a.wrapping_sub(b) as i32 > 0
+}
+
+/// Returns the latest boot sequence number of the valid records in a half, and
+/// the smallest sequence number of its records.
+///
+/// # Safety
+///
+/// `records` must point to `count` records.
+unsafe fn scan_half(records: *const Record, count: usize) -> Option<(u32, u32)> {
+    let mut latest: Option<(u32, u32)> = None;
+    for i in 0..count {
+        let r = unsafe { records.add(i).read_volatile() };
+        if !r.is_valid() {
+            continue;
+        }
+        latest = match latest {
+            Some((boot, _)) if is_later(r.boot, boot) => Some((r.boot, r.seq)),
+            Some((boot, seq)) if r.boot == boot => Some((boot, seq.min(r.seq))),
+            Some(l) => Some(l),
+            None => Some((r.boot, r.seq)),
+        };
+    }
+    latest
+}
+
+/// Moves the bytes of the records of boot `boot` to the start of the half, in
+/// the order of the records, and returns the number of bytes.
+///
+/// # Safety
+///
+/// `records` must point to `count` records, and `first_seq` must be the
+/// smallest sequence number of the records of the boot.
+unsafe fn compact_half(records: *mut Record, count: usize, boot: u32, first_seq: u32) -> usize {
+    // The record with sequence number `seq` is at `seq % count`.
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(records.cast::<u8>(), count * Record::SIZE) };
+    bytes.rotate_left(first_seq as usize % count * Record::SIZE);
+
+    // The bytes of record `i` are moved before `(i + 1) * RECORD_DATA_SIZE`,
+    // which does not overwrite the records after it.
+    let mut len = 0;
+    for i in 0..count {
+        let r = unsafe { bytes.as_ptr().cast::<Record>().add(i).read_volatile() };
+        if r.is_valid() && r.boot == boot && r.seq == first_seq.wrapping_add(i as u32) {
+            let n = r.len as usize;
+            bytes[len..len + n].copy_from_slice(&r.data[..n]);
+            len += n;
+        }
+    }
+    len
+}
+
+impl PersistentLog {
+    /// Opens the log in the given memory region.
+    ///
+    /// The log of the previous boot is kept, and the other half is used for
+    /// this boot.
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid, 4-byte aligned and not used by others.
+    pub unsafe fn new(base: *mut u8, size: usize) -> Self {
+        let count = size / 2 / Record::SIZE;
+        assert!(count > 0, "persistent log region too small");
+        let halves = [0, 1].map(|i| unsafe { base.add(i * count * Record::SIZE).cast::<Record>() });
+        let scanned = halves.map(|h| unsafe { scan_half(h, count) });
+
+        // The previous boot used the half with the later boot sequence number.
+        let prev = match scanned {
+            [Some((a, _)), Some((b, _))] if is_later(b, a) => Some(1),
+            [Some(_), _] => Some(0),
+            [None, Some(_)] => Some(1),
+            [None, None] => None,
+        };
+        let previous = prev.map(|i| {
+            let (boot, first_seq) = scanned[i].unwrap();
+            let len = unsafe { compact_half(halves[i], count, boot, first_seq) };
+            (halves[i] as *const u8, len)
+        });
+
+        let cur = prev.map_or(0, |i| 1 - i);
+        let log = Self {
+            records: halves[cur],
+            count,
+            boot: prev.map_or(0, |i| scanned[i].unwrap().0.wrapping_add(1)),
+            next: AtomicU32::new(0),
+            previous,
+        };
+        // An empty record marks the half as used by this boot, even if nothing
+        // is written.
+        log.write_record(&[]);
+        log
+    }
+
+    /// Returns the log of the previous boot, or `None` if it is not found.
+    ///
+    /// Records that are corrupted are left out.
+    pub fn previous(&self) -> Option<&[u8]> {
+        self.previous
+            .map(|(ptr, len)| unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// Writes a record with the given bytes, overwriting the oldest record if
+    /// the log is full.
+    fn write_record(&self, bytes: &[u8]) {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut r = Record {
+            boot: self.boot,
+            seq,
+            len: bytes.len() as u32,
+            check: 0,
+            data: [0; RECORD_DATA_SIZE],
+        };
+        r.data[..bytes.len()].copy_from_slice(bytes);
+        r.check = r.checksum();
+        let slot = unsafe { self.records.add(seq as usize % self.count) };
+        // Write the checksum last, so the record is not valid until it is
+        // fully written, as the old checksum does not match the new sequence
+        // number.
+        unsafe {
+            (&raw mut (*slot).boot).write_volatile(r.boot);
+            (&raw mut (*slot).seq).write_volatile(r.seq);
+            (&raw mut (*slot).len).write_volatile(r.len);
+            (&raw mut (*slot).data).write_volatile(r.data);
+            compiler_fence(Ordering::Release);
+            (&raw mut (*slot).check).write_volatile(r.check);
+        }
+    }
+
+    /// Appends bytes to the log of this boot, overwriting the oldest ones if
+    /// it is full.
+    ///
+    /// The bytes take at least one record, so callers should collect small
+    /// writes (see [`RecordWriter`]). It can be called concurrently, as each
+    /// record is reserved atomically, but the records of concurrent calls may
+    /// be interleaved.
+    pub fn write(&self, bytes: &[u8]) {
+        for chunk in bytes.chunks(RECORD_DATA_SIZE) {
+            self.write_record(chunk);
+        }
+    }
+}
+
+static PSTORE: LazyInit<PersistentLog> = LazyInit::new();
+
+/// Initializes the persistent log in the given memory region.
+///
+/// It is called by the platform on boot. The region must be reserved RAM
+/// (see [`MemIf::reserved_phys_ram_ranges`](crate::mem::MemIf)) that is not
+/// cleared on warm reboots. The output already in `LOG_BUFFER` (with the
+/// `log-buffer` feature) is written to it.
+pub fn init(base: VirtAddr, size: usize) {
+    let _log = PSTORE.init_once(unsafe { PersistentLog::new(base.as_mut_ptr(), size) });
+    #[cfg(feature = "log-buffer")]
+    {
+        let mut pos = 0;
+        let mut buf = [0; RECORD_DATA_SIZE];
+        loop {
+            let n = crate::console::LOG_BUFFER.read(&mut pos, &mut buf);
+            if n == 0 {
+                break;
+            }
+            _log.write(&buf[..n]);
+        }
+    }
+}
+
+/// Returns the log of the previous boot, or `None` if it is not found, or the
+/// platform does not support the persistent log.
+pub fn previous_log() -> Option<&'static [u8]> {
+    PSTORE.get()?.previous()
+}
+
+/// Collects the console output of a print into records of the log, so that
+/// each small write does not take a record.
+pub(crate) struct RecordWriter {
+    buf: [u8; RECORD_DATA_SIZE],
+    len: usize,
+}
+
+impl RecordWriter {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [0; RECORD_DATA_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Appends bytes, and writes the full records to the log.
+    pub(crate) fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let n = bytes.len().min(RECORD_DATA_SIZE - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+            if self.len == RECORD_DATA_SIZE {
+                self.flush();
+            }
+        }
+    }
+
+    /// Writes the collected bytes to the log.
+    pub(crate) fn flush(&mut self) {
+        if self.len > 0
+            && let Some(log) = PSTORE.get()
+        {
+            log.write(&self.buf[..self.len]);
+        }
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PersistentLog, RECORD_DATA_SIZE, Record};
+
+    /// Four records in each half.
+    const SIZE: usize = 2 * 4 * Record::SIZE;
+
+    fn region() -> *mut u8 {
+        Box::leak(Box::new([0u32; SIZE / 4]))
+            .as_mut_ptr()
+            .cast::<u8>()
+    }
+
+    #[test]
+    fn persistent_log() {
+        let region = region();
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert!(log.previous().is_none());
+        log.write(b"hello");
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert_eq!(log.previous(), Some(&b"hello"[..]));
+
+        // Only the latest records are kept when the ring wraps around.
+        let text = [b'a', b'b', b'c', b'd', b'e'].map(|c| [c; RECORD_DATA_SIZE]);
+        text.iter().for_each(|t| log.write(t));
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert_eq!(log.previous(), Some(text[1..].concat().as_slice()));
+
+        // Nothing is written in this boot.
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert_eq!(log.previous(), Some(&b""[..]));
+        log.write(b"bye");
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert_eq!(log.previous(), Some(&b"bye"[..]));
+    }
+
+    #[test]
+    fn corrupted_records() {
+        let region = region();
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        log.write(b"one");
+        log.write(b"two");
+        log.write(b"three");
+        // Corrupt "two", which is in the third record after the boot marker.
+        unsafe { *region.add(2 * Record::SIZE + 16) = b'T' };
+        // A torn record only loses itself.
+        let log = unsafe { PersistentLog::new(region, SIZE) };
+        assert_eq!(log.previous(), Some(&b"onethree"[..]));
+    }
+}
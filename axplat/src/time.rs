@@ -1,7 +1,15 @@
 //! Time-related operations.
 
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering, fence};
 pub use core::time::Duration;
 
+use kspin::SpinNoIrq;
+#[cfg(feature = "irq")]
+use lazyinit::LazyInit;
+
+#[cfg(feature = "timer-queue")]
+pub mod timer_queue;
+
 /// A measurement of the system clock.
 ///
 /// Currently, it reuses the [`core::time::Duration`] type. But it does not
@@ -19,6 +27,62 @@ pub const NANOS_PER_MILLIS: u64 = 1_000_000;
 /// Number of nanoseconds in a microsecond.
 pub const NANOS_PER_MICROS: u64 = 1_000;
 
+/// Conversion between hardware ticks and nanoseconds for a fixed clock
+/// frequency.
+///
+/// The conversions are exact (truncated to integers) and do not overflow in
+/// the intermediate steps, so they do not drift over long uptimes. Results
+/// that do not fit in `u64` are saturated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickConverter {
+    freq: u64,
+}
+
+impl TickConverter {
+    /// The maximum supported frequency in Hz (about 18.4 GHz).
+    pub const MAX_FREQUENCY: u64 = u64::MAX / NANOS_PER_SEC;
+
+    /// Creates a converter for the given frequency in Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `freq` is zero or larger than [`Self::MAX_FREQUENCY`].
+    pub const fn new(freq: u64) -> Self {
+        assert!(freq > 0 && freq <= Self::MAX_FREQUENCY);
+        Self { freq }
+    }
+
+    /// Creates a converter that converts everything to zero, which is used
+    /// before the frequency is known.
+    pub const fn zero() -> Self {
+        Self { freq: 0 }
+    }
+
+    /// Returns the frequency in Hz, or 0 if it is [`zero`](Self::zero).
+    pub const fn frequency(self) -> u64 {
+        self.freq
+    }
+
+    /// Converts ticks to nanoseconds.
+    pub const fn ticks_to_nanos(self, ticks: u64) -> u64 {
+        if self.freq == 0 {
+            return 0;
+        }
+        // `rem * NANOS_PER_SEC` does not overflow as `rem < MAX_FREQUENCY`.
+        let (secs, rem) = (ticks / self.freq, ticks % self.freq);
+        secs.saturating_mul(NANOS_PER_SEC)
+            .saturating_add(rem * NANOS_PER_SEC / self.freq)
+    }
+
+    /// Converts nanoseconds to ticks.
+    pub const fn nanos_to_ticks(self, nanos: u64) -> u64 {
+        // `rem * self.freq` does not overflow as `self.freq <= MAX_FREQUENCY`.
+        let (secs, rem) = (nanos / NANOS_PER_SEC, nanos % NANOS_PER_SEC);
+        secs.saturating_mul(self.freq)
+            .saturating_add(rem * self.freq / NANOS_PER_SEC)
+    }
+}
+
 /// Time-related interfaces.
 #[def_plat_interface]
 pub trait TimeIf {
@@ -35,15 +99,314 @@ pub trait TimeIf {
     /// clock start).
     fn epochoffset_nanos() -> u64;
 
+    /// Set epoch offset in nanoseconds, which changes the wall time.
+    ///
+    /// The new wall time is also written back to the RTC if the platform has
+    /// one, so it is kept across reboots.
+    fn set_epochoffset_nanos(epochoffset_nanos: u64);
+
     /// Set a one-shot timer.
     ///
     /// A timer interrupt will be triggered at the specified monotonic time
-    /// deadline (in nanoseconds).
+    /// deadline (in nanoseconds). If the deadline is beyond the range of the
+    /// hardware timer, the interrupt is triggered earlier, and the handler
+    /// should set the timer again.
     #[cfg(feature = "irq")]
     fn set_oneshot_timer(deadline_ns: u64);
+
+    /// Set a periodic timer.
+    ///
+    /// Timer interrupts will be triggered every `period_ns` nanoseconds,
+    /// starting one period from now, until the timer is cancelled or replaced
+    /// by [`set_oneshot_timer`].
+    ///
+    /// Returns `false` if the timer does not support the periodic mode, in
+    /// which case the caller should re-arm a one-shot timer in the handler.
+    #[cfg(feature = "irq")]
+    fn set_periodic_timer(period_ns: u64) -> bool;
+
+    /// Cancel the pending timer on the current CPU, either one-shot or
+    /// periodic.
+    ///
+    /// No timer interrupt will be triggered until a new timer is set.
+    #[cfg(feature = "irq")]
+    fn cancel_timer();
+
+    /// Set an RTC alarm at the specified wall time (in nanoseconds since
+    /// epoch).
+    ///
+    /// The RTC keeps running when the CPU timers are stopped, so the alarm can
+    /// wake up the system. When it fires, the handler set by
+    /// [`set_alarm_handler`] is called. The time is rounded up to the RTC
+    /// resolution, and an alarm in the past fires as soon as possible. It
+    /// replaces the previous alarm, if any.
+    ///
+    /// Returns `false` if the platform does not have an RTC alarm.
+    #[cfg(feature = "irq")]
+    fn set_rtc_alarm(wall_time_ns: u64) -> bool;
+
+    /// Cancel the pending RTC alarm, if any.
+    #[cfg(feature = "irq")]
+    fn cancel_rtc_alarm();
+}
+
+/// The function called when the RTC alarm fires, or 0 if not set.
+#[cfg(feature = "irq")]
+static ALARM_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function called when the RTC alarm set by [`set_rtc_alarm`] fires.
+///
+/// It is called in the IRQ context. `None` removes the function.
+#[cfg(feature = "irq")]
+pub fn set_alarm_handler(handler: Option<fn()>) {
+    ALARM_HANDLER.store(handler.map_or(0, |f| f as usize), Ordering::Release);
+}
+
+/// Calls the function set by [`set_alarm_handler`], if any.
+///
+/// It is called by the RTC IRQ handler of the platform.
+#[cfg(feature = "irq")]
+pub fn notify_alarm() {
+    let handler = ALARM_HANDLER.load(Ordering::Acquire);
+    if handler != 0 {
+        // SAFETY: `handler` is stored from a `fn()` by `set_alarm_handler`.
+        unsafe { core::mem::transmute::<usize, fn()>(handler)() };
+    }
+}
+
+#[cfg(feature = "irq")]
+static TIMER_IRQ: LazyInit<usize> = LazyInit::new();
+
+/// Sets the IRQ number of the per-CPU timer set by [`set_oneshot_timer`].
+///
+/// It is called by the platform during initialization, which also calls
+/// [`handle_timer_irq`] when it receives this IRQ.
+#[cfg(feature = "irq")]
+pub fn init_timer_irq(timer_irq: usize) {
+    TIMER_IRQ.init_once(timer_irq);
+}
+
+/// Returns the IRQ number of the per-CPU timer, or `None` if it is not set by
+/// the platform.
+#[cfg(feature = "irq")]
+pub fn timer_irq() -> Option<usize> {
+    TIMER_IRQ.get().copied()
+}
+
+/// Runs the expired timers of the `timer_queue` on the given CPU.
+///
+/// It is called by the platform on the CPU when it receives the timer IRQ,
+/// before the timer handler registered by the kernel (if any). Returns `true`
+/// if the interrupt may be armed by the timer queue, or always `false` without
+/// the `timer-queue` feature.
+#[cfg(feature = "irq")]
+pub fn handle_timer_irq(_cpu_id: usize) -> bool {
+    #[cfg(feature = "timer-queue")]
+    if timer_queue::handle_timers(_cpu_id) {
+        return true;
+    }
+    false
+}
+
+/// Maximum frequency adjustment in parts per billion (500 ppm).
+pub const MAX_FREQUENCY_ADJUSTMENT_PPB: i64 = 500_000;
+
+/// Rate of the correction applied by [`slew_wall_time`] in parts per billion
+/// (500 ppm, i.e., 0.5 ms per second).
+pub const SLEW_RATE_PPB: i64 = 500_000;
+
+/// Maximum correction of [`slew_wall_time`] in nanoseconds (about 106 days),
+/// where the slew takes `u64::MAX` nanoseconds at [`SLEW_RATE_PPB`].
+pub const MAX_SLEW_NANOS: u64 = u64::MAX / (NANOS_PER_SEC / SLEW_RATE_PPB as u64);
+
+/// The clock of the wall time as a piecewise linear function of the monotonic
+/// clock.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ClockAdjustment {
+    /// Raw time when the adjustment was last changed.
+    base_raw: u64,
+    /// Adjusted time at `base_raw`.
+    base: u64,
+    /// Frequency adjustment in parts per billion.
+    ppb: i64,
+    /// Additional frequency adjustment for the slew, which is applied for
+    /// `slew_len` nanoseconds of raw time since `base_raw`.
+    slew_ppb: i64,
+    slew_len: u64,
+}
+
+impl ClockAdjustment {
+    /// Scales the duration `d` by `1 + ppb / 10^9`.
+    ///
+    /// It is non-decreasing in `d`, as `ppb > -10^9`.
+    fn scale(d: u64, ppb: i64) -> u64 {
+        let (secs, rem) = (d / NANOS_PER_SEC, d % NANOS_PER_SEC);
+        let correction = secs as i64 * ppb + rem as i64 * ppb / NANOS_PER_SEC as i64;
+        d.saturating_add_signed(correction)
+    }
+
+    /// Returns the adjusted time at the raw time `raw`.
+    fn adjusted(&self, raw: u64) -> u64 {
+        let d = raw.saturating_sub(self.base_raw);
+        let slewed = d.min(self.slew_len);
+        self.base
+            .saturating_add(Self::scale(slewed, self.ppb + self.slew_ppb))
+            .saturating_add(Self::scale(d - slewed, self.ppb))
+    }
+
+    /// Returns the same function with a new slew of `delta_ns` from `raw`,
+    /// which replaces the slew that is not done yet.
+    ///
+    /// The correction is clamped to [`MAX_SLEW_NANOS`].
+    fn with_slew(&self, raw: u64, delta_ns: i64) -> Self {
+        let delta = delta_ns.unsigned_abs().min(MAX_SLEW_NANOS);
+        Self {
+            slew_ppb: SLEW_RATE_PPB * delta_ns.signum(),
+            slew_len: delta * (NANOS_PER_SEC / SLEW_RATE_PPB as u64),
+            ..self.rebase(raw)
+        }
+    }
+
+    /// Returns the correction of the slew that is not applied yet at `raw`.
+    fn remaining_slew(&self, raw: u64) -> i64 {
+        let left = self.slew_len - raw.saturating_sub(self.base_raw).min(self.slew_len);
+        (left as i128 * self.slew_ppb as i128 / NANOS_PER_SEC as i128) as i64
+    }
+
+    /// Returns the same function, with the base moved to `raw`.
+    fn rebase(&self, raw: u64) -> Self {
+        let d = raw.saturating_sub(self.base_raw);
+        Self {
+            base_raw: raw,
+            base: self.adjusted(raw),
+            slew_len: self.slew_len - d.min(self.slew_len),
+            ..*self
+        }
+    }
+}
+
+/// A sequence lock of [`ClockAdjustment`], which is read without locking.
+struct AdjustmentCell {
+    /// Odd when it is being updated.
+    seq: AtomicUsize,
+    fields: [AtomicU64; 5],
+    /// Serializes the updates.
+    lock: SpinNoIrq<()>,
+}
+
+impl AdjustmentCell {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            fields: [const { AtomicU64::new(0) }; 5],
+            lock: SpinNoIrq::new(()),
+        }
+    }
+
+    fn load_fields(&self) -> ClockAdjustment {
+        let [base_raw, base, ppb, slew_ppb, slew_len] =
+            self.fields.each_ref().map(|f| f.load(Ordering::Relaxed));
+        ClockAdjustment {
+            base_raw,
+            base,
+            ppb: ppb as i64,
+            slew_ppb: slew_ppb as i64,
+            slew_len,
+        }
+    }
+
+    /// Calls `f` with a consistent adjustment, retrying if it is updated
+    /// concurrently.
+    fn read<R>(&self, f: impl Fn(&ClockAdjustment) -> R) -> R {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                let result = f(&self.load_fields());
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return result;
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Replaces the adjustment by the result of `f`.
+    fn update(&self, f: impl FnOnce(&ClockAdjustment) -> ClockAdjustment) {
+        let _guard = self.lock.lock();
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        let adj = f(&self.load_fields());
+        let values = [
+            adj.base_raw,
+            adj.base,
+            adj.ppb as u64,
+            adj.slew_ppb as u64,
+            adj.slew_len,
+        ];
+        for (field, value) in self.fields.iter().zip(values) {
+            field.store(value, Ordering::Relaxed);
+        }
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+static CLOCK_ADJUSTMENT: AdjustmentCell = AdjustmentCell::new();
+
+/// Returns the monotonic time in nanoseconds adjusted by
+/// [`set_frequency_adjustment`] and [`slew_wall_time`], which is the wall time
+/// without the epoch offset.
+fn adjusted_time_nanos() -> u64 {
+    CLOCK_ADJUSTMENT.read(|adj| adj.adjusted(monotonic_time_nanos()))
+}
+
+/// Sets the frequency adjustment of the wall clock in parts per billion, e.g.,
+/// to correct the drift of the oscillator measured by NTP.
+///
+/// A positive value makes the wall clock run faster. It applies to
+/// [`wall_time`] from now on without steps. [`monotonic_time`] is the raw
+/// hardware clock and is not adjusted, so it stays in the same time base as
+/// the timer deadlines.
+///
+/// Returns `false` if `ppb` exceeds [`MAX_FREQUENCY_ADJUSTMENT_PPB`].
+pub fn set_frequency_adjustment(ppb: i64) -> bool {
+    if ppb.abs() > MAX_FREQUENCY_ADJUSTMENT_PPB {
+        return false;
+    }
+    CLOCK_ADJUSTMENT.update(|adj| ClockAdjustment {
+        ppb,
+        ..adj.rebase(monotonic_time_nanos())
+    });
+    true
+}
+
+/// Returns the frequency adjustment of the clock in parts per billion.
+pub fn frequency_adjustment() -> i64 {
+    CLOCK_ADJUSTMENT.read(|adj| adj.ppb)
+}
+
+/// Gradually moves the wall clock by `delta_ns` nanoseconds, like `adjtime`.
+///
+/// The wall clock runs faster or slower by [`SLEW_RATE_PPB`] until the
+/// correction is done, so it never steps or goes backwards. Like the frequency
+/// adjustment, it does not apply to [`monotonic_time`]. It replaces the slew
+/// that is not done yet, and the correction is clamped to [`MAX_SLEW_NANOS`].
+pub fn slew_wall_time(delta_ns: i64) {
+    CLOCK_ADJUSTMENT.update(|adj| adj.with_slew(monotonic_time_nanos(), delta_ns));
+}
+
+/// Returns the correction of [`slew_wall_time`] in nanoseconds that is not
+/// applied yet.
+pub fn remaining_slew_nanos() -> i64 {
+    CLOCK_ADJUSTMENT.read(|adj| adj.remaining_slew(monotonic_time_nanos()))
 }
 
 /// Returns nanoseconds elapsed since system boot.
+///
+/// It is the raw hardware clock, which is not adjusted by
+/// [`set_frequency_adjustment`] or [`slew_wall_time`]. Timer deadlines are in
+/// this time base.
 pub fn monotonic_time_nanos() -> u64 {
     ticks_to_nanos(current_ticks())
 }
@@ -54,23 +417,147 @@ pub fn monotonic_time() -> TimeValue {
 }
 
 /// Returns nanoseconds elapsed since epoch (also known as realtime).
+///
+/// It is adjusted by [`set_frequency_adjustment`] and [`slew_wall_time`].
 pub fn wall_time_nanos() -> u64 {
-    monotonic_time_nanos() + epochoffset_nanos()
+    adjusted_time_nanos() + epochoffset_nanos()
 }
 
 /// Returns the time elapsed since epoch (also known as realtime) in [`TimeValue`].
 pub fn wall_time() -> TimeValue {
-    TimeValue::from_nanos(monotonic_time_nanos() + epochoffset_nanos())
+    TimeValue::from_nanos(wall_time_nanos())
+}
+
+/// Sets the wall time (also known as realtime), e.g., when it is synchronized
+/// by NTP.
+///
+/// The monotonic time is not affected. The new time is written back to the
+/// RTC if the platform has one.
+pub fn set_wall_time(time: TimeValue) {
+    let nanos = time.as_nanos().min(u64::MAX as u128) as u64;
+    set_epochoffset_nanos(nanos.saturating_sub(adjusted_time_nanos()));
 }
 
 /// Busy waiting for the given duration.
 pub fn busy_wait(dur: Duration) {
-    busy_wait_until(wall_time() + dur);
+    busy_wait_until(monotonic_time() + dur);
 }
 
 /// Busy waiting until reaching the given deadline.
+///
+/// The deadline is in the monotonic time, so it is not affected by the changes
+/// of the wall time.
 pub fn busy_wait_until(deadline: TimeValue) {
-    while wall_time() < deadline {
+    while monotonic_time() < deadline {
         core::hint::spin_loop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ClockAdjustment, MAX_SLEW_NANOS, NANOS_PER_SEC, TickConverter};
+
+    fn exact_nanos(freq: u64, ticks: u64) -> u64 {
+        (ticks as u128 * NANOS_PER_SEC as u128 / freq as u128) as u64
+    }
+
+    fn exact_ticks(freq: u64, nanos: u64) -> u64 {
+        (nanos as u128 * freq as u128 / NANOS_PER_SEC as u128) as u64
+    }
+
+    #[test]
+    fn tick_converter() {
+        const YEAR_SECS: u64 = 365 * 24 * 3600;
+        // Frequencies that do not divide 10^9, and a TSC faster than 4.3 GHz.
+        for freq in [
+            1_193_182,
+            3_579_545,
+            19_200_000,
+            24_000_000,
+            1_000_000_000,
+            2_994_373_000,
+            5_200_000_001,
+            TickConverter::MAX_FREQUENCY,
+        ] {
+            let conv = TickConverter::new(freq);
+            for secs in [0, 1, 3600, YEAR_SECS, 10 * YEAR_SECS, 100 * YEAR_SECS] {
+                for extra in [0, 1, freq / 3, freq - 1] {
+                    let Some(ticks) = (secs as u128 * freq as u128 + extra as u128)
+                        .try_into()
+                        .ok()
+                    else {
+                        continue;
+                    };
+                    let nanos = conv.ticks_to_nanos(ticks);
+                    assert_eq!(nanos, exact_nanos(freq, ticks));
+                    assert_eq!(conv.nanos_to_ticks(nanos), exact_ticks(freq, nanos));
+                    // Converting back loses less than a nanosecond.
+                    assert!(ticks - conv.nanos_to_ticks(nanos) <= freq.div_ceil(NANOS_PER_SEC));
+                }
+            }
+            // The time after 10 years does not drift from the frequency.
+            let ticks = 10 * YEAR_SECS * freq;
+            assert_eq!(conv.ticks_to_nanos(ticks), 10 * YEAR_SECS * NANOS_PER_SEC);
+            assert_eq!(conv.nanos_to_ticks(10 * YEAR_SECS * NANOS_PER_SEC), ticks);
+        }
+
+        // Results are saturated.
+        let conv = TickConverter::new(1);
+        assert_eq!(conv.ticks_to_nanos(u64::MAX), u64::MAX);
+        let conv = TickConverter::new(TickConverter::MAX_FREQUENCY);
+        assert_eq!(conv.nanos_to_ticks(u64::MAX), u64::MAX);
+        assert_eq!(TickConverter::zero().ticks_to_nanos(123), 0);
+        assert_eq!(TickConverter::zero().nanos_to_ticks(123), 0);
+    }
+
+    #[test]
+    fn clock_adjustment() {
+        const SEC: u64 = NANOS_PER_SEC;
+        let adj = ClockAdjustment::default();
+        assert_eq!(adj.adjusted(123 * SEC), 123 * SEC);
+
+        // 100 ppm faster from 10 seconds.
+        let adj = ClockAdjustment {
+            ppb: 100_000,
+            ..adj.rebase(10 * SEC)
+        };
+        assert_eq!(adj.adjusted(10 * SEC), 10 * SEC);
+        assert_eq!(adj.adjusted(20 * SEC), 20 * SEC + 1_000_000);
+
+        // Slowing down does not step the clock or make it go backwards.
+        let slower = ClockAdjustment {
+            ppb: -500_000,
+            ..adj.rebase(20 * SEC)
+        };
+        assert_eq!(slower.adjusted(20 * SEC), adj.adjusted(20 * SEC));
+        let mut last = 0;
+        for raw in (20 * SEC..20 * SEC + 10_000).chain([30 * SEC, 1 << 62]) {
+            let t = slower.adjusted(raw);
+            assert!(t >= last);
+            last = t;
+        }
+
+        // Slew by -1 ms, which takes 2 seconds at the slew rate.
+        let delta: i64 = -1_000_000;
+        let slew = ClockAdjustment::default().with_slew(SEC, delta);
+        assert_eq!(slew.remaining_slew(SEC), delta);
+        assert_eq!(slew.remaining_slew(2 * SEC), delta / 2);
+        assert_eq!(slew.adjusted(2 * SEC), 2 * SEC - 500_000);
+        assert_eq!(slew.adjusted(3 * SEC), 3 * SEC - 1_000_000);
+        assert_eq!(slew.remaining_slew(3 * SEC), 0);
+        assert_eq!(slew.adjusted(10 * SEC), 10 * SEC - 1_000_000);
+        // Rebasing in the middle of the slew keeps the rest of it.
+        let rebased = slew.rebase(2 * SEC);
+        assert_eq!(rebased.remaining_slew(2 * SEC), delta / 2);
+        for raw in [2 * SEC, 2 * SEC + 1, 3 * SEC, 10 * SEC] {
+            assert_eq!(rebased.adjusted(raw), slew.adjusted(raw));
+        }
+
+        // Huge corrections are clamped instead of overflowing.
+        for delta in [i64::MIN, i64::MAX] {
+            let slew = ClockAdjustment::default().with_slew(0, delta);
+            assert_eq!(slew.slew_len, u64::MAX - u64::MAX % 2000);
+            assert_eq!(slew.remaining_slew(0).unsigned_abs(), MAX_SLEW_NANOS);
+        }
+    }
+}
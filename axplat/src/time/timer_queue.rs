@@ -0,0 +1,273 @@
+//! Software timers multiplexed onto the one-shot timer of each CPU.
+//!
+//! Each CPU has a queue of up to [`MAX_TIMERS`] timers, and the earliest
+//! deadline of them is set by [`set_oneshot_timer`]. The platform runs the
+//! expired timers in its timer IRQ handler by
+//! [`handle_timer_irq`](super::handle_timer_irq), before the handler
+//! registered by the kernel, and sets the next deadline.
+//!
+//! Timers are added on the current CPU, as the one-shot timer is per-CPU, and
+//! can be removed from any CPU. The kernel tells the queue how to get the
+//! current CPU ID by [`set_cpu_id_fn`]. While a CPU has pending timers, the
+//! queue owns its one-shot timer, so the kernel should not set or cancel the
+//! timer by itself, e.g., it can use a timer of the queue for the scheduler
+//! tick instead. Otherwise the queue leaves the timer alone: it never cancels
+//! the timer, and ignores the timer interrupts that it has not armed.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use super::{monotonic_time_nanos, set_oneshot_timer};
+use crate::irq::CpuMask;
+
+/// The type of a function called when a timer expires.
+///
+/// The argument is the one given to [`add_timer`].
+pub type TimerCallback = fn(usize);
+
+/// The maximum number of pending timers of a CPU.
+pub const MAX_TIMERS: usize = 32;
+
+/// The identifier of a timer, used to remove it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId {
+    cpu_id: usize,
+    seq: u64,
+}
+
+/// A pending timer.
+#[derive(Clone, Copy)]
+struct Timer {
+    deadline: u64,
+    /// The order in which timers are added, which also identifies the timer.
+    seq: u64,
+    callback: TimerCallback,
+    arg: usize,
+}
+
+/// Pending timers of a CPU, in no particular order.
+struct TimerList<const N: usize> {
+    timers: [Option<Timer>; N],
+}
+
+impl<const N: usize> TimerList<N> {
+    const fn new() -> Self {
+        Self { timers: [None; N] }
+    }
+
+    /// Adds a timer. Returns `false` if the list is full.
+    fn insert(&mut self, timer: Timer) -> bool {
+        match self.timers.iter_mut().find(|t| t.is_none()) {
+            Some(slot) => {
+                *slot = Some(timer);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the timer with the given sequence number. Returns `false` if it
+    /// is not found.
+    fn remove(&mut self, seq: u64) -> bool {
+        let slot = self
+            .timers
+            .iter_mut()
+            .find(|t| t.is_some_and(|t| t.seq == seq));
+        match slot {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the slot of the earliest timer, where timers with the same
+    /// deadline are ordered by when they are added.
+    fn earliest(&self) -> Option<usize> {
+        (0..N)
+            .filter(|&i| self.timers[i].is_some())
+            .min_by_key(|&i| self.timers[i].map(|t| (t.deadline, t.seq)))
+    }
+
+    /// Returns the earliest deadline.
+    fn next_deadline(&self) -> Option<u64> {
+        self.earliest()
+            .and_then(|i| self.timers[i])
+            .map(|t| t.deadline)
+    }
+
+    /// Removes and returns the earliest timer if it expires at `now`.
+    fn pop_expired(&mut self, now: u64) -> Option<Timer> {
+        let i = self.earliest()?;
+        if self.timers[i]?.deadline > now {
+            return None;
+        }
+        self.timers[i].take()
+    }
+}
+
+/// Timers of a CPU.
+struct CpuTimers {
+    list: TimerList<MAX_TIMERS>,
+    /// The deadline set to the one-shot timer by the queue, or `None` if the
+    /// queue does not own the timer.
+    armed: Option<u64>,
+}
+
+impl CpuTimers {
+    const fn new() -> Self {
+        Self {
+            list: TimerList::new(),
+            armed: None,
+        }
+    }
+
+    /// Sets the one-shot timer to the earliest deadline, or releases the timer
+    /// if there are no timers.
+    fn rearm(&mut self) {
+        self.armed = self.list.next_deadline();
+        if let Some(deadline) = self.armed {
+            set_oneshot_timer(deadline);
+        }
+    }
+}
+
+static QUEUES: [SpinNoIrq<CpuTimers>; CpuMask::MAX_CPUS] =
+    [const { SpinNoIrq::new(CpuTimers::new()) }; CpuMask::MAX_CPUS];
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// The function to get the current CPU ID, or 0 if not set.
+static CPU_ID_FN: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function to get the current CPU ID, which selects the queue of
+/// [`add_timer`].
+///
+/// The current CPU is 0 before this function is called, so it must be called
+/// before timers are added on other CPUs.
+pub fn set_cpu_id_fn(cpu_id: fn() -> usize) {
+    CPU_ID_FN.store(cpu_id as usize, Ordering::Release);
+}
+
+fn current_cpu_id() -> usize {
+    match CPU_ID_FN.load(Ordering::Acquire) {
+        0 => 0,
+        // SAFETY: it is stored from a `fn() -> usize` by `set_cpu_id_fn`.
+        f => unsafe { core::mem::transmute::<usize, fn() -> usize>(f)() },
+    }
+}
+
+/// Adds a timer on the current CPU, which calls `callback(arg)` in the timer
+/// IRQ handler at the monotonic time `deadline_ns`.
+///
+/// Preemption should be disabled, so the current CPU does not change during
+/// the call. Returns `None` if the CPU already has [`MAX_TIMERS`] timers.
+///
+/// # Panics
+///
+/// Panics if the current CPU ID is not less than [`CpuMask::MAX_CPUS`].
+pub fn add_timer(deadline_ns: u64, callback: TimerCallback, arg: usize) -> Option<TimerId> {
+    let cpu_id = current_cpu_id();
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let timer = Timer {
+        deadline: deadline_ns,
+        seq,
+        callback,
+        arg,
+    };
+    let mut queue = QUEUES[cpu_id].lock();
+    if !queue.list.insert(timer) {
+        return None;
+    }
+    if queue.armed.is_none_or(|d| deadline_ns < d) {
+        queue.armed = Some(deadline_ns);
+        set_oneshot_timer(deadline_ns);
+    }
+    Some(TimerId { cpu_id, seq })
+}
+
+/// Removes a pending timer, which can be called on any CPU.
+///
+/// Returns `false` if the timer has expired or been removed.
+pub fn remove_timer(id: TimerId) -> bool {
+    // The one-shot timer is kept, as `handle_timers` ignores it if nothing
+    // expires.
+    QUEUES[id.cpu_id].lock().list.remove(id.seq)
+}
+
+/// Returns the earliest deadline of the timers on the given CPU, or `None` if
+/// there are no timers.
+pub fn next_deadline(cpu_id: usize) -> Option<u64> {
+    QUEUES.get(cpu_id)?.lock().list.next_deadline()
+}
+
+/// Runs the expired timers of the given CPU in the order of their deadlines,
+/// and sets the one-shot timer to the next deadline if the queue owns it.
+///
+/// It is called in the timer IRQ handler on the CPU. The callbacks may add or
+/// remove timers. Returns `true` if the queue owns the one-shot timer, i.e.,
+/// the interrupt may be armed by the queue.
+pub(crate) fn handle_timers(cpu_id: usize) -> bool {
+    let Some(queue) = QUEUES.get(cpu_id) else {
+        return false;
+    };
+    if queue.lock().armed.is_none() {
+        return false;
+    }
+    loop {
+        let expired = queue.lock().list.pop_expired(monotonic_time_nanos());
+        let Some(timer) = expired else {
+            break;
+        };
+        (timer.callback)(timer.arg);
+    }
+    // The timer may also fire early if the deadline is beyond the range of
+    // the hardware timer, so it is set again even if nothing expires.
+    queue.lock().rearm();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Timer, TimerList};
+
+    fn timer(deadline: u64, seq: u64) -> Timer {
+        Timer {
+            deadline,
+            seq,
+            callback: |_| {},
+            arg: seq as usize,
+        }
+    }
+
+    #[test]
+    fn timer_list() {
+        let mut list = TimerList::<4>::new();
+        assert_eq!(list.next_deadline(), None);
+        assert!(list.pop_expired(u64::MAX).is_none());
+
+        for (deadline, seq) in [(300, 0), (100, 1), (200, 2), (100, 3)] {
+            assert!(list.insert(timer(deadline, seq)));
+        }
+        assert!(!list.insert(timer(50, 4)));
+        assert_eq!(list.next_deadline(), Some(100));
+
+        // Timers with the same deadline run in the order they are added.
+        assert!(list.pop_expired(99).is_none());
+        assert_eq!(list.pop_expired(150).map(|t| t.seq), Some(1));
+        assert_eq!(list.pop_expired(150).map(|t| t.seq), Some(3));
+        assert!(list.pop_expired(150).is_none());
+
+        assert!(list.remove(2));
+        assert!(!list.remove(2));
+        assert!(!list.remove(1));
+        assert_eq!(list.next_deadline(), Some(300));
+        assert!(list.insert(timer(50, 4)));
+        assert_eq!(list.next_deadline(), Some(50));
+        assert_eq!(list.pop_expired(1000).map(|t| t.seq), Some(4));
+        assert_eq!(list.pop_expired(1000).map(|t| t.seq), Some(0));
+        assert_eq!(list.next_deadline(), None);
+    }
+}
@@ -0,0 +1,11 @@
+use axplat::boot::{BootIf, BootInfo};
+
+struct BootIfImpl;
+
+#[impl_plat_interface]
+impl BootIf for BootIfImpl {
+    /// Parses the boot information from the argument passed by the bootloader.
+    fn parse_boot_info(arg: usize) -> BootInfo {
+        todo!()
+    }
+}
@@ -15,4 +15,15 @@ impl ConsoleIf for ConsoleIfImpl {
     fn read_bytes(bytes: &mut [u8]) -> usize {
         todo!()
     }
+
+    /// Waits until all bytes written to the console are sent.
+    fn flush() {
+        todo!()
+    }
+
+    /// Writes given bytes to the console synchronously, without taking any
+    /// lock.
+    fn write_bytes_nolock(bytes: &[u8]) {
+        todo!()
+    }
 }
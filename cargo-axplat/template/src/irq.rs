@@ -1,4 +1,11 @@
-use axplat::irq::{IrqHandler, IrqIf, IpiTarget};
+#[cfg(feature = "alloc")]
+use axplat::irq::BoxedIrqHandler;
+#[cfg(feature = "irq-stats")]
+use axplat::irq::IrqCounters;
+use axplat::irq::{
+    CpuMask, IrqContextHandler, IrqHandler, IrqIf, IpiTarget, MsiMessage, Polarity,
+    SharedIrqHandler, TriggerMode,
+};
 
 struct IrqIfImpl;
 
@@ -9,6 +16,27 @@ impl IrqIf for IrqIfImpl {
         todo!()
     }
 
+    /// Sets the trigger mode and polarity of the given IRQ.
+    ///
+    /// It returns `false` if it is not supported.
+    fn set_trigger(irq: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+        todo!()
+    }
+
+    /// Sets the priority of the given IRQ.
+    ///
+    /// It returns `false` if it is not supported.
+    fn set_priority(irq: usize, priority: u8) -> bool {
+        todo!()
+    }
+
+    /// Sets the CPUs that the given IRQ can be delivered to.
+    ///
+    /// It returns `false` if it is not supported.
+    fn set_affinity(irq: usize, cpu_mask: CpuMask) -> bool {
+        todo!()
+    }
+
     /// Registers an IRQ handler for the given IRQ.
     ///
     /// It also enables the IRQ if the registration succeeds. It returns `false`
@@ -17,6 +45,23 @@ impl IrqIf for IrqIfImpl {
         todo!()
     }
 
+    /// Registers an IRQ handler with a context pointer for the given IRQ.
+    ///
+    /// It also enables the IRQ if the registration succeeds. It returns `false`
+    /// if the registration failed.
+    fn register_with_context(irq: usize, handler: IrqContextHandler, context: *mut ()) -> bool {
+        todo!()
+    }
+
+    /// Registers a boxed closure as the IRQ handler for the given IRQ.
+    ///
+    /// It also enables the IRQ if the registration succeeds. It returns `false`
+    /// if the registration failed.
+    #[cfg(feature = "alloc")]
+    fn register_boxed(irq: usize, handler: BoxedIrqHandler) -> bool {
+        todo!()
+    }
+
     /// Unregisters the IRQ handler for the given IRQ.
     ///
     /// It also disables the IRQ if the unregistration succeeds. It returns the
@@ -25,6 +70,36 @@ impl IrqIf for IrqIfImpl {
         todo!()
     }
 
+    /// Adds a handler to the shared IRQ line.
+    ///
+    /// It also enables the IRQ if the registration succeeds. It returns `false`
+    /// if the registration failed.
+    fn register_shared(irq: usize, handler: SharedIrqHandler, context: *mut ()) -> bool {
+        todo!()
+    }
+
+    /// Removes the handler registered with the given context pointer from the
+    /// shared IRQ line.
+    ///
+    /// It also disables the IRQ if no handlers remain. It returns `false` if
+    /// the handler is not found.
+    fn unregister_shared(irq: usize, context: *mut ()) -> bool {
+        todo!()
+    }
+
+    /// Allocates an MSI vector that is delivered to the given CPU.
+    ///
+    /// It returns the IRQ number of the vector and the message to program
+    /// into the device, or `None` if MSIs are not supported.
+    fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+        todo!()
+    }
+
+    /// Frees an MSI vector allocated by [`alloc_msi`](IrqIf::alloc_msi).
+    fn free_msi(irq: usize) -> bool {
+        todo!()
+    }
+
     /// Handles the IRQ.
     ///
     /// It is called by the common interrupt handler. It should look up in the
@@ -38,4 +113,19 @@ impl IrqIf for IrqIfImpl {
     fn send_ipi(irq_num: usize, target: IpiTarget) {
         todo!()
     }
+
+    /// Takes a snapshot of the interrupt statistics.
+    ///
+    /// `f` is called with (`irq`, `cpu_id`, counters) for each IRQ on each CPU
+    /// with non-zero counters.
+    #[cfg(feature = "irq-stats")]
+    fn irq_stats(f: &mut dyn FnMut(usize, usize, IrqCounters)) {
+        todo!()
+    }
+
+    /// Returns the number of spurious interrupts on the given CPU.
+    #[cfg(feature = "irq-stats")]
+    fn spurious_irq_count(cpu_id: usize) -> u64 {
+        todo!()
+    }
 }
@@ -3,6 +3,7 @@
 #[macro_use]
 extern crate axplat;
 
+mod boot_info;
 mod console;
 mod init;
 #[cfg(feature = "irq")]
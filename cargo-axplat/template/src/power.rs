@@ -1,4 +1,4 @@
-use axplat::power::PowerIf;
+use axplat::power::{CpuState, PowerIf, ResetKind, SuspendState};
 
 struct PowerImpl;
 
@@ -14,8 +14,30 @@ impl PowerIf for PowerImpl {
         todo!()
     }
 
+    /// Powers off the calling CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        todo!()
+    }
+
+    /// Returns the state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        todo!()
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        todo!()
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         todo!()
     }
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> ! {
+        todo!()
+    }
 }
@@ -25,6 +25,12 @@ impl TimeIf for TimeIfImpl {
         todo!()
     }
 
+    /// Set epoch offset in nanoseconds, and write the new wall time back to
+    /// the RTC if any.
+    fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+        todo!()
+    }
+
     /// Set a one-shot timer.
     ///
     /// A timer interrupt will be triggered at the specified monotonic time
@@ -33,4 +39,34 @@ impl TimeIf for TimeIfImpl {
     fn set_oneshot_timer(deadline_ns: u64) {
         todo!()
     }
+
+    /// Set a periodic timer.
+    ///
+    /// Timer interrupts will be triggered every `period_ns` nanoseconds.
+    /// Returns `false` if the periodic mode is not supported.
+    #[cfg(feature = "irq")]
+    fn set_periodic_timer(period_ns: u64) -> bool {
+        todo!()
+    }
+
+    /// Cancel the pending timer on the current CPU.
+    #[cfg(feature = "irq")]
+    fn cancel_timer() {
+        todo!()
+    }
+
+    /// Set an RTC alarm at the specified wall time (in nanoseconds since
+    /// epoch). Returns `false` if there is no RTC alarm.
+    ///
+    /// The platform calls [`axplat::time::notify_alarm`] when it fires.
+    #[cfg(feature = "irq")]
+    fn set_rtc_alarm(wall_time_ns: u64) -> bool {
+        todo!()
+    }
+
+    /// Cancel the pending RTC alarm, if any.
+    #[cfg(feature = "irq")]
+    fn cancel_rtc_alarm() {
+        todo!()
+    }
 }
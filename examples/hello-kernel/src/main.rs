@@ -1,6 +1,8 @@
 #![no_std]
 #![no_main]
 
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         extern crate axplat_x86_pc;
@@ -15,7 +17,12 @@ cfg_if::cfg_if! {
     }
 }
 
+/// The ID of the only running CPU, which is printed on panic.
+static CPU_ID: AtomicUsize = AtomicUsize::new(0);
+
 fn init_kernel(cpu_id: usize, arg: usize) {
+    CPU_ID.store(cpu_id, Ordering::Relaxed);
+
     // x86_64 requires the `percpu` crate to be initialized first.
     #[cfg(target_arch = "x86_64")]
     axcpu::init::init_percpu(cpu_id);
@@ -46,6 +53,6 @@ fn main(cpu_id: usize, arg: usize) -> ! {
 #[cfg(all(target_os = "none", not(test)))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    axplat::console_println!("{info}");
+    axplat::console_panic_println!(CPU_ID.load(Ordering::Relaxed), "{info}");
     axplat::power::system_off()
 }
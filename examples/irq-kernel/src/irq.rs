@@ -1,5 +1,5 @@
-use core::sync::atomic::AtomicU64;
 use core::sync::atomic::Ordering::{Acquire, Release};
+use core::sync::atomic::{AtomicBool, AtomicU64};
 
 use axcpu::trap::{IRQ, register_trap_handler};
 
@@ -18,10 +18,18 @@ fn irq_handler(vector: usize) -> bool {
 }
 
 pub fn init_irq() {
-    fn update_timer() {
-        static PERIODIC_INTERVAL_NANOS: u64 = axplat::time::NANOS_PER_SEC / TICKS_PER_SEC;
+    const PERIODIC_INTERVAL_NANOS: u64 = axplat::time::NANOS_PER_SEC / TICKS_PER_SEC;
+
+    // Whether the timer is re-armed by the handler, as the periodic mode is
+    // not supported.
+    static ONESHOT: AtomicBool = AtomicBool::new(false);
 
+    fn update_timer() {
         IRQ_COUNTER.fetch_add(1, Release);
+        if !ONESHOT.load(Acquire) {
+            return;
+        }
+
         // Reset the timer for the next interrupt.
         static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(0);
 
@@ -39,6 +47,12 @@ pub fn init_irq() {
     axplat::irq::register(axplat_crate::config::devices::TIMER_IRQ, update_timer);
     axplat::console_println!("Timer IRQ handler registered.");
 
+    if axplat::time::set_periodic_timer(PERIODIC_INTERVAL_NANOS) {
+        axplat::console_println!("Periodic timer set.");
+    } else {
+        ONESHOT.store(true, Release);
+    }
+
     // Enable the timer IRQ.
     axcpu::asm::enable_irqs();
 }
@@ -68,5 +82,6 @@ pub fn test_irq() {
         );
     }
 
+    axplat::time::cancel_timer();
     axplat::console_println!("Timer IRQ test passed.");
 }
@@ -59,6 +59,6 @@ fn main(cpu_id: usize, arg: usize) -> ! {
 #[cfg(all(target_os = "none", not(test)))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    axplat::console_println!("{info}");
+    axplat::console_panic_println!(this_cpu_id(), "{info}");
     axplat::power::system_off()
 }
@@ -129,9 +129,9 @@ pub(crate) unsafe extern "C" fn _start_secondary() -> ! {
 
         mov     sp, x0
         bl      {switch_to_el1}
+        bl      {enable_fp}
         adrp    x0, {boot_pt}
         bl      {init_mmu}
-        bl      {enable_fp}
 
         mov     x8, {phys_virt_offset}  // set SP to the high address
         add     sp, sp, x8
@@ -148,3 +148,5 @@ pub(crate) unsafe extern "C" fn _start_secondary() -> ! {
         entry = sym axplat::call_secondary_main,
     )
 }
+
+axplat_aarch64_peripherals::resume_entry!(_start_resume, BOOT_PT_L0, PHYS_VIRT_OFFSET, enable_fp);
@@ -1,7 +1,9 @@
 //! snps,dw-apb-uart serial driver
 
 use crate::mem::phys_to_virt;
-use axplat::console::ConsoleIf;
+#[cfg(feature = "irq")]
+use axplat::console::{ByteRing, TxRing};
+use axplat::console::{ConsoleIf, Transmitter};
 use axplat::mem::{PhysAddr, pa};
 use dw_apb_uart::DW8250;
 use kspin::SpinNoIrq;
@@ -10,22 +12,93 @@ const UART_BASE: PhysAddr = pa!(crate::config::devices::UART_PADDR);
 
 static UART: SpinNoIrq<DW8250> = SpinNoIrq::new(DW8250::new(phys_to_virt(UART_BASE).as_usize()));
 
+/// Bytes received by the UART interrupt, which are read before the ones still
+/// in the UART.
+#[cfg(feature = "irq")]
+static RX_RING: ByteRing<256> = ByteRing::new();
+
+/// Bytes to be sent by the UART interrupt.
+#[cfg(feature = "irq")]
+static TX_RING: TxRing<4096> = TxRing::new();
+
+/// The transmitter of the UART, accessed by its registers.
+struct UartTx;
+
+impl UartTx {
+    const THR: usize = 0x00;
+    #[cfg(feature = "irq")]
+    const IER: usize = 0x04;
+    const LSR: usize = 0x14;
+    /// Transmitter holding register empty.
+    const THRE: u32 = 1 << 5;
+    /// Interrupt when the transmitter holding register is empty.
+    #[cfg(feature = "irq")]
+    const IER_THRE: u32 = 1 << 1;
+
+    fn reg(offset: usize) -> *mut u32 {
+        (phys_to_virt(UART_BASE).as_usize() + offset) as *mut u32
+    }
+
+    /// Enables or disables the interrupt when the transmitter is empty.
+    #[cfg(feature = "irq")]
+    fn set_irq_enabled(&mut self, enabled: bool) {
+        let ier = Self::reg(Self::IER);
+        unsafe {
+            let val = ier.read_volatile();
+            ier.write_volatile(if enabled {
+                val | Self::IER_THRE
+            } else {
+                val & !Self::IER_THRE
+            });
+        }
+    }
+}
+
+impl Transmitter for UartTx {
+    fn is_ready(&mut self) -> bool {
+        unsafe { Self::reg(Self::LSR).read_volatile() & Self::THRE != 0 }
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe { Self::reg(Self::THR).write_volatile(byte as u32) };
+    }
+}
+
 /// Writes a byte to the console.
 #[allow(dead_code)]
 pub fn putchar(c: u8) {
-    let mut uart = UART.lock();
-    match c {
-        b'\r' | b'\n' => {
-            uart.putchar(b'\r');
-            uart.putchar(b'\n');
+    #[cfg(feature = "irq")]
+    {
+        let _uart = UART.lock();
+        let buffered = match c {
+            b'\r' | b'\n' => TX_RING.write(&mut UartTx, b'\r') | TX_RING.write(&mut UartTx, b'\n'),
+            c => TX_RING.write(&mut UartTx, c),
+        };
+        if buffered {
+            UartTx.set_irq_enabled(true);
+        }
+    }
+    #[cfg(not(feature = "irq"))]
+    {
+        let mut uart = UART.lock();
+        match c {
+            b'\r' | b'\n' => {
+                uart.putchar(b'\r');
+                uart.putchar(b'\n');
+            }
+            c => uart.putchar(c),
         }
-        c => uart.putchar(c),
     }
 }
 
 /// Reads a byte from the console, or returns [`None`] if no input is available.
 fn getchar() -> Option<u8> {
-    UART.lock().getchar()
+    let mut uart = UART.lock();
+    #[cfg(feature = "irq")]
+    if let Some(c) = RX_RING.pop() {
+        return Some(c);
+    }
+    uart.getchar()
 }
 
 /// UART simply initialize
@@ -33,10 +106,33 @@ pub fn init_early() {
     UART.lock().init();
 }
 
-/// Set UART IRQ Enable
+/// Set UART IRQ Enable, and register the IRQ handler which buffers the
+/// received bytes and the output.
 #[cfg(feature = "irq")]
 pub fn init_irq() {
+    axplat::irq::register(crate::config::devices::UART_IRQ, irq_handler);
     UART.lock().set_ier(true);
+    TX_RING.enable();
+}
+
+/// UART IRQ handler, which moves the received bytes into the ring buffer, and
+/// sends the buffered output.
+#[cfg(feature = "irq")]
+fn irq_handler(_irq: usize) {
+    let mut uart = UART.lock();
+    let mut received = false;
+    while let Some(c) = uart.getchar() {
+        // Drop the byte if the buffer is full.
+        RX_RING.push(c);
+        received = true;
+    }
+    if !TX_RING.drain(&mut UartTx) {
+        UartTx.set_irq_enabled(false);
+    }
+    drop(uart);
+    if received {
+        axplat::console::notify_input();
+    }
 }
 
 struct ConsoleIfImpl;
@@ -65,6 +161,31 @@ impl ConsoleIf for ConsoleIfImpl {
         read_len
     }
 
+    /// Waits until all bytes written to the console are sent.
+    fn flush() {
+        #[cfg(feature = "irq")]
+        {
+            let _uart = UART.lock();
+            TX_RING.flush(&mut UartTx);
+            UartTx.set_irq_enabled(false);
+        }
+    }
+
+    /// Writes given bytes to the console synchronously, without taking any
+    /// lock.
+    fn write_bytes_nolock(bytes: &[u8]) {
+        #[cfg(feature = "irq")]
+        TX_RING.flush(&mut UartTx);
+        for &c in bytes {
+            if c == b'\r' || c == b'\n' {
+                UartTx.send_blocking(b'\r');
+                UartTx.send_blocking(b'\n');
+            } else {
+                UartTx.send_blocking(c);
+            }
+        }
+    }
+
     /// Returns the IRQ number for the console, if applicable.
     #[cfg(feature = "irq")]
     fn irq_number() -> Option<u32> {
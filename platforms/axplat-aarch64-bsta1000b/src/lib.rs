@@ -21,6 +21,7 @@ pub mod config {
 }
 
 mod boot;
+mod boot_info;
 mod dw_apb_uart;
 mod init;
 mod mem;
@@ -32,4 +33,4 @@ mod power;
 axplat_aarch64_peripherals::time_if_impl!(TimeIfImpl);
 
 #[cfg(feature = "irq")]
-axplat_aarch64_peripherals::irq_if_impl!(IrqIfImpl);
+axplat_aarch64_peripherals::irq_if_impl!(IrqIfImpl, gicv2, crate::config::plat::CPU_NUM);
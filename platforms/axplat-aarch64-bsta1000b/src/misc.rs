@@ -21,7 +21,7 @@ pub fn reset_qspi() {
 }
 
 /// Do CPU reset
-pub fn reset_cpu() {
+pub fn reset_cpu() -> ! {
     reset_qspi();
 
     //Data Width = 32
@@ -35,17 +35,13 @@ pub fn reset_cpu() {
 }
 
 /// reboot system
-#[allow(dead_code)]
-pub fn do_reset() {
+pub fn do_reset() -> ! {
     // wait 50 ms
     busy_wait(Duration::from_millis(50));
 
     // disable_interrupts();
 
-    reset_cpu();
-
-    // NOT REACHED
-    warn!("NOT REACHED Resetting");
+    reset_cpu()
 }
 
 /// bootmode define bit [27:26], from strap pin
@@ -1,5 +1,6 @@
 use crate::config::plat::CPU_ID_LIST;
 use axplat::mem::{PhysAddr, va, virt_to_phys};
+use axplat::power::CpuState;
 
 /// Starts the given secondary CPU with its boot stack.
 pub fn start_secondary_cpu(cpu_id: usize, stack_top: PhysAddr) {
@@ -15,3 +16,11 @@ pub fn start_secondary_cpu(cpu_id: usize, stack_top: PhysAddr) {
         stack_top.as_usize(),
     );
 }
+
+/// Returns the power state of the given CPU.
+pub fn secondary_cpu_state(cpu_id: usize) -> CpuState {
+    match CPU_ID_LIST.get(cpu_id) {
+        Some(&target_cpu) => axplat_aarch64_peripherals::psci::cpu_state(target_cpu),
+        None => CpuState::Unknown,
+    }
+}
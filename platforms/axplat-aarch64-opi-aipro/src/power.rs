@@ -1,4 +1,6 @@
-use axplat::power::PowerIf;
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
 
 struct PowerImpl;
 
@@ -22,9 +24,48 @@ impl PowerIf for PowerImpl {
         );
     }
 
+    /// Powers off the current CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        axplat_aarch64_peripherals::psci::cpu_off()
+    }
+
+    /// Returns the power state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        use crate::config::plat::CPU_ID_LIST;
+        match CPU_ID_LIST.get(cpu_id) {
+            Some(&target_cpu) => axplat_aarch64_peripherals::psci::cpu_state(target_cpu),
+            None => CpuState::Unknown,
+        }
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        use axplat::mem::{va, virt_to_phys};
+        let entry = virt_to_phys(va!(crate::boot::_start_resume as usize));
+        if !axplat_aarch64_peripherals::psci::cpu_suspend(state, entry.as_usize()) {
+            return false;
+        }
+        // The GIC CPU interface and the banked enable bit of the timer
+        // interrupt may be lost after power down.
+        #[cfg(feature = "irq")]
+        if state == SuspendState::PowerDown {
+            use axplat_aarch64_peripherals::gic;
+            gic::init_gicr(gic::current_cpu_id());
+            axplat::irq::set_enable(crate::config::devices::TIMER_IRQ, true);
+        }
+        true
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         info!("Shutting down...");
         axplat_aarch64_peripherals::psci::system_off()
     }
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> ! {
+        axplat_aarch64_peripherals::psci::system_reset(kind)
+    }
 }
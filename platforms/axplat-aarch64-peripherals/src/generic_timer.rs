@@ -2,10 +2,9 @@
 
 use aarch64_cpu::registers::{CNTFRQ_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0, CNTPCT_EL0};
 use aarch64_cpu::registers::{Readable, Writeable};
-use int_ratio::Ratio;
+use axplat::time::TickConverter;
 
-static mut CNTPCT_TO_NANOS_RATIO: Ratio = Ratio::zero();
-static mut NANOS_TO_CNTPCT_RATIO: Ratio = Ratio::zero();
+static mut TICK_CONVERTER: TickConverter = TickConverter::zero();
 
 /// Returns the current clock time in hardware ticks.
 #[inline]
@@ -16,13 +15,13 @@ pub fn current_ticks() -> u64 {
 /// Converts hardware ticks to nanoseconds.
 #[inline]
 pub fn ticks_to_nanos(ticks: u64) -> u64 {
-    unsafe { CNTPCT_TO_NANOS_RATIO.mul_trunc(ticks) }
+    unsafe { TICK_CONVERTER }.ticks_to_nanos(ticks)
 }
 
 /// Converts nanoseconds to hardware ticks.
 #[inline]
 pub fn nanos_to_ticks(nanos: u64) -> u64 {
-    unsafe { NANOS_TO_CNTPCT_RATIO.mul_trunc(nanos) }
+    unsafe { TICK_CONVERTER }.nanos_to_ticks(nanos)
 }
 
 /// Set a one-shot timer.
@@ -38,15 +37,25 @@ pub fn set_oneshot_timer(deadline_ns: u64) {
     } else {
         CNTP_TVAL_EL0.set(0);
     }
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
+}
+
+/// Set a periodic timer.
+///
+/// It is not supported, as the generic timer only has the one-shot mode.
+pub fn set_periodic_timer(_period_ns: u64) -> bool {
+    false
+}
+
+/// Cancel the pending timer on the current CPU.
+pub fn cancel_timer() {
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR);
 }
 
 /// Early stage initialization: stores the timer frequency.
 pub fn init_early() {
     let freq = CNTFRQ_EL0.get();
-    unsafe {
-        CNTPCT_TO_NANOS_RATIO = Ratio::new(axplat::time::NANOS_PER_SEC as u32, freq as u32);
-        NANOS_TO_CNTPCT_RATIO = CNTPCT_TO_NANOS_RATIO.inverse();
-    }
+    unsafe { TICK_CONVERTER = TickConverter::new(freq) };
 }
 
 /// Enable timer interrupts.
@@ -56,11 +65,14 @@ pub fn init_early() {
 pub fn enable_irqs(timer_irq_num: usize) {
     CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
     CNTP_TVAL_EL0.set(0);
-    crate::gic::set_enable(timer_irq_num, true);
+    axplat::irq::set_enable(timer_irq_num, true);
 }
 
 /// Default implementation of [`axplat::time::TimeIf`] using the generic
 /// timer.
+///
+/// The timer and alarm methods are enabled by the `irq` feature of the
+/// platform crate, which should enable the `irq` feature of `axplat`.
 #[macro_export]
 macro_rules! time_if_impl {
     ($name:ident) => {
@@ -89,13 +101,49 @@ macro_rules! time_if_impl {
                 $crate::pl031::epochoffset_nanos()
             }
 
+            /// Set epoch offset in nanoseconds, and write the new wall time
+            /// back to the RTC.
+            fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+                $crate::pl031::set_epochoffset_nanos(epochoffset_nanos)
+            }
+
             /// Set a one-shot timer.
             ///
             /// A timer interrupt will be triggered at the specified monotonic time
             /// deadline (in nanoseconds).
+            #[cfg(feature = "irq")]
             fn set_oneshot_timer(deadline_ns: u64) {
                 $crate::generic_timer::set_oneshot_timer(deadline_ns)
             }
+
+            /// Set a periodic timer.
+            ///
+            /// It is not supported by the generic timer.
+            #[cfg(feature = "irq")]
+            fn set_periodic_timer(period_ns: u64) -> bool {
+                $crate::generic_timer::set_periodic_timer(period_ns)
+            }
+
+            /// Cancel the pending timer on the current CPU.
+            #[cfg(feature = "irq")]
+            fn cancel_timer() {
+                $crate::generic_timer::cancel_timer()
+            }
+
+            /// Set an RTC alarm at the specified wall time (in nanoseconds
+            /// since epoch), by the PL031 match register.
+            ///
+            /// It returns `false` if the RTC interrupt is not registered.
+            #[cfg(feature = "irq")]
+            fn set_rtc_alarm(wall_time_ns: u64) -> bool {
+                $crate::pl031::set_alarm(wall_time_ns)
+            }
+
+            /// Cancel the pending RTC alarm, if any.
+            #[cfg(feature = "irq")]
+            fn cancel_rtc_alarm() {
+                $crate::pl031::cancel_alarm()
+            }
         }
     };
 }
@@ -1,68 +1,61 @@
-//! GICv3 中断控制器
+//! ARM Generic Interrupt Controller version 3 (GICv3).
 
-use arm_gic_driver::v3::Gic;
-use kspin::SpinNoIrq;
 use alloc::boxed::Box;
 use core::ptr::NonNull;
-use aarch64_cpu::registers::*;
-use axplat::irq::{IrqIf, HandlerTable, IrqHandler};
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
 
+use aarch64_cpu::registers::*;
+use arm_gic_driver::v3::Gic;
+#[cfg(feature = "alloc")]
+use axplat::irq::BoxedIrqHandler;
+use axplat::irq::{
+    CpuMask, HandlerTable, IpiTarget, IrqContextHandler, IrqHandler, IrqStats, MsiMessage,
+    Polarity, SharedIrqHandler, TriggerMode,
+};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
 
-const MAX_IRQ_COUNT: usize = 1024;
+/// The maximum number of IRQs.
+pub const MAX_IRQ_COUNT: usize = 1024;
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
 static GICD: SpinNoIrq<Option<Gic>> = SpinNoIrq::new(None);
 static GICR: SpinNoIrq<Option<Box<dyn arm_gic_driver::local::Interface>>> = SpinNoIrq::new(None);
 
-pub struct IrqIfImpl;
-impl IrqIf for IrqIfImpl {
-    fn set_enable(irq: usize, enabled: bool) {
-        warn!("call GIC set enable: {} {}, but it is not implemented", irq, enabled);
-    }
+/// Base virtual address of the GIC distributor.
+static GICD_BASE: LazyInit<usize> = LazyInit::new();
 
-    fn register(irq: usize, handler: IrqHandler) -> bool {
-        if IRQ_HANDLER_TABLE.register_handler(irq, handler) {
-            Self::set_enable(irq, true);
-            true
-        } else {
-            false
-        }
-    }
+/// The implemented bits of the priority fields.
+static PRIORITY_BITS: AtomicU8 = AtomicU8::new(0);
 
-    fn unregister(irq: usize) -> Option<IrqHandler> {
-        Self::set_enable(irq, false);
-        IRQ_HANDLER_TABLE.unregister_handler(irq)
-    }
+/// The affinities (Aff3.Aff2.Aff1.Aff0) of the CPUs.
+static CPU_IDS: CpuIdMap = CpuIdMap::new();
 
-    fn handle(_unused: usize) {
-        let Some(irq) =  GICR.lock().as_mut().unwrap().ack() else {
-            return;
-        };
-        let irq_num: usize = irq.into();
-        // trace!("IRQ {}", irq_num);
-        if !IRQ_HANDLER_TABLE.handle(irq_num as _) {
-            warn!("Unhandled IRQ {irq_num}");
-        }
+/// The first SPI (Shared Peripheral Interrupt) number.
+const SPI_BASE: usize = 32;
 
-        GICR.lock().as_mut().unwrap().eoi(irq);
-        if GICR.lock().as_mut().unwrap().get_eoi_mode() {
-            GICR.lock().as_mut().unwrap().dir(irq);
-        }
-    }
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ICFGR: usize = 0xc00;
+const GICD_IROUTER: usize = 0x6000;
+const GICD_IROUTER_IRM: u64 = 1 << 31;
 
-    fn send_ipi(irq_num: usize, target: axplat::irq::IpiTarget) {
-        todo!("send ipi");
-    }
-}
+/// Sends the SGI to all CPUs except the current one.
+const ICC_SGI1R_IRM: u64 = 1 << 40;
 
-pub(crate) fn init(gicd_base: axplat::mem::VirtAddr, gicr_base: axplat::mem::VirtAddr) {
- let mut gicd = arm_gic_driver::v3::Gic::new(
+/// Initializes the GIC distributor, and the redistributor of the current CPU.
+pub fn init_gic(cpu_id: usize, gicd_base: axplat::mem::VirtAddr, gicr_base: axplat::mem::VirtAddr) {
+    GICD_BASE.init_once(gicd_base.as_usize());
+    let mut gicd = arm_gic_driver::v3::Gic::new(
         NonNull::new(gicd_base.as_mut_ptr()).unwrap(),
         NonNull::new(gicr_base.as_mut_ptr()).unwrap(),
     );
 
-     debug!("Initializing GICD at {:#x}", gicd_base);
+    debug!("Initializing GICD at {:#x}", gicd_base);
     gicd.open().unwrap();
+    PRIORITY_BITS.store(
+        implemented_priority_bits(gicd_reg(GICD_IPRIORITYR + SPI_BASE)),
+        Ordering::Relaxed,
+    );
 
     info!(
         "Initializing GICR for BSP. Global GICR base at {:#x}",
@@ -73,23 +66,37 @@ pub(crate) fn init(gicd_base: axplat::mem::VirtAddr, gicr_base: axplat::mem::Vir
 
     GICD.lock().replace(gicd);
     GICR.lock().replace(interface);
-    info!("GIC initialized {}",current_cpu());
+    CPU_IDS.record(cpu_id, current_affinity());
+    info!("GIC initialized {:#x}", current_affinity());
 }
 
-#[allow(dead_code)]
-pub(crate) fn init_current_cpu() {
-    debug!("Initializing GICR for current CPU {}",current_cpu());
+/// Initializes the redistributor of the current CPU, whose logical ID is
+/// `cpu_id`.
+///
+/// It must be called on secondary CPUs after [`init_gic`], and again when a
+/// CPU resumes from a power-down state.
+pub fn init_gicr(cpu_id: usize) {
+    debug!(
+        "Initializing GICR for current CPU {:#x}",
+        current_affinity()
+    );
     let mut interface = GICD.lock().as_mut().unwrap().cpu_local().unwrap();
     interface.open().unwrap();
     GICR.lock().replace(interface);
-    debug!(  "Initialized GICR for current CPU {}",current_cpu());
+    CPU_IDS.record(cpu_id, current_affinity());
+    debug!("Initialized GICR for current CPU {:#x}", current_affinity());
 }
 
-fn current_cpu() -> usize {
-    MPIDR_EL1.get() as usize & 0xffffff
+/// Returns the affinity of the current CPU, in the format of
+/// `GICR_TYPER[63:32]`, i.e., Aff3.Aff2.Aff1.Aff0.
+fn current_affinity() -> u64 {
+    // `MPIDR_EL1` is Aff3[39:32], Aff2[23:16], Aff1[15:8], Aff0[7:0].
+    let mpidr = MPIDR_EL1.get();
+    (mpidr & 0xff_ffff) | (((mpidr >> 32) & 0xff) << 24)
 }
 
-pub(crate) fn set_enable(irq_num: usize, enabled: bool) {
+/// Enables or disables the given IRQ.
+pub fn set_enable(irq_num: usize, enabled: bool) {
     use arm_gic_driver::local::cap::ConfigLocalIrq;
 
     let mut gicd = GICD.lock();
@@ -112,4 +119,473 @@ pub(crate) fn set_enable(irq_num: usize, enabled: bool) {
             d.irq_disable(irq_num.into()).unwrap();
         }
     }
-}
\ No newline at end of file
+}
+
+fn gicd_reg<T>(offset: usize) -> *mut T {
+    (*GICD_BASE + offset) as *mut T
+}
+
+/// Returns the logical ID of the current CPU, which is recorded by
+/// [`init_gic`] or [`init_gicr`].
+///
+/// It returns 0 before the CPU is recorded.
+pub fn current_cpu_id() -> usize {
+    CPU_IDS.logical_id(current_affinity()).unwrap_or(0)
+}
+
+/// The hardware IDs of the CPUs, indexed by the logical CPU IDs given by the
+/// kernel.
+///
+/// Each CPU records its own hardware ID when its CPU interface or
+/// redistributor is initialized, as the hardware IDs may not be in the order
+/// of the logical IDs.
+pub(crate) struct CpuIdMap([AtomicU64; CpuMask::MAX_CPUS]);
+
+impl CpuIdMap {
+    const UNKNOWN: u64 = u64::MAX;
+
+    pub(crate) const fn new() -> Self {
+        Self([const { AtomicU64::new(Self::UNKNOWN) }; CpuMask::MAX_CPUS])
+    }
+
+    /// Records the hardware ID of the CPU with the given logical ID.
+    pub(crate) fn record(&self, cpu_id: usize, hw_id: u64) {
+        match self.0.get(cpu_id) {
+            Some(id) => id.store(hw_id, Ordering::Release),
+            None => warn!("GIC can not record CPU {}", cpu_id),
+        }
+    }
+
+    /// Returns the hardware ID of the CPU with the given logical ID, or
+    /// `None` if it is not recorded.
+    pub(crate) fn hw_id(&self, cpu_id: usize) -> Option<u64> {
+        let id = self.0.get(cpu_id)?.load(Ordering::Acquire);
+        (id != Self::UNKNOWN).then_some(id)
+    }
+
+    /// Returns the logical ID of the CPU with the given hardware ID, or
+    /// `None` if it is not recorded.
+    pub(crate) fn logical_id(&self, hw_id: u64) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|id| id.load(Ordering::Acquire) == hw_id)
+    }
+
+    /// Returns the mask of the recorded CPUs.
+    pub(crate) fn recorded(&self) -> CpuMask {
+        let mut mask = CpuMask::new();
+        for cpu_id in 0..CpuMask::MAX_CPUS {
+            if self.hw_id(cpu_id).is_some() {
+                mask.insert(cpu_id);
+            }
+        }
+        mask
+    }
+}
+
+/// Returns the implemented bits of a priority field, by writing all ones to
+/// it and reading it back.
+///
+/// The priority field is restored afterwards.
+pub(crate) fn implemented_priority_bits(reg: *mut u8) -> u8 {
+    unsafe {
+        let old = reg.read_volatile();
+        reg.write_volatile(u8::MAX);
+        let bits = reg.read_volatile();
+        reg.write_volatile(old);
+        bits
+    }
+}
+
+/// Converts a priority of [`IrqIf::set_priority`](axplat::irq::IrqIf), where a
+/// larger value means a higher priority, to the value of a GIC priority field
+/// with the given implemented bits, where a smaller value means a higher
+/// priority.
+///
+/// The priority is scaled to the implemented levels except the lowest one, as
+/// an interrupt of the lowest level is never signalled, even if the priority
+/// mask register allows all priorities.
+pub(crate) const fn gic_priority(priority: u8, bits: u8) -> u8 {
+    // The value of the second lowest level, e.g., 0xe0 for 4 bits.
+    let lowest = bits.wrapping_sub(bits & bits.wrapping_neg()) as u32;
+    ((u8::MAX - priority) as u32 * lowest / u8::MAX as u32) as u8 & bits
+}
+
+/// Sets the trigger mode of the given SPI.
+///
+/// GIC only supports active-high level-triggered and rising edge-triggered
+/// interrupts. The configuration of SGIs and PPIs is not supported.
+pub fn set_trigger(irq_num: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) || polarity != Polarity::High {
+        return false;
+    }
+    trace!("GICD set trigger: {} {:?}", irq_num, mode);
+    let reg = gicd_reg::<u32>(GICD_ICFGR + irq_num / 16 * 4);
+    let bit = 1 << ((irq_num % 16) * 2 + 1);
+    let _guard = GICD.lock();
+    unsafe {
+        let cfg = reg.read_volatile();
+        reg.write_volatile(match mode {
+            TriggerMode::Edge => cfg | bit,
+            TriggerMode::Level => cfg & !bit,
+        });
+    }
+    true
+}
+
+/// Sets the priority of the given SPI.
+///
+/// `priority` is scaled to the priority levels implemented by the GIC by
+/// [`gic_priority`].
+pub fn set_priority(irq_num: usize, priority: u8) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) {
+        return false;
+    }
+    let value = gic_priority(priority, PRIORITY_BITS.load(Ordering::Relaxed));
+    trace!("GICD set priority: {} {:#x}", irq_num, value);
+    let _guard = GICD.lock();
+    unsafe { gicd_reg::<u8>(GICD_IPRIORITYR + irq_num).write_volatile(value) };
+    true
+}
+
+/// Routes the given SPI to the CPUs in `cpu_mask`.
+///
+/// GICv3 can route an SPI to either a single CPU or any CPU. So the SPI is
+/// routed to any CPU if `cpu_mask` contains all CPUs, or to the first CPU in
+/// `cpu_mask` otherwise. The logical CPU IDs are assumed to be in the order of
+/// the redistributors.
+pub fn set_affinity(irq_num: usize, cpu_mask: CpuMask) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) {
+        return false;
+    }
+    let route = if cpu_mask == CPU_IDS.recorded() {
+        GICD_IROUTER_IRM
+    } else {
+        let Some(aff) = cpu_mask.first().and_then(|cpu_id| CPU_IDS.hw_id(cpu_id)) else {
+            return false;
+        };
+        // `GICD_IROUTER` has Aff3 in bits [39:32] and Aff2.Aff1.Aff0 in bits
+        // [23:0].
+        ((aff >> 24) << 32) | (aff & 0xff_ffff)
+    };
+    trace!("GICD set affinity: {} {:#x}", irq_num, route);
+    let _guard = GICD.lock();
+    unsafe { gicd_reg::<u64>(GICD_IROUTER + irq_num * 8).write_volatile(route) };
+    true
+}
+
+/// Registers an IRQ handler for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
+    if IRQ_HANDLER_TABLE.register_handler(irq_num, handler) {
+        trace!("register handler IRQ {}", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Registers an IRQ handler with a context pointer for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+pub fn register_handler_with_context(
+    irq_num: usize,
+    handler: IrqContextHandler,
+    context: *mut (),
+) -> bool {
+    if IRQ_HANDLER_TABLE.register_handler_with_context(irq_num, handler, context) {
+        trace!("register handler IRQ {} with context", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Registers a boxed closure as the IRQ handler for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+#[cfg(feature = "alloc")]
+pub fn register_boxed_handler(irq_num: usize, handler: BoxedIrqHandler) -> bool {
+    if IRQ_HANDLER_TABLE.register_boxed_handler(irq_num, handler) {
+        trace!("register boxed handler IRQ {}", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Unregisters the IRQ handler for the given IRQ.
+///
+/// It also disables the IRQ if the unregistration succeeds. It returns the
+/// existing handler if it is registered, `None` otherwise.
+pub fn unregister_handler(irq_num: usize) -> Option<IrqHandler> {
+    trace!("unregister handler IRQ {}", irq_num);
+    set_enable(irq_num, false);
+    IRQ_HANDLER_TABLE.unregister_handler(irq_num)
+}
+
+/// Adds a handler to the shared IRQ line.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+pub fn register_shared_handler(
+    irq_num: usize,
+    handler: SharedIrqHandler,
+    context: *mut (),
+) -> bool {
+    if IRQ_HANDLER_TABLE.register_shared_handler(irq_num, handler, context) {
+        trace!("register shared handler IRQ {}", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Removes the handler registered with the given context pointer from the
+/// shared IRQ line.
+///
+/// It also disables the IRQ if no handlers remain. It returns `false` if the
+/// handler is not found.
+pub fn unregister_shared_handler(irq_num: usize, context: *mut ()) -> bool {
+    trace!("unregister shared handler IRQ {}", irq_num);
+    if !IRQ_HANDLER_TABLE.unregister_shared_handler(irq_num, context) {
+        return false;
+    }
+    if !IRQ_HANDLER_TABLE.has_handler(irq_num) {
+        set_enable(irq_num, false);
+    }
+    true
+}
+
+/// Handles the IRQ.
+///
+/// It is called by the common interrupt handler. It should look up in the
+/// IRQ handler table and calls the corresponding handler. If necessary, it
+/// also acknowledges the interrupt controller after handling.
+///
+/// The interrupts are recorded in `stats`, which is defined by the platform
+/// with its number of CPUs.
+pub fn handle_irq<const C: usize>(stats: &IrqStats<MAX_IRQ_COUNT, C>) {
+    let Some(irq) = GICR.lock().as_mut().unwrap().ack() else {
+        stats.record_spurious(current_cpu_id());
+        return;
+    };
+    let irq_num: usize = irq.into();
+    // Cross-CPU calls are run before the registered IPI handler.
+    let called =
+        axplat::ipi::ipi_irq() == Some(irq_num) && axplat::ipi::handle_calls(current_cpu_id());
+    // Expired software timers are run before the registered timer handler.
+    let timed = axplat::time::timer_irq() == Some(irq_num)
+        && axplat::time::handle_timer_irq(current_cpu_id());
+    let handled = IRQ_HANDLER_TABLE.handle(irq_num as _) || called || timed;
+    stats.record(irq_num, current_cpu_id(), handled);
+    if !handled {
+        warn!("Unhandled IRQ {irq_num}");
+    }
+
+    let mut gicr = GICR.lock();
+    let gicr = gicr.as_mut().unwrap();
+    gicr.eoi(irq);
+    if gicr.get_eoi_mode() {
+        gicr.dir(irq);
+    }
+}
+
+/// Allocates an MSI vector that is delivered to the given CPU.
+///
+/// MSIs of GICv3 are LPIs translated by the ITS, which is not supported yet,
+/// so it always returns `None`.
+pub fn alloc_msi(_cpu_id: usize) -> Option<(usize, MsiMessage)> {
+    None
+}
+
+/// Frees an MSI vector allocated by [`alloc_msi`].
+pub fn free_msi(_irq_num: usize) -> bool {
+    false
+}
+
+/// Writes `ICC_SGI1R_EL1` to generate an SGI.
+fn write_sgi1r(value: u64) {
+    // `ICC_SGI1R_EL1` is encoded as S3_0_C12_C11_5.
+    unsafe { core::arch::asm!("msr S3_0_C12_C11_5, {}", "isb", in(reg) value) };
+}
+
+/// Sends the SGI to the CPUs in `cpu_mask`.
+///
+/// The CPUs with the same Aff3.Aff2.Aff1 are targeted by a single write of
+/// `ICC_SGI1R_EL1`, whose target list is a bitmap of Aff0. CPUs with Aff0
+/// greater than 15 can not be targeted.
+fn send_sgi(irq_num: usize, cpu_mask: CpuMask) {
+    let intid = (irq_num as u64 & 0xf) << 24;
+    let mut cluster = None;
+    let mut targets = 0;
+    for cpu_id in cpu_mask.iter() {
+        let Some(aff) = CPU_IDS.hw_id(cpu_id) else {
+            warn!("GIC SGI can not target unknown CPU {}", cpu_id);
+            continue;
+        };
+        if aff & 0xff >= 16 {
+            warn!("GIC SGI can not target CPU {}", cpu_id);
+            continue;
+        }
+        // Aff1 is in bits [23:16], Aff2 in [39:32] and Aff3 in [55:48].
+        let route = (((aff >> 8) & 0xff) << 16)
+            | (((aff >> 16) & 0xff) << 32)
+            | (((aff >> 24) & 0xff) << 48);
+        if cluster != Some(route) {
+            if let Some(prev) = cluster {
+                write_sgi1r(prev | intid | targets);
+            }
+            cluster = Some(route);
+            targets = 0;
+        }
+        targets |= 1 << (aff & 0xff);
+    }
+    if let Some(route) = cluster {
+        write_sgi1r(route | intid | targets);
+    }
+}
+
+/// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
+pub fn send_ipi(irq_num: usize, target: IpiTarget) {
+    match target {
+        IpiTarget::AllExceptCurrent { .. } => {
+            write_sgi1r(ICC_SGI1R_IRM | ((irq_num as u64 & 0xf) << 24))
+        }
+        _ => send_sgi(irq_num, target.cpu_mask()),
+    }
+}
+
+/// Default implementation of [`axplat::irq::IrqIf`] using the GIC.
+///
+/// `$gic` is the driver module of the GIC, i.e., [`gic`](crate::gic) for
+/// GICv3, or [`gicv2`](crate::gicv2) for GICv2. `$cpu_num` is the number of
+/// CPUs of the platform.
+///
+/// It also defines the interrupt statistics `IRQ_STATS`, which are recorded
+/// only if the `irq-stats` feature of `axplat` is enabled.
+#[macro_export]
+macro_rules! irq_if_impl {
+    ($name:ident, $gic:ident, $cpu_num:expr) => {
+        // CPU masks of IRQ affinities and IPIs can hold at most `CpuMask::MAX_CPUS` CPUs.
+        const _: () = assert!($cpu_num <= axplat::irq::CpuMask::MAX_CPUS);
+
+        static IRQ_STATS: axplat::irq::IrqStats<{ $crate::$gic::MAX_IRQ_COUNT }, { $cpu_num }> =
+            axplat::irq::IrqStats::new();
+
+        struct $name;
+
+        #[impl_plat_interface]
+        impl axplat::irq::IrqIf for $name {
+            /// Enables or disables the given IRQ.
+            fn set_enable(irq: usize, enabled: bool) {
+                $crate::$gic::set_enable(irq, enabled);
+            }
+
+            /// Sets the trigger mode and polarity of the given IRQ.
+            fn set_trigger(
+                irq: usize,
+                mode: axplat::irq::TriggerMode,
+                polarity: axplat::irq::Polarity,
+            ) -> bool {
+                $crate::$gic::set_trigger(irq, mode, polarity)
+            }
+
+            /// Sets the priority of the given IRQ.
+            fn set_priority(irq: usize, priority: u8) -> bool {
+                $crate::$gic::set_priority(irq, priority)
+            }
+
+            /// Sets the CPUs that the given IRQ can be delivered to.
+            fn set_affinity(irq: usize, cpu_mask: axplat::irq::CpuMask) -> bool {
+                $crate::$gic::set_affinity(irq, cpu_mask)
+            }
+
+            /// Registers an IRQ handler for the given IRQ.
+            ///
+            /// It also enables the IRQ if the registration succeeds. It returns `false`
+            /// if the registration failed.
+            fn register(irq: usize, handler: axplat::irq::IrqHandler) -> bool {
+                $crate::$gic::register_handler(irq, handler)
+            }
+
+            /// Registers an IRQ handler with a context pointer for the given IRQ.
+            fn register_with_context(
+                irq: usize,
+                handler: axplat::irq::IrqContextHandler,
+                context: *mut (),
+            ) -> bool {
+                $crate::$gic::register_handler_with_context(irq, handler, context)
+            }
+
+            /// Registers a boxed closure as the IRQ handler for the given IRQ.
+            #[cfg(feature = "alloc")]
+            fn register_boxed(irq: usize, handler: axplat::irq::BoxedIrqHandler) -> bool {
+                $crate::$gic::register_boxed_handler(irq, handler)
+            }
+
+            /// Unregisters the IRQ handler for the given IRQ.
+            ///
+            /// It also disables the IRQ if the unregistration succeeds. It returns the
+            /// existing handler if it is a plain handler, `None` otherwise.
+            fn unregister(irq: usize) -> Option<axplat::irq::IrqHandler> {
+                $crate::$gic::unregister_handler(irq)
+            }
+
+            /// Adds a handler to the shared IRQ line.
+            fn register_shared(
+                irq: usize,
+                handler: axplat::irq::SharedIrqHandler,
+                context: *mut (),
+            ) -> bool {
+                $crate::$gic::register_shared_handler(irq, handler, context)
+            }
+
+            /// Removes the handler registered with the given context pointer from the
+            /// shared IRQ line.
+            fn unregister_shared(irq: usize, context: *mut ()) -> bool {
+                $crate::$gic::unregister_shared_handler(irq, context)
+            }
+
+            /// Allocates an MSI vector that is delivered to the given CPU.
+            fn alloc_msi(cpu_id: usize) -> Option<(usize, axplat::irq::MsiMessage)> {
+                $crate::$gic::alloc_msi(cpu_id)
+            }
+
+            /// Frees an MSI vector allocated by [`alloc_msi`](axplat::irq::IrqIf::alloc_msi).
+            fn free_msi(irq: usize) -> bool {
+                $crate::$gic::free_msi(irq)
+            }
+
+            /// Handles the IRQ.
+            ///
+            /// It is called by the common interrupt handler. It should look up in the
+            /// IRQ handler table and calls the corresponding handler. If necessary, it
+            /// also acknowledges the interrupt controller after handling.
+            fn handle(_irq: usize) {
+                $crate::$gic::handle_irq(&IRQ_STATS)
+            }
+
+            /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
+            fn send_ipi(irq_num: usize, target: axplat::irq::IpiTarget) {
+                $crate::$gic::send_ipi(irq_num, target);
+            }
+
+            /// Takes a snapshot of the interrupt statistics.
+            #[cfg(feature = "irq-stats")]
+            fn irq_stats(f: &mut dyn FnMut(usize, usize, axplat::irq::IrqCounters)) {
+                IRQ_STATS.for_each(f);
+            }
+
+            /// Returns the number of spurious interrupts on the given CPU.
+            #[cfg(feature = "irq-stats")]
+            fn spurious_irq_count(cpu_id: usize) -> u64 {
+                IRQ_STATS.spurious(cpu_id)
+            }
+        }
+    };
+}
@@ -1,19 +1,53 @@
-//! ARM Generic Interrupt Controller (GIC).
+//! ARM Generic Interrupt Controller version 2 (GICv2).
 
 use arm_gic_driver::v2::{Ack, Gic, IntId, SGITarget, TargetList, TrapOp, VirtAddr};
-use axplat::irq::{HandlerTable, IpiTarget, IrqHandler};
+#[cfg(feature = "alloc")]
+use axplat::irq::BoxedIrqHandler;
+use axplat::irq::{
+    CpuMask, HandlerTable, IpiTarget, IrqContextHandler, IrqHandler, IrqStats, MsiMessage,
+    MsiVectorAllocator, Polarity, SharedIrqHandler, TriggerMode,
+};
+use axplat::mem::PhysAddr;
+use core::sync::atomic::{AtomicU8, Ordering};
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 
+use crate::gic::CpuIdMap;
+
 /// The maximum number of IRQs.
-const MAX_IRQ_COUNT: usize = 1024;
+pub const MAX_IRQ_COUNT: usize = 1024;
+
+/// The first SPI (Shared Peripheral Interrupt) number.
+const SPI_BASE: usize = 32;
+
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ITARGETSR: usize = 0x800;
+const GICD_ICFGR: usize = 0xc00;
+
+const GICV2M_MSI_TYPER: usize = 0x008;
+const GICV2M_MSI_SETSPI_NS: usize = 0x040;
 
 static GIC: LazyInit<SpinNoIrq<Gic>> = LazyInit::new();
 
+/// Base virtual address of the GIC distributor.
+static GICD_BASE: LazyInit<usize> = LazyInit::new();
+
 static TRAP_OP: LazyInit<TrapOp> = LazyInit::new();
 
+/// The implemented bits of the priority fields.
+static PRIORITY_BITS: AtomicU8 = AtomicU8::new(0);
+
+/// The CPU interface numbers of the CPUs.
+static CPU_IDS: CpuIdMap = CpuIdMap::new();
+
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+/// The GICv2m MSI frame, with format (`paddr`, first SPI, number of SPIs).
+static GICV2M: LazyInit<(PhysAddr, usize, usize)> = LazyInit::new();
+
+/// SPIs of the GICv2m MSI frame, indexed from its first SPI.
+static MSI_VECTORS: MsiVectorAllocator<{ MAX_IRQ_COUNT - SPI_BASE }> = MsiVectorAllocator::new();
+
 /// Enables or disables the given IRQ.
 pub fn set_enable(irq_num: usize, enabled: bool) {
     trace!("GIC set enable: {} {}", irq_num, enabled);
@@ -21,6 +55,84 @@ pub fn set_enable(irq_num: usize, enabled: bool) {
     GIC.lock().set_irq_enable(intid, enabled);
 }
 
+fn gicd_reg<T>(offset: usize) -> *mut T {
+    (*GICD_BASE + offset) as *mut T
+}
+
+/// Returns the logical ID of the current CPU, which is recorded by
+/// [`init_gicc`].
+///
+/// It returns 0 before the CPU is recorded.
+pub fn current_cpu_id() -> usize {
+    CPU_IDS.logical_id(current_interface()).unwrap_or(0)
+}
+
+/// Returns the CPU interface number of the current CPU.
+fn current_interface() -> u64 {
+    // `GICD_ITARGETSR0` is banked, and its first byte has only the bit of the
+    // current CPU set. It reads as zero on uniprocessor systems.
+    let targets = unsafe { gicd_reg::<u8>(GICD_ITARGETSR).read_volatile() };
+    if targets == 0 {
+        0
+    } else {
+        targets.trailing_zeros() as u64
+    }
+}
+
+/// Sets the trigger mode of the given IRQ.
+///
+/// GIC only supports active-high level-triggered and rising edge-triggered
+/// interrupts. The trigger mode of SGIs can not be changed, and that of PPIs is
+/// implementation defined.
+pub fn set_trigger(irq_num: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+    if irq_num < 16 || irq_num >= MAX_IRQ_COUNT || polarity != Polarity::High {
+        return false;
+    }
+    trace!("GIC set trigger: {} {:?}", irq_num, mode);
+    let reg = gicd_reg::<u32>(GICD_ICFGR + irq_num / 16 * 4);
+    let bit = 1 << ((irq_num % 16) * 2 + 1);
+    let _guard = GIC.lock();
+    unsafe {
+        let cfg = reg.read_volatile();
+        reg.write_volatile(match mode {
+            TriggerMode::Edge => cfg | bit,
+            TriggerMode::Level => cfg & !bit,
+        });
+    }
+    true
+}
+
+/// Sets the priority of the given IRQ.
+///
+/// `priority` is scaled to the priority levels implemented by the GIC by
+/// [`gic_priority`](crate::gic::gic_priority).
+pub fn set_priority(irq_num: usize, priority: u8) -> bool {
+    if irq_num >= MAX_IRQ_COUNT {
+        return false;
+    }
+    let value = crate::gic::gic_priority(priority, PRIORITY_BITS.load(Ordering::Relaxed));
+    trace!("GIC set priority: {} {:#x}", irq_num, value);
+    let _guard = GIC.lock();
+    unsafe { gicd_reg::<u8>(GICD_IPRIORITYR + irq_num).write_volatile(value) };
+    true
+}
+
+/// Sets the target CPUs of the given SPI.
+///
+/// Returns `false` if any of the CPUs has not initialized its CPU interface.
+pub fn set_affinity(irq_num: usize, cpu_mask: CpuMask) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) || cpu_mask.is_empty() {
+        return false;
+    }
+    let Some(targets) = target_list(cpu_mask) else {
+        return false;
+    };
+    trace!("GIC set affinity: {} {:#x}", irq_num, targets);
+    let _guard = GIC.lock();
+    unsafe { gicd_reg::<u8>(GICD_ITARGETSR + irq_num).write_volatile(targets) };
+    true
+}
+
 /// Registers an IRQ handler for the given IRQ.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false`
@@ -34,6 +146,37 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
     false
 }
 
+/// Registers an IRQ handler with a context pointer for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+pub fn register_handler_with_context(
+    irq_num: usize,
+    handler: IrqContextHandler,
+    context: *mut (),
+) -> bool {
+    if IRQ_HANDLER_TABLE.register_handler_with_context(irq_num, handler, context) {
+        trace!("register handler IRQ {} with context", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Registers a boxed closure as the IRQ handler for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+#[cfg(feature = "alloc")]
+pub fn register_boxed_handler(irq_num: usize, handler: BoxedIrqHandler) -> bool {
+    if IRQ_HANDLER_TABLE.register_boxed_handler(irq_num, handler) {
+        trace!("register boxed handler IRQ {}", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
 /// Unregisters the IRQ handler for the given IRQ.
 ///
 /// It also disables the IRQ if the unregistration succeeds. It returns the
@@ -44,14 +187,51 @@ pub fn unregister_handler(irq_num: usize) -> Option<IrqHandler> {
     IRQ_HANDLER_TABLE.unregister_handler(irq_num)
 }
 
+/// Adds a handler to the shared IRQ line.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false`
+/// if the registration failed.
+pub fn register_shared_handler(
+    irq_num: usize,
+    handler: SharedIrqHandler,
+    context: *mut (),
+) -> bool {
+    if IRQ_HANDLER_TABLE.register_shared_handler(irq_num, handler, context) {
+        trace!("register shared handler IRQ {}", irq_num);
+        set_enable(irq_num, true);
+        return true;
+    }
+    false
+}
+
+/// Removes the handler registered with the given context pointer from the
+/// shared IRQ line.
+///
+/// It also disables the IRQ if no handlers remain. It returns `false` if the
+/// handler is not found.
+pub fn unregister_shared_handler(irq_num: usize, context: *mut ()) -> bool {
+    trace!("unregister shared handler IRQ {}", irq_num);
+    if !IRQ_HANDLER_TABLE.unregister_shared_handler(irq_num, context) {
+        return false;
+    }
+    if !IRQ_HANDLER_TABLE.has_handler(irq_num) {
+        set_enable(irq_num, false);
+    }
+    true
+}
+
 /// Handles the IRQ.
 ///
 /// It is called by the common interrupt handler. It should look up in the
 /// IRQ handler table and calls the corresponding handler. If necessary, it
 /// also acknowledges the interrupt controller after handling.
-pub fn handle_irq(_unused: usize) {
+///
+/// The interrupts are recorded in `stats`, which is defined by the platform
+/// with its number of CPUs.
+pub fn handle_irq<const C: usize>(stats: &IrqStats<MAX_IRQ_COUNT, C>) {
     let ack = TRAP_OP.ack();
     if ack.is_special() {
+        stats.record_spurious(current_cpu_id());
         return;
     }
 
@@ -62,8 +242,17 @@ pub fn handle_irq(_unused: usize) {
         Ack::SGI { intid, cpu_id: _ } => intid,
     };
 
-    if !IRQ_HANDLER_TABLE.handle(irq_num.to_u32() as _) {
-        warn!("Unhandled IRQ {:?}", irq_num);
+    let irq_num = irq_num.to_u32() as usize;
+    // Cross-CPU calls are run before the registered IPI handler.
+    let called =
+        axplat::ipi::ipi_irq() == Some(irq_num) && axplat::ipi::handle_calls(current_cpu_id());
+    // Expired software timers are run before the registered timer handler.
+    let timed = axplat::time::timer_irq() == Some(irq_num)
+        && axplat::time::handle_timer_irq(current_cpu_id());
+    let handled = IRQ_HANDLER_TABLE.handle(irq_num) || called || timed;
+    stats.record(irq_num, current_cpu_id(), handled);
+    if !handled {
+        warn!("Unhandled IRQ {irq_num}");
     }
 
     if !ack.is_special() {
@@ -77,41 +266,96 @@ pub fn handle_irq(_unused: usize) {
 /// Initializes GIC
 pub fn init_gic(gicd_base: axplat::mem::VirtAddr, gicc_base: axplat::mem::VirtAddr) {
     info!("Initialize GICv2...");
+    GICD_BASE.init_once(gicd_base.as_usize());
     let gicd_base = VirtAddr::new(gicd_base.into());
     let gicc_base = VirtAddr::new(gicc_base.into());
 
     let mut gic = unsafe { Gic::new(gicd_base, gicc_base, None) };
     gic.init();
+    PRIORITY_BITS.store(
+        crate::gic::implemented_priority_bits(gicd_reg(GICD_IPRIORITYR + SPI_BASE)),
+        Ordering::Relaxed,
+    );
 
     GIC.init_once(SpinNoIrq::new(gic));
     let cpu = GIC.lock().cpu_interface();
     TRAP_OP.init_once(cpu.trap_operations());
 }
 
-/// Initializes GICC (for all CPUs).
+/// Initializes the GICv2m MSI frame, which turns MSI writes into SPIs.
 ///
-/// It must be called after [`init_gic`].
-pub fn init_gicc() {
+/// `base` and `paddr` are the virtual and physical addresses of the frame.
+/// MSIs are not supported if it is not called.
+pub fn init_gicv2m(base: axplat::mem::VirtAddr, paddr: PhysAddr) {
+    let typer = unsafe { ((base.as_usize() + GICV2M_MSI_TYPER) as *const u32).read_volatile() };
+    // Bits [25:16] are the first SPI, and bits [9:0] are the number of SPIs.
+    let spi_base = ((typer >> 16) & 0x3ff) as usize;
+    let spi_count = (typer & 0x3ff) as usize;
+    info!(
+        "Initialize GICv2m: SPIs {}..{}",
+        spi_base,
+        spi_base + spi_count
+    );
+    GICV2M.init_once((paddr, spi_base, spi_count));
+}
+
+/// Allocates an SPI of the GICv2m frame as an MSI vector, and routes it to the
+/// given CPU.
+///
+/// The message writes the SPI number to `MSI_SETSPI_NS` of the frame. Returns
+/// `None` if GICv2m is not initialized, the CPU can not be targeted, or no
+/// SPIs are available.
+pub fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+    let &(paddr, spi_base, spi_count) = GICV2M.get()?;
+    CPU_IDS.hw_id(cpu_id)?;
+    let irq_num = spi_base + MSI_VECTORS.alloc(spi_count)?;
+    set_trigger(irq_num, TriggerMode::Edge, Polarity::High);
+    set_affinity(irq_num, CpuMask::one(cpu_id));
+    let message = MsiMessage {
+        address: paddr + GICV2M_MSI_SETSPI_NS,
+        data: irq_num as u32,
+    };
+    Some((irq_num, message))
+}
+
+/// Frees an MSI vector allocated by [`alloc_msi`].
+pub fn free_msi(irq_num: usize) -> bool {
+    GICV2M.get().is_some_and(|&(_, spi_base, _)| {
+        irq_num
+            .checked_sub(spi_base)
+            .is_some_and(|idx| MSI_VECTORS.free(idx))
+    })
+}
+
+/// Initializes GICC of the current CPU, whose logical ID is `cpu_id`.
+///
+/// It must be called on all CPUs after [`init_gic`], and again when a CPU
+/// resumes from a power-down state.
+pub fn init_gicc(cpu_id: usize) {
     debug!("Initialize GIC CPU Interface...");
     let mut cpu = GIC.lock().cpu_interface();
     cpu.init_current_cpu();
     cpu.set_eoi_mode_ns(false);
+    CPU_IDS.record(cpu_id, current_interface());
+}
+
+/// Returns the bitmap of the CPU interfaces of the CPUs in `cpu_mask`, or
+/// `None` if any of them is not recorded.
+fn target_list(cpu_mask: CpuMask) -> Option<u8> {
+    cpu_mask.iter().try_fold(0, |targets, cpu_id| {
+        Some(targets | 1 << CPU_IDS.hw_id(cpu_id)?)
+    })
 }
 
 /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
+///
+/// The CPUs that have not initialized their CPU interfaces are skipped.
 pub fn send_ipi(irq_num: usize, target: IpiTarget) {
     match target {
         IpiTarget::Current { cpu_id: _ } => {
             GIC.lock()
                 .send_sgi(IntId::sgi(irq_num as u32), SGITarget::Current);
         }
-        IpiTarget::Other { cpu_id } => {
-            let target_list = TargetList::new(&mut [cpu_id].into_iter());
-            GIC.lock().send_sgi(
-                IntId::sgi(irq_num as u32),
-                SGITarget::TargetList(target_list),
-            );
-        }
         IpiTarget::AllExceptCurrent {
             cpu_id: _,
             cpu_num: _,
@@ -119,51 +363,17 @@ pub fn send_ipi(irq_num: usize, target: IpiTarget) {
             GIC.lock()
                 .send_sgi(IntId::sgi(irq_num as u32), SGITarget::AllOther);
         }
-    }
-}
-
-/// Default implementation of [`axplat::irq::IrqIf`] using the GIC.
-#[macro_export]
-macro_rules! irq_if_impl {
-    ($name:ident) => {
-        struct $name;
-
-        #[impl_plat_interface]
-        impl axplat::irq::IrqIf for $name {
-            /// Enables or disables the given IRQ.
-            fn set_enable(irq: usize, enabled: bool) {
-                $crate::gic::set_enable(irq, enabled);
-            }
-
-            /// Registers an IRQ handler for the given IRQ.
-            ///
-            /// It also enables the IRQ if the registration succeeds. It returns `false`
-            /// if the registration failed.
-            fn register(irq: usize, handler: axplat::irq::IrqHandler) -> bool {
-                $crate::gic::register_handler(irq, handler)
-            }
-
-            /// Unregisters the IRQ handler for the given IRQ.
-            ///
-            /// It also disables the IRQ if the unregistration succeeds. It returns the
-            /// existing handler if it is registered, `None` otherwise.
-            fn unregister(irq: usize) -> Option<axplat::irq::IrqHandler> {
-                $crate::gic::unregister_handler(irq)
-            }
-
-            /// Handles the IRQ.
-            ///
-            /// It is called by the common interrupt handler. It should look up in the
-            /// IRQ handler table and calls the corresponding handler. If necessary, it
-            /// also acknowledges the interrupt controller after handling.
-            fn handle(irq: usize) {
-                $crate::gic::handle_irq(irq)
-            }
-
-            /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
-            fn send_ipi(irq_num: usize, target: axplat::irq::IpiTarget) {
-                $crate::gic::send_ipi(irq_num, target);
-            }
+        _ => {
+            let mut targets = target
+                .cpu_mask()
+                .iter()
+                .filter_map(|cpu_id| CPU_IDS.hw_id(cpu_id))
+                .map(|interface| interface as usize);
+            let target_list = TargetList::new(&mut targets);
+            GIC.lock().send_sgi(
+                IntId::sgi(irq_num as u32),
+                SGITarget::TargetList(target_list),
+            );
         }
-    };
+    }
 }
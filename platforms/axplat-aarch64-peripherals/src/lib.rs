@@ -7,6 +7,7 @@ extern crate alloc;
 
 pub mod generic_timer;
 pub mod gic;
+pub mod gicv2;
 pub mod pl011;
 pub mod pl031;
 pub mod psci;
@@ -1,40 +1,121 @@
 //! PL011 UART.
 
 use arm_pl011::Pl011Uart;
+use axplat::console::{ByteRing, Transmitter, TxRing};
 use axplat::mem::VirtAddr;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 
 static UART: LazyInit<SpinNoIrq<Pl011Uart>> = LazyInit::new();
+static UART_BASE: LazyInit<VirtAddr> = LazyInit::new();
 
-fn do_putchar(uart: &mut Pl011Uart, c: u8) {
-    match c {
-        b'\n' => {
-            uart.putchar(b'\r');
-            uart.putchar(b'\n');
+/// Bytes received by the UART interrupt, which are read before the ones still
+/// in the UART.
+static RX_RING: ByteRing<256> = ByteRing::new();
+
+/// Bytes to be sent by the UART interrupt.
+static TX_RING: TxRing<4096> = TxRing::new();
+
+/// The transmitter of the UART, accessed by its registers.
+struct Pl011Tx(*mut u32);
+
+impl Pl011Tx {
+    const DR: usize = 0x00;
+    const FR: usize = 0x18;
+    const IMSC: usize = 0x38;
+    /// Transmit FIFO full.
+    const FR_TXFF: u32 = 1 << 5;
+    /// Transmit interrupt mask.
+    const IMSC_TXIM: u32 = 1 << 5;
+
+    fn new() -> Self {
+        Self(UART_BASE.as_mut_ptr().cast())
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        self.0.wrapping_byte_add(offset)
+    }
+
+    /// Enables or disables the transmit interrupt.
+    fn set_irq_enabled(&mut self, enabled: bool) {
+        let imsc = self.reg(Self::IMSC);
+        unsafe {
+            let val = imsc.read_volatile();
+            imsc.write_volatile(if enabled {
+                val | Self::IMSC_TXIM
+            } else {
+                val & !Self::IMSC_TXIM
+            });
         }
-        c => uart.putchar(c),
+    }
+}
+
+impl Transmitter for Pl011Tx {
+    fn is_ready(&mut self) -> bool {
+        unsafe { self.reg(Self::FR).read_volatile() & Self::FR_TXFF == 0 }
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe { self.reg(Self::DR).write_volatile(byte as u32) };
+    }
+}
+
+/// Writes a byte to the UART, and returns `true` if it is buffered.
+fn do_putchar(tx: &mut Pl011Tx, c: u8) -> bool {
+    match c {
+        b'\n' => TX_RING.write(tx, b'\r') | TX_RING.write(tx, b'\n'),
+        c => TX_RING.write(tx, c),
     }
 }
 
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
-    do_putchar(&mut UART.lock(), c);
+    write_bytes(&[c]);
 }
 
 /// Reads a byte from the console, or returns [`None`] if no input is available.
 pub fn getchar() -> Option<u8> {
-    UART.lock().getchar()
+    let mut uart = UART.lock();
+    if let Some(c) = RX_RING.pop() {
+        return Some(c);
+    }
+    uart.getchar()
 }
 
 /// Write a slice of bytes to the console.
 pub fn write_bytes(bytes: &[u8]) {
-    let mut uart = UART.lock();
+    let _uart = UART.lock();
+    let mut tx = Pl011Tx::new();
+    let mut buffered = false;
     for c in bytes {
-        do_putchar(&mut uart, *c);
+        buffered |= do_putchar(&mut tx, *c);
+    }
+    if buffered {
+        tx.set_irq_enabled(true);
+    }
+}
+
+/// Writes a slice of bytes to the console synchronously, without taking any
+/// lock.
+pub fn write_bytes_nolock(bytes: &[u8]) {
+    let mut tx = Pl011Tx::new();
+    TX_RING.flush(&mut tx);
+    for &c in bytes {
+        if c == b'\n' {
+            tx.send_blocking(b'\r');
+        }
+        tx.send_blocking(c);
     }
 }
 
+/// Waits until all bytes written to the console are sent.
+pub fn flush() {
+    let _uart = UART.lock();
+    let mut tx = Pl011Tx::new();
+    TX_RING.flush(&mut tx);
+    tx.set_irq_enabled(false);
+}
+
 /// Reads bytes from the console into the given mutable slice.
 /// Returns the number of bytes read.
 pub fn read_bytes(bytes: &mut [u8]) -> usize {
@@ -52,6 +133,7 @@ pub fn read_bytes(bytes: &mut [u8]) -> usize {
 
 /// Early stage initialization of the PL011 UART driver.
 pub fn init_early(uart_base: VirtAddr) {
+    UART_BASE.init_once(uart_base);
     UART.init_once(SpinNoIrq::new({
         let mut uart = Pl011Uart::new(uart_base.as_mut_ptr());
         uart.init();
@@ -59,6 +141,38 @@ pub fn init_early(uart_base: VirtAddr) {
     }));
 }
 
+/// Registers the UART IRQ handler, and starts buffering the output.
+pub fn init_irq(irq_num: usize) {
+    axplat::irq::register(irq_num, irq_handler);
+    TX_RING.enable();
+}
+
+/// UART IRQ handler, which moves the received bytes into the ring buffer, and
+/// sends the buffered output.
+///
+/// The receive interrupt is enabled by [`init_early`], and the transmit
+/// interrupt is enabled when there is buffered output.
+fn irq_handler(_irq: usize) {
+    let mut uart = UART.lock();
+    let mut received = false;
+    if uart.is_receive_interrupt() {
+        uart.ack_interrupts();
+        while let Some(c) = uart.getchar() {
+            // Drop the byte if the buffer is full.
+            RX_RING.push(c);
+            received = true;
+        }
+    }
+    let mut tx = Pl011Tx::new();
+    if !TX_RING.drain(&mut tx) {
+        tx.set_irq_enabled(false);
+    }
+    drop(uart);
+    if received {
+        axplat::console::notify_input();
+    }
+}
+
 /// Default implementation of [`axplat::console::ConsoleIf`] using the
 /// PL011 UART.
 #[macro_export]
@@ -80,6 +194,17 @@ macro_rules! console_if_impl {
                 $crate::pl011::read_bytes(bytes)
             }
 
+            /// Waits until all bytes written to the console are sent.
+            fn flush() {
+                $crate::pl011::flush();
+            }
+
+            /// Writes given bytes to the console synchronously, without
+            /// taking any lock.
+            fn write_bytes_nolock(bytes: &[u8]) {
+                $crate::pl011::write_bytes_nolock(bytes);
+            }
+
             /// Returns the IRQ number for the console, if applicable.
             #[cfg(feature = "irq")]
             fn irq_number() -> Option<u32> {
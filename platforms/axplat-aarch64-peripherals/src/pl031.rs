@@ -1,17 +1,88 @@
 //! PL031 Real Time Clock (RTC) driver.
 
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
 use arm_pl031::Rtc;
 use axplat::mem::VirtAddr;
 
 use crate::generic_timer::{current_ticks, ticks_to_nanos};
 
 /// RTC wall time offset in nanoseconds at monotonic time base.
-static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
+static RTC_EPOCHOFFSET_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Base virtual address of the RTC, or 0 if it is not initialized.
+static RTC_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the RTC interrupt handler is registered by [`init_irq`].
+static IRQ_REGISTERED: AtomicBool = AtomicBool::new(false);
 
 /// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
 #[inline]
 pub fn epochoffset_nanos() -> u64 {
-    unsafe { RTC_EPOCHOFFSET_NANOS }
+    RTC_EPOCHOFFSET_NANOS.load(Ordering::Acquire)
+}
+
+/// Set epoch offset in nanoseconds.
+///
+/// The new wall time is written to the load register of the RTC, if it is
+/// initialized.
+pub fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+    RTC_EPOCHOFFSET_NANOS.store(epochoffset_nanos, Ordering::Release);
+
+    if let Some(mut rtc) = rtc() {
+        let wall_time_nanos = axplat::time::wall_time_nanos();
+        rtc.set_unix_timestamp((wall_time_nanos / axplat::time::NANOS_PER_SEC) as u32);
+    }
+}
+
+/// Returns the RTC, or `None` if it is not initialized.
+fn rtc() -> Option<Rtc> {
+    match RTC_BASE.load(Ordering::Acquire) {
+        0 => None,
+        rtc_base => Some(unsafe { Rtc::new(rtc_base as _) }),
+    }
+}
+
+/// Sets an alarm at the given wall time in nanoseconds, by the match register.
+///
+/// The time is rounded up to seconds, and an alarm in the past fires in a
+/// second. Returns `false` if the RTC is not initialized, or its interrupt is
+/// not registered by [`init_irq`], where the alarm would never be notified.
+pub fn set_alarm(wall_time_ns: u64) -> bool {
+    if !IRQ_REGISTERED.load(Ordering::Acquire) {
+        return false;
+    }
+    let Some(mut rtc) = rtc() else {
+        return false;
+    };
+    let secs = wall_time_ns.div_ceil(axplat::time::NANOS_PER_SEC);
+    let secs = secs.clamp(rtc.get_unix_timestamp() as u64 + 1, u32::MAX as u64);
+    rtc.set_match_timestamp(secs as u32);
+    rtc.clear_interrupt();
+    rtc.enable_interrupt(true);
+    true
+}
+
+/// Cancels the pending alarm, if any.
+pub fn cancel_alarm() {
+    if let Some(mut rtc) = rtc() {
+        rtc.enable_interrupt(false);
+        rtc.clear_interrupt();
+    }
+}
+
+/// Registers the RTC interrupt handler for alarms.
+pub fn init_irq(rtc_irq: usize) {
+    if axplat::irq::register(rtc_irq, irq_handler) {
+        IRQ_REGISTERED.store(true, Ordering::Release);
+    }
+}
+
+/// Handles the RTC interrupt, by disabling the alarm and calling
+/// [`axplat::time::notify_alarm`].
+fn irq_handler(_irq: usize) {
+    cancel_alarm();
+    axplat::time::notify_alarm();
 }
 
 /// Early stage initialization of the RTC driver.
@@ -29,7 +100,9 @@ pub fn init_early(rtc_base: VirtAddr) {
     // Subtract the timer ticks to get the actual time when ArceOS was booted.
     let epoch_time_nanos = rtc.get_unix_timestamp() as u64 * 1_000_000_000;
 
-    unsafe {
-        RTC_EPOCHOFFSET_NANOS = epoch_time_nanos - ticks_to_nanos(current_ticks());
-    }
+    RTC_EPOCHOFFSET_NANOS.store(
+        epoch_time_nanos - ticks_to_nanos(current_ticks()),
+        Ordering::Release,
+    );
+    RTC_BASE.store(rtc_base.as_usize(), Ordering::Release);
 }
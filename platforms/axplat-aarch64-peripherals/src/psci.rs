@@ -4,6 +4,9 @@
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
+use axplat::mem::{va, virt_to_phys};
+use axplat::power::{CpuState, ResetKind, SuspendState};
+
 const PSCI_0_2_FN_BASE: u32 = 0x84000000;
 const PSCI_0_2_64BIT: u32 = 0x40000000;
 const PSCI_0_2_FN_CPU_SUSPEND: u32 = PSCI_0_2_FN_BASE + 1;
@@ -14,7 +17,18 @@ const PSCI_0_2_FN_SYSTEM_OFF: u32 = PSCI_0_2_FN_BASE + 8;
 const PSCI_0_2_FN_SYSTEM_RESET: u32 = PSCI_0_2_FN_BASE + 9;
 const PSCI_0_2_FN64_CPU_SUSPEND: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 1;
 const PSCI_0_2_FN64_CPU_ON: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 3;
+const PSCI_0_2_FN64_AFFINITY_INFO: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 4;
 const PSCI_0_2_FN64_MIGRATE: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 5;
+const PSCI_1_0_FN_PSCI_FEATURES: u32 = PSCI_0_2_FN_BASE + 0xa;
+const PSCI_1_1_FN64_SYSTEM_RESET2: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 0x12;
+
+const PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET: usize = 0;
+
+const PSCI_POWER_STATE_TYPE_STANDBY: u32 = 0;
+const PSCI_POWER_STATE_TYPE_POWER_DOWN: u32 = 1;
+const PSCI_0_2_POWER_STATE_TYPE_SHIFT: u32 = 16;
+const PSCI_1_0_EXT_POWER_STATE_TYPE_SHIFT: u32 = 30;
+const PSCI_1_0_FEATURES_CPU_SUSPEND_PF_EXT: i32 = 1 << 1;
 
 static PSCI_METHOD_HVC: AtomicBool = AtomicBool::new(false);
 
@@ -82,12 +96,16 @@ fn psci_hvc_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
-fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), PsciError> {
-    let ret = if PSCI_METHOD_HVC.load(Ordering::Acquire) {
+fn psci_raw_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    if PSCI_METHOD_HVC.load(Ordering::Acquire) {
         psci_hvc_call(func, arg0, arg1, arg2)
     } else {
         arm_smccc_smc(func, arg0, arg1, arg2)
-    };
+    }
+}
+
+fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), PsciError> {
+    let ret = psci_raw_call(func, arg0, arg1, arg2);
     if ret == 0 {
         Ok(())
     } else {
@@ -116,6 +134,35 @@ pub fn system_off() -> ! {
     }
 }
 
+/// Returns whether the PSCI function `func` is implemented by the firmware.
+fn psci_features(func: u32) -> bool {
+    // `PSCI_FEATURES` returns a negative error code if the function is not
+    // implemented, or not supported at all (before PSCI 1.0).
+    (psci_raw_call(PSCI_1_0_FN_PSCI_FEATURES, func as usize, 0, 0) as i32) >= 0
+}
+
+/// Resets the whole system.
+///
+/// Warm reset is done by `SYSTEM_RESET2` if the firmware supports it,
+/// otherwise a cold reset is done by `SYSTEM_RESET`.
+pub fn system_reset(kind: ResetKind) -> ! {
+    info!("Resetting the system...");
+    if kind == ResetKind::Warm && psci_features(PSCI_1_1_FN64_SYSTEM_RESET2) {
+        psci_call(
+            PSCI_1_1_FN64_SYSTEM_RESET2,
+            PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET,
+            0,
+            0,
+        )
+        .ok();
+    }
+    psci_call(PSCI_0_2_FN_SYSTEM_RESET, 0, 0, 0).ok();
+    warn!("It should reset!");
+    loop {
+        axcpu::asm::halt();
+    }
+}
+
 /// Power up a core. This call is used to power up cores that either:
 ///
 /// * Have not yet been booted into the calling supervisory software.
@@ -135,10 +182,229 @@ pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) {
 /// Power down the calling core. This call is intended for use in hotplug. A
 /// core that is powered down by `cpu_off` can only be powered up again in
 /// response to a `cpu_on`.
-pub fn cpu_off() {
-    const PSCI_POWER_STATE_TYPE_STANDBY: u32 = 0;
-    const PSCI_POWER_STATE_TYPE_POWER_DOWN: u32 = 1;
-    const PSCI_0_2_POWER_STATE_TYPE_SHIFT: u32 = 16;
+pub fn cpu_off() -> ! {
     let state: u32 = PSCI_POWER_STATE_TYPE_POWER_DOWN << PSCI_0_2_POWER_STATE_TYPE_SHIFT;
-    psci_call(PSCI_0_2_FN_CPU_OFF, state as usize, 0, 0).ok();
+    if let Err(e) = psci_call(PSCI_0_2_FN_CPU_OFF, state as usize, 0, 0) {
+        error!("failed to power down the current CPU ({:?})", e);
+    }
+    loop {
+        axcpu::asm::halt();
+    }
+}
+
+/// Returns the power state of a core.
+///
+/// `target_cpu` contains a copy of the affinity fields of the MPIDR register.
+pub fn cpu_state(target_cpu: usize) -> CpuState {
+    const PSCI_0_2_AFFINITY_LEVEL_ON: i32 = 0;
+    const PSCI_0_2_AFFINITY_LEVEL_OFF: i32 = 1;
+    const PSCI_0_2_AFFINITY_LEVEL_ON_PENDING: i32 = 2;
+    match psci_raw_call(PSCI_0_2_FN64_AFFINITY_INFO, target_cpu, 0, 0) as i32 {
+        PSCI_0_2_AFFINITY_LEVEL_ON => CpuState::On,
+        PSCI_0_2_AFFINITY_LEVEL_OFF => CpuState::Off,
+        PSCI_0_2_AFFINITY_LEVEL_ON_PENDING => CpuState::Pending,
+        _ => CpuState::Unknown,
+    }
+}
+
+/// CPU context saved before the non-retentive suspend.
+///
+/// Layout: `x19`-`x30`, `sp`, `sp_el0`, `tpidr_el0`, `tpidr_el1`, `ttbr0_el1`,
+/// `ttbr1_el1`, `vbar_el1`, `cntp_ctl_el0`, `cntp_cval_el0`.
+#[repr(C, align(16))]
+pub struct SuspendContext([u64; 22]);
+
+/// Returns the `power_state` parameter of `CPU_SUSPEND` with the given state
+/// type, at the core power level.
+fn power_state(state_type: u32) -> usize {
+    // The extended StateID format is indicated by `PSCI_FEATURES`.
+    let flags = psci_raw_call(
+        PSCI_1_0_FN_PSCI_FEATURES,
+        PSCI_0_2_FN64_CPU_SUSPEND as usize,
+        0,
+        0,
+    ) as i32;
+    let shift = if flags >= 0 && flags & PSCI_1_0_FEATURES_CPU_SUSPEND_PF_EXT != 0 {
+        PSCI_1_0_EXT_POWER_STATE_TYPE_SHIFT
+    } else {
+        PSCI_0_2_POWER_STATE_TYPE_SHIFT
+    };
+    (state_type << shift) as usize
+}
+
+/// Calls `CPU_SUSPEND` with the physical address of the saved context as the
+/// context ID.
+unsafe extern "C" fn suspend_finisher(
+    ctx: *mut SuspendContext,
+    power_state: usize,
+    entry_point: usize,
+) -> usize {
+    let ctx_paddr = virt_to_phys(va!(ctx as usize)).as_usize();
+    psci_raw_call(
+        PSCI_0_2_FN64_CPU_SUSPEND,
+        power_state,
+        entry_point,
+        ctx_paddr,
+    )
+}
+
+/// Saves the CPU context to `ctx`, then suspends the current core.
+///
+/// It returns either from the finisher directly (if the core did not lose its
+/// context), or from [`cpu_resume`] after the core is powered up again.
+#[unsafe(naked)]
+unsafe extern "C" fn suspend_enter(
+    ctx: *mut SuspendContext,
+    power_state: usize,
+    entry_point: usize,
+) -> usize {
+    core::arch::naked_asm!("
+        stp     x19, x20, [x0]
+        stp     x21, x22, [x0, #16]
+        stp     x23, x24, [x0, #32]
+        stp     x25, x26, [x0, #48]
+        stp     x27, x28, [x0, #64]
+        stp     x29, x30, [x0, #80]
+        mov     x9, sp
+        mrs     x10, sp_el0
+        stp     x9, x10, [x0, #96]
+        mrs     x9, tpidr_el0
+        mrs     x10, tpidr_el1
+        stp     x9, x10, [x0, #112]
+        mrs     x9, ttbr0_el1
+        mrs     x10, ttbr1_el1
+        stp     x9, x10, [x0, #128]
+        mrs     x9, vbar_el1
+        mrs     x10, cntp_ctl_el0
+        stp     x9, x10, [x0, #144]
+        mrs     x9, cntp_cval_el0
+        str     x9, [x0, #160]
+
+        mov     x19, x0
+        bl      {finisher}              // finisher(ctx, power_state, entry_point)
+
+        ldp     x29, x30, [x19, #80]    // returned without losing the context
+        ldr     x19, [x19]
+        ret",
+        finisher = sym suspend_finisher,
+    )
+}
+
+/// Restores the CPU context saved before the non-retentive suspend, and
+/// returns to the caller of [`cpu_suspend`].
+///
+/// It should be called by the platform resume entry with `ctx` set to the
+/// virtual address of the context, after the MMU is enabled.
+///
+/// # Safety
+///
+/// `ctx` must be the context ID passed to the resume entry, converted to the
+/// virtual address.
+#[unsafe(naked)]
+pub unsafe extern "C" fn cpu_resume(ctx: *const SuspendContext) -> ! {
+    core::arch::naked_asm!(
+        "
+        ldp     x9, x10, [x0, #128]
+        msr     ttbr0_el1, x9
+        msr     ttbr1_el1, x10
+        isb
+        tlbi    vmalle1
+        dsb     nsh
+        isb
+
+        ldr     x9, [x0, #160]
+        msr     cntp_cval_el0, x9
+        ldp     x9, x10, [x0, #144]
+        msr     vbar_el1, x9
+        msr     cntp_ctl_el0, x10
+        ldp     x9, x10, [x0, #112]
+        msr     tpidr_el0, x9
+        msr     tpidr_el1, x10
+        ldp     x9, x10, [x0, #96]
+        mov     sp, x9
+        msr     sp_el0, x10
+        isb
+
+        ldp     x19, x20, [x0]
+        ldp     x21, x22, [x0, #16]
+        ldp     x23, x24, [x0, #32]
+        ldp     x25, x26, [x0, #48]
+        ldp     x27, x28, [x0, #64]
+        ldp     x29, x30, [x0, #80]
+        mov     x0, #0                  // return from `suspend_enter` with success
+        ret"
+    )
+}
+
+/// Defines the platform resume entry `$name` for [`cpu_suspend`].
+///
+/// The entry switches to EL1, calls `$enable_fp` to enable FP/SIMD, enables
+/// the MMU with the boot page table `$boot_pt`, and then jumps to
+/// [`cpu_resume`] with the context converted to the virtual address by adding
+/// `$phys_virt_offset`.
+#[macro_export]
+macro_rules! resume_entry {
+    ($name:ident, $boot_pt:ident, $phys_virt_offset:expr, $enable_fp:path) => {
+        /// The entry point for the CPUs resuming from the non-retentive suspend.
+        #[unsafe(naked)]
+        #[unsafe(link_section = ".text.boot")]
+        pub(crate) unsafe extern "C" fn $name() -> ! {
+            // X0 = saved context (physical address)
+            core::arch::naked_asm!("
+                mov     x19, x0
+                mov     sp, x0                  // use the space below the context as the stack
+                bl      {switch_to_el1}
+                bl      {enable_fp}
+                adrp    x0, {boot_pt}
+                bl      {init_mmu}
+
+                mov     x8, {phys_virt_offset}  // set SP and the context to the high address
+                add     sp, sp, x8
+                add     x0, x19, x8
+                ldr     x8, ={entry}            // cpu_resume(ctx)
+                br      x8",
+                switch_to_el1 = sym axcpu::init::switch_to_el1,
+                init_mmu = sym axcpu::init::init_mmu,
+                enable_fp = sym $enable_fp,
+                boot_pt = sym $boot_pt,
+                phys_virt_offset = const $phys_virt_offset,
+                entry = sym $crate::psci::cpu_resume,
+            )
+        }
+    };
+}
+
+/// Suspends the calling core in the given low-power state, until a wake-up
+/// event arrives.
+///
+/// For [`SuspendState::PowerDown`], `resume_entry` is the physical address of
+/// the platform resume entry. The firmware jumps to it with the MMU disabled
+/// and `X0` set to the physical address of the saved context, which can be
+/// used as the stack to enable the MMU. It should then jump to [`cpu_resume`]
+/// with the virtual address of the context. Such an entry is defined by
+/// [`resume_entry!`](crate::resume_entry).
+///
+/// Returns `false` if the firmware fails to suspend the core.
+pub fn cpu_suspend(state: SuspendState, resume_entry: usize) -> bool {
+    let ret = match state {
+        SuspendState::Standby => psci_raw_call(
+            PSCI_0_2_FN64_CPU_SUSPEND,
+            power_state(PSCI_POWER_STATE_TYPE_STANDBY),
+            0,
+            0,
+        ),
+        SuspendState::PowerDown => {
+            let mut ctx = SuspendContext([0; 22]);
+            let power_state = power_state(PSCI_POWER_STATE_TYPE_POWER_DOWN);
+            unsafe { suspend_enter(&mut ctx, power_state, resume_entry) }
+        }
+    };
+    if ret != 0 {
+        warn!(
+            "failed to suspend the current CPU ({:?})",
+            PsciError::from(ret as i32)
+        );
+        return false;
+    }
+    true
 }
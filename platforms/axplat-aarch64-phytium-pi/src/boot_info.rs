@@ -0,0 +1,11 @@
+use axplat::boot::{BootIf, BootInfo};
+
+struct BootIfImpl;
+
+#[impl_plat_interface]
+impl BootIf for BootIfImpl {
+    /// Parses the boot information from the device tree blob.
+    fn parse_boot_info(dtb: usize) -> BootInfo {
+        BootInfo::from_fdt(dtb, crate::mem::MEM.init(dtb).as_ref())
+    }
+}
@@ -142,3 +142,5 @@ pub(crate) unsafe extern "C" fn _start_secondary() -> ! {
         entry = sym axplat::call_secondary_main,
     )
 }
+
+axplat_aarch64_peripherals::resume_entry!(_start_resume, BOOT_PT_L0, PHYS_VIRT_OFFSET, enable_fp);
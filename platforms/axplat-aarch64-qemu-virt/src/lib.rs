@@ -4,6 +4,7 @@
 extern crate axplat;
 
 mod boot;
+mod boot_info;
 mod init;
 mod mem;
 mod power;
@@ -27,4 +28,4 @@ axplat_aarch64_peripherals::console_if_impl!(ConsoleIfImpl);
 axplat_aarch64_peripherals::time_if_impl!(TimeIfImpl);
 
 #[cfg(feature = "irq")]
-axplat_aarch64_peripherals::irq_if_impl!(IrqIfImpl);
+axplat_aarch64_peripherals::irq_if_impl!(IrqIfImpl, gicv2, crate::config::plat::CPU_NUM);
@@ -1,4 +1,6 @@
-use axplat::power::PowerIf;
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
 
 struct PowerImpl;
 
@@ -16,8 +18,43 @@ impl PowerIf for PowerImpl {
         axplat_aarch64_peripherals::psci::cpu_on(cpu_id, entry_paddr.as_usize(), stack_top_paddr);
     }
 
+    /// Powers off the current CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        axplat_aarch64_peripherals::psci::cpu_off()
+    }
+
+    /// Returns the power state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        axplat_aarch64_peripherals::psci::cpu_state(cpu_id)
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        use axplat::mem::{va, virt_to_phys};
+        let entry = virt_to_phys(va!(crate::boot::_start_resume as usize));
+        if !axplat_aarch64_peripherals::psci::cpu_suspend(state, entry.as_usize()) {
+            return false;
+        }
+        // The GIC CPU interface and the banked enable bit of the timer
+        // interrupt may be lost after power down.
+        #[cfg(feature = "irq")]
+        if state == SuspendState::PowerDown {
+            use axplat_aarch64_peripherals::gicv2;
+            gicv2::init_gicc(gicv2::current_cpu_id());
+            axplat::irq::set_enable(crate::config::devices::TIMER_IRQ, true);
+        }
+        true
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         axplat_aarch64_peripherals::psci::system_off()
     }
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> ! {
+        axplat_aarch64_peripherals::psci::system_reset(kind)
+    }
 }
@@ -16,6 +16,11 @@ impl InitIf for InitIfImpl {
     fn init_early(_cpu_id: usize, _dtb: usize) {
         axcpu::init::init_trap();
         axplat_aarch64_peripherals::pl011::init_early(phys_to_virt(pa!(UART_PADDR)));
+        #[cfg(feature = "pstore")]
+        axplat::pstore::init(
+            axplat::mem::phys_to_virt(axplat::mem::pa!(crate::config::plat::PSTORE_PADDR)),
+            crate::config::plat::PSTORE_SIZE,
+        );
         axplat_aarch64_peripherals::generic_timer::init_early();
     }
 
@@ -31,14 +36,19 @@ impl InitIf for InitIfImpl {
     /// initialization (e.g, logging, memory management), and finalized the rest of
     /// platform configuration and initialization.
     fn init_later(_cpu_id: usize, _dtb: usize) {
+        // The device tree is parsed before the logger is ready.
+        crate::mem::MEM.log_warnings();
         #[cfg(feature = "irq")]
         {
-            axplat_aarch64_peripherals::gic::init_gic(
+            axplat_aarch64_peripherals::gicv2::init_gic(
                 phys_to_virt(pa!(GICD_PADDR)),
                 phys_to_virt(pa!(GICC_PADDR)),
             );
-            axplat_aarch64_peripherals::gic::init_gicc();
+            axplat_aarch64_peripherals::gicv2::init_gicc(_cpu_id);
             axplat_aarch64_peripherals::generic_timer::enable_irqs(TIMER_IRQ);
+            axplat::ipi::init(crate::config::devices::IPI_IRQ);
+            axplat::time::init_timer_irq(crate::config::devices::TIMER_IRQ);
+            axplat_aarch64_peripherals::pl011::init_irq(UART_IRQ);
         }
     }
 
@@ -47,7 +57,7 @@ impl InitIf for InitIfImpl {
     fn init_later_secondary(_cpu_id: usize) {
         #[cfg(feature = "irq")]
         {
-            axplat_aarch64_peripherals::gic::init_gicc();
+            axplat_aarch64_peripherals::gicv2::init_gicc(_cpu_id);
             axplat_aarch64_peripherals::generic_timer::enable_irqs(TIMER_IRQ);
         }
     }
@@ -1,7 +1,34 @@
+use axplat::fdt::{MemConfig, PlatformMem};
 use axplat::mem::{MemIf, PhysAddr, RawRange, VirtAddr, pa, va};
 
 use crate::config::devices::MMIO_RANGES;
 use crate::config::plat::{PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE, PHYS_VIRT_OFFSET};
+#[cfg(feature = "pstore")]
+use crate::config::plat::{PSTORE_PADDR, PSTORE_SIZE};
+
+/// Maximum number of memory ranges of each kind discovered from the device
+/// tree.
+const MAX_MEM_RANGES: usize = 32;
+
+/// Reserved ranges that are not described in the device tree.
+///
+/// The first page is the spin table.
+#[cfg(feature = "pstore")]
+const RESERVED_RANGES: &[RawRange] = &[(0, 0x1000), (PSTORE_PADDR, PSTORE_SIZE)];
+#[cfg(not(feature = "pstore"))]
+const RESERVED_RANGES: &[RawRange] = &[(0, 0x1000)];
+
+/// Memory layout of the platform, which is discovered from the device tree
+/// passed by the bootloader.
+pub(crate) static MEM: PlatformMem<MAX_MEM_RANGES> = PlatformMem::new(MemConfig {
+    ram: &[(PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE)],
+    reserved: RESERVED_RANGES,
+    mmio: &MMIO_RANGES,
+    excluded_ram: &[],
+    // The device tree blob is expected to be placed in RAM by the bootloader,
+    // don't touch it otherwise.
+    fdt_range: Some(PHYS_MEMORY_BASE..PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE),
+});
 
 struct MemIfImpl;
 
@@ -12,7 +39,7 @@ impl MemIf for MemIfImpl {
     /// All memory ranges except reserved ranges (including the kernel loaded
     /// range) are free for allocation.
     fn phys_ram_ranges() -> &'static [RawRange] {
-        &[(PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE)]
+        MEM.ram_ranges()
     }
 
     /// Returns all reserved physical memory ranges on the platform.
@@ -23,12 +50,12 @@ impl MemIf for MemIfImpl {
     /// Note that the ranges returned should not include the range where the
     /// kernel is loaded.
     fn reserved_phys_ram_ranges() -> &'static [RawRange] {
-        &[(0, 0x1000)] // spintable
+        MEM.reserved_ranges()
     }
 
     /// Returns all device memory (MMIO) ranges on the platform.
     fn mmio_ranges() -> &'static [RawRange] {
-        &MMIO_RANGES
+        MEM.mmio_ranges()
     }
 
     /// Translates a physical address to a virtual address.
@@ -1,4 +1,28 @@
-use axplat::power::PowerIf;
+use axplat::mem::{pa, phys_to_virt};
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
+
+use crate::config::devices::PM_PADDR;
+
+/// Resets the SoC by the watchdog in the power management block.
+fn pm_watchdog_reset() {
+    const PM_RSTC: usize = 0x1c;
+    const PM_WDOG: usize = 0x24;
+    const PM_PASSWORD: u32 = 0x5a00_0000;
+    const PM_RSTC_WRCFG_CLR: u32 = 0xffff_ffcf;
+    const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x20;
+
+    let base = phys_to_virt(pa!(PM_PADDR)).as_usize();
+    let rstc = (base + PM_RSTC) as *mut u32;
+    let wdog = (base + PM_WDOG) as *mut u32;
+    unsafe {
+        // Timeout after 10 ticks (~150us).
+        wdog.write_volatile(PM_PASSWORD | 10);
+        let val = rstc.read_volatile() & PM_RSTC_WRCFG_CLR;
+        rstc.write_volatile(val | PM_PASSWORD | PM_RSTC_WRCFG_FULL_RESET);
+    }
+}
 
 struct PowerImpl;
 
@@ -14,6 +38,41 @@ impl PowerIf for PowerImpl {
         crate::mp::start_secondary_cpu(cpu_id, axplat::mem::pa!(stack_top_paddr));
     }
 
+    /// Powers off the current CPU core.
+    ///
+    /// The spin-table boot protocol has no way to power off a core, so the
+    /// core is just parked and cannot be booted again.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        log::warn!("CPU power off is not supported, parking the current CPU");
+        axcpu::asm::disable_irqs();
+        loop {
+            aarch64_cpu::asm::wfe();
+        }
+    }
+
+    /// Returns the power state of the given CPU core.
+    ///
+    /// It is always [`CpuState::Unknown`] since the spin-table boot protocol
+    /// cannot report it.
+    #[cfg(feature = "smp")]
+    fn cpu_state(_cpu_id: usize) -> CpuState {
+        CpuState::Unknown
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    ///
+    /// Only [`SuspendState::Standby`] is supported, which is entered by `wfi`.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        match state {
+            SuspendState::Standby => {
+                aarch64_cpu::asm::wfi();
+                true
+            }
+            SuspendState::PowerDown => false,
+        }
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         log::info!("Shutting down...");
@@ -22,4 +81,15 @@ impl PowerIf for PowerImpl {
             axcpu::asm::halt();
         }
     }
+
+    /// Resets (reboots) the whole system.
+    ///
+    /// Only cold reset is supported.
+    fn system_reset(_kind: ResetKind) -> ! {
+        log::info!("Resetting the system...");
+        pm_watchdog_reset();
+        loop {
+            axcpu::asm::halt();
+        }
+    }
 }
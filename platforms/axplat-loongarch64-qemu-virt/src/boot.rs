@@ -1,7 +1,7 @@
-use axplat::mem::{Aligned4K, pa, va};
+use axplat::mem::{Aligned4K, pa, phys_to_virt, va};
 use page_table_entry::{GenericPTE, MappingFlags, loongarch64::LA64PTE};
 
-use crate::config::plat::{BOOT_STACK_SIZE, PHYS_BOOT_OFFSET, PHYS_VIRT_OFFSET};
+use crate::config::plat::{BOOT_STACK_SIZE, FDT_PADDR, PHYS_BOOT_OFFSET, PHYS_VIRT_OFFSET};
 
 #[unsafe(link_section = ".bss.stack")]
 static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
@@ -65,6 +65,50 @@ fn init_mmu() {
 
 const BOOT_TO_VIRT: usize = PHYS_VIRT_OFFSET - PHYS_BOOT_OFFSET;
 
+/// Size of the physical memory mapped by the boot page table.
+const BOOT_MAPPED_SIZE: usize = 0x4000_0000;
+
+/// Signature of the EFI system table ("IBI SYST").
+const EFI_SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249;
+
+/// GUID of the device tree in the EFI configuration tables
+/// (b1b621d5-f19c-41a5-830b-d9152c69aae0).
+const EFI_DTB_TABLE_GUID: [u8; 16] = [
+    0xd5, 0x21, 0xb6, 0xb1, 0x9c, 0xf1, 0xa5, 0x41, 0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0,
+];
+
+/// Returns the physical address of the device tree blob.
+///
+/// Following the Linux boot protocol, the bootloader (e.g., QEMU) passes
+/// `a0 = 1` and the EFI system table in `a2`, and the device tree is found in
+/// its configuration tables. Otherwise, it falls back to [`FDT_PADDR`], where
+/// older QEMU versions load it.
+extern "C" fn boot_fdt_paddr(efi_boot: usize, systab: usize) -> usize {
+    // Only the memory mapped by the boot page table can be read here.
+    fn read<T: Copy>(paddr: usize) -> Option<T> {
+        let end = paddr.checked_add(size_of::<T>())?;
+        let ptr = phys_to_virt(pa!(paddr)).as_ptr().cast::<T>();
+        (end <= BOOT_MAPPED_SIZE).then(|| unsafe { ptr.read_unaligned() })
+    }
+    let find = || {
+        if efi_boot != 1 || read::<u64>(systab)? != EFI_SYSTEM_TABLE_SIGNATURE {
+            return None;
+        }
+        // `NumberOfTableEntries` and `ConfigurationTable` in the system table.
+        let count: u64 = read(systab + 104)?;
+        let tables: u64 = read(systab + 112)?;
+        // Each entry is a GUID followed by the table pointer.
+        (0..count.min(64) as usize).find_map(|i| {
+            let entry = (tables as usize).checked_add(i * 24)?;
+            if read::<[u8; 16]>(entry)? != EFI_DTB_TABLE_GUID {
+                return None;
+            }
+            read::<u64>(entry + 16)
+        })
+    };
+    find().map_or(FDT_PADDR, |paddr| paddr as usize)
+}
+
 /// The earliest entry point for the primary CPU.
 ///
 /// We can't use bl to jump to higher address, so we use jirl to jump to higher address.
@@ -84,6 +128,9 @@ unsafe extern "C" fn _start() -> ! {
         .word   0x818223cd          # Magic number
         .word   0x0                 # Offset to the PE header
 
+        move        $s0, $a0            # EFI boot flag
+        move        $s1, $a2            # EFI system table
+
         # Setup DMW
         li.d        $t0, {boot_virt_offset} | 0x11
         csrwr       $t0, 0x180          # DMWIN0
@@ -109,8 +156,11 @@ unsafe extern "C" fn _start() -> ! {
         li.d        $t0, {boot_to_virt}
         add.d       $sp, $sp, $t0
 
+        move        $a0, $s0
+        move        $a1, $s1
+        bl          {boot_fdt_paddr}
+        move        $a1, $a0            # dtb
         csrrd       $a0, 0x20           # cpuid
-        li.d        $a1, 0              # TODO: parse dtb
         la.global   $t0, {entry}
         jirl        $zero, $t0, 0",
 
@@ -119,6 +169,7 @@ unsafe extern "C" fn _start() -> ! {
 
         boot_stack = sym BOOT_STACK,
         boot_stack_size = const BOOT_STACK_SIZE,
+        boot_fdt_paddr = sym boot_fdt_paddr,
         enable_fp_simd = sym enable_fp_simd,
         init_boot_page_table = sym init_boot_page_table,
         init_mmu = sym init_mmu,
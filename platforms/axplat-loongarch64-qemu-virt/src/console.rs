@@ -1,5 +1,7 @@
+#[cfg(feature = "irq")]
+use axplat::console::{ByteRing, TxRing};
 use axplat::{
-    console::ConsoleIf,
+    console::{ConsoleIf, Transmitter},
     mem::{pa, phys_to_virt},
 };
 use kspin::SpinNoIrq;
@@ -10,6 +12,57 @@ use crate::config::devices::UART_PADDR;
 
 static UART: LazyInit<SpinNoIrq<MmioSerialPort>> = LazyInit::new();
 
+/// Bytes received by the UART interrupt, which are read before the ones still
+/// in the UART.
+#[cfg(feature = "irq")]
+static RX_RING: ByteRing<256> = ByteRing::new();
+
+/// Bytes to be sent by the UART interrupt.
+#[cfg(feature = "irq")]
+static TX_RING: TxRing<4096> = TxRing::new();
+
+/// The transmitter of the UART, accessed by its registers.
+struct UartTx;
+
+impl UartTx {
+    #[cfg(feature = "irq")]
+    const IER: usize = 1;
+    const LSR: usize = 5;
+    /// Transmitter holding register empty.
+    const THRE: u8 = 1 << 5;
+    /// Interrupt when the transmitter holding register is empty.
+    #[cfg(feature = "irq")]
+    const IER_THRE: u8 = 1 << 1;
+
+    fn reg(offset: usize) -> *mut u8 {
+        (phys_to_virt(pa!(UART_PADDR)).as_usize() + offset) as *mut u8
+    }
+
+    /// Enables or disables the interrupt when the transmitter is empty.
+    #[cfg(feature = "irq")]
+    fn set_irq_enabled(&mut self, enabled: bool) {
+        let ier = Self::reg(Self::IER);
+        unsafe {
+            let val = ier.read_volatile();
+            ier.write_volatile(if enabled {
+                val | Self::IER_THRE
+            } else {
+                val & !Self::IER_THRE
+            });
+        }
+    }
+}
+
+impl Transmitter for UartTx {
+    fn is_ready(&mut self) -> bool {
+        unsafe { Self::reg(Self::LSR).read_volatile() & Self::THRE != 0 }
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe { Self::reg(0).write_volatile(byte) };
+    }
+}
+
 pub(crate) fn init_early() {
     UART.init_once({
         let mut uart = unsafe { MmioSerialPort::new(phys_to_virt(pa!(UART_PADDR)).as_usize()) };
@@ -18,12 +71,58 @@ pub(crate) fn init_early() {
     });
 }
 
+/// Registers the UART interrupt handler, and starts buffering the output.
+#[cfg(feature = "irq")]
+pub(crate) fn init_irq() {
+    axplat::irq::register(crate::config::devices::UART_IRQ, irq_handler);
+    TX_RING.enable();
+}
+
+/// Handles the UART interrupt, by moving the received bytes into the ring
+/// buffer, and sending the buffered output.
+///
+/// The UART raises the interrupt when data is received, as enabled by
+/// [`init_early`], or when the transmitter is empty and there is buffered
+/// output.
+#[cfg(feature = "irq")]
+fn irq_handler(_irq: usize) {
+    let mut uart = UART.lock();
+    let mut received = false;
+    while let Ok(c) = uart.try_receive() {
+        // Drop the byte if the buffer is full.
+        RX_RING.push(c);
+        received = true;
+    }
+    if !TX_RING.drain(&mut UartTx) {
+        UartTx.set_irq_enabled(false);
+    }
+    drop(uart);
+    if received {
+        axplat::console::notify_input();
+    }
+}
+
 struct ConsoleIfImpl;
 
 #[impl_plat_interface]
 impl ConsoleIf for ConsoleIfImpl {
     /// Writes bytes to the console from input u8 slice.
     fn write_bytes(bytes: &[u8]) {
+        #[cfg(feature = "irq")]
+        {
+            let _uart = UART.lock();
+            let mut buffered = false;
+            for &c in bytes {
+                if c == b'\n' {
+                    buffered |= TX_RING.write(&mut UartTx, b'\r');
+                }
+                buffered |= TX_RING.write(&mut UartTx, c);
+            }
+            if buffered {
+                UartTx.set_irq_enabled(true);
+            }
+        }
+        #[cfg(not(feature = "irq"))]
         for &c in bytes {
             let mut uart = UART.lock();
             match c {
@@ -42,13 +141,41 @@ impl ConsoleIf for ConsoleIfImpl {
     /// Returns the number of bytes read.
     fn read_bytes(bytes: &mut [u8]) -> usize {
         let mut uart = UART.lock();
-        for (i, byte) in bytes.iter_mut().enumerate() {
+        #[cfg(feature = "irq")]
+        let mut read_len = RX_RING.read(bytes);
+        #[cfg(not(feature = "irq"))]
+        let mut read_len = 0;
+        while read_len < bytes.len() {
             match uart.try_receive() {
-                Ok(c) => *byte = c,
-                Err(_) => return i,
+                Ok(c) => bytes[read_len] = c,
+                Err(_) => break,
+            }
+            read_len += 1;
+        }
+        read_len
+    }
+
+    /// Waits until all bytes written to the console are sent.
+    fn flush() {
+        #[cfg(feature = "irq")]
+        {
+            let _uart = UART.lock();
+            TX_RING.flush(&mut UartTx);
+            UartTx.set_irq_enabled(false);
+        }
+    }
+
+    /// Writes given bytes to the console synchronously, without taking any
+    /// lock.
+    fn write_bytes_nolock(bytes: &[u8]) {
+        #[cfg(feature = "irq")]
+        TX_RING.flush(&mut UartTx);
+        for &c in bytes {
+            if c == b'\n' {
+                UartTx.send_blocking(b'\r');
             }
+            UartTx.send_blocking(c);
         }
-        bytes.len()
     }
 
     #[cfg(feature = "irq")]
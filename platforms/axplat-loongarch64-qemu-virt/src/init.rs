@@ -8,14 +8,22 @@ impl InitIf for InitIfImpl {
     /// and performed earliest platform configuration and initialization (e.g.,
     /// early console, clocking).
     fn init_early(_cpu_id: usize, _mbi: usize) {
+        #[cfg(feature = "smp")]
+        crate::mp::set_cpu_state(_cpu_id, axplat::power::CpuState::On);
         axcpu::init::init_trap();
         crate::console::init_early();
+        #[cfg(feature = "pstore")]
+        axplat::pstore::init(
+            axplat::mem::phys_to_virt(axplat::mem::pa!(crate::config::plat::PSTORE_PADDR)),
+            crate::config::plat::PSTORE_SIZE,
+        );
         crate::time::init_early();
     }
 
     /// Initializes the platform at the early stage for secondary cores.
     #[cfg(feature = "smp")]
-    fn init_early_secondary(_cpu_id: usize) {
+    fn init_early_secondary(cpu_id: usize) {
+        crate::mp::set_cpu_state(cpu_id, axplat::power::CpuState::On);
         axcpu::init::init_trap();
     }
 
@@ -25,14 +33,22 @@ impl InitIf for InitIfImpl {
     /// initialization (e.g, logging, memory management), and finalized the rest of
     /// platform configuration and initialization.
     fn init_later(_cpu_id: usize, _arg: usize) {
+        // The device tree is parsed before the logger is ready.
+        crate::mem::MEM.log_warnings();
         #[cfg(feature = "irq")]
-        crate::irq::init();
+        {
+            crate::irq::init();
+            crate::irq::init_percpu();
+            crate::console::init_irq();
+        }
         crate::time::init_percpu();
     }
 
     /// Initializes the platform at the later stage for secondary cores.
     #[cfg(feature = "smp")]
     fn init_later_secondary(_cpu_id: usize) {
+        #[cfg(feature = "irq")]
+        crate::irq::init_percpu();
         crate::time::init_percpu();
     }
 }
@@ -1,22 +1,66 @@
-use axplat::irq::{HandlerTable, IpiTarget, IrqHandler, IrqIf};
+#[cfg(feature = "alloc")]
+use axplat::irq::BoxedIrqHandler;
+use axplat::irq::{
+    CpuMask, HandlerTable, IpiTarget, IrqContextHandler, IrqHandler, IrqIf, MsiMessage,
+    MsiVectorAllocator, Polarity, SharedIrqHandler, TriggerMode,
+};
+#[cfg(feature = "irq-stats")]
+use axplat::irq::{IrqCounters, IrqStats};
+use loongArch64::iocsr::{iocsr_read_w, iocsr_write_w};
+use loongArch64::ipi::send_ipi_single;
 use loongArch64::register::{
     ecfg::{self, LineBasedInterrupt},
     ticlr,
 };
 
-use crate::config::devices::{PLATIC_IRQ, TIMER_IRQ};
+use axplat::mem::pa;
+
+use crate::config::devices::{IPI_IRQ, PCH_MSI_PADDR, PLATIC_IRQ, TIMER_IRQ};
+use crate::config::plat::CPU_NUM;
 
 mod eiointc;
 mod platic;
 
-/// The maximum number of IRQs.
-pub const MAX_IRQ_COUNT: usize = 0x20;
+/// The maximum number of IRQs, i.e., the number of EIOINTC vectors.
+pub const MAX_IRQ_COUNT: usize = 0x100;
+
+/// The first IRQ of MSIs. The IRQs below it are from the platform interrupt
+/// controller (PCH-PIC).
+const MSI_IRQ_BASE: usize = 0x40;
+const MSI_VECTOR_COUNT: usize = MAX_IRQ_COUNT - MSI_IRQ_BASE;
+
+/// The IPI action (bit of the IPI status) used for [`IrqIf::send_ipi`]. Bit 0
+/// is used to start secondary CPUs.
+const ACTION_IPI: u32 = 1 << 1;
+
+const LA_IOCSR_IPI_STATUS: usize = 0x1000;
+const LA_IOCSR_IPI_EN: usize = 0x1004;
+const LA_IOCSR_IPI_CLEAR: usize = 0x100c;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+static MSI_VECTORS: MsiVectorAllocator<MSI_VECTOR_COUNT> = MsiVectorAllocator::new();
+
+// CPU masks of IRQ affinities and IPIs can hold at most `CpuMask::MAX_CPUS` CPUs.
+const _: () = assert!(CPU_NUM <= CpuMask::MAX_CPUS);
+
+#[cfg(feature = "irq-stats")]
+static IRQ_STATS: IrqStats<MAX_IRQ_COUNT, CPU_NUM> = IrqStats::new();
+
+fn current_cpu_id() -> usize {
+    loongArch64::register::cpuid::read().core_id()
+}
+
 pub(crate) fn init() {
     eiointc::init();
     platic::init();
+    axplat::ipi::init(IPI_IRQ);
+    axplat::time::init_timer_irq(TIMER_IRQ);
+}
+
+/// Enables the IPI of the current CPU.
+pub(crate) fn init_percpu() {
+    IrqIfImpl::set_enable(IPI_IRQ, true);
 }
 
 struct IrqIfImpl;
@@ -24,23 +68,58 @@ struct IrqIfImpl;
 #[impl_plat_interface]
 impl IrqIf for IrqIfImpl {
     /// Enables or disables the given IRQ.
+    ///
+    /// The timer interrupt and the IPI are enabled or disabled on the current
+    /// CPU only.
     fn set_enable(irq_num: usize, enabled: bool) {
-        if irq_num == TIMER_IRQ {
+        if irq_num == TIMER_IRQ || irq_num == IPI_IRQ {
+            let line = if irq_num == TIMER_IRQ {
+                LineBasedInterrupt::TIMER
+            } else {
+                iocsr_write_w(LA_IOCSR_IPI_EN, if enabled { ACTION_IPI } else { 0 });
+                LineBasedInterrupt::IPI
+            };
             let old_value = ecfg::read().lie();
             let new_value = match enabled {
-                true => old_value | LineBasedInterrupt::TIMER,
-                false => old_value & !LineBasedInterrupt::TIMER,
+                true => old_value | line,
+                false => old_value & !line,
             };
             ecfg::set_lie(new_value);
         } else if enabled {
             eiointc::enable_irq(irq_num);
-            platic::enable_irq(irq_num);
+            if irq_num < MSI_IRQ_BASE {
+                platic::enable_irq(irq_num);
+            }
         } else {
             eiointc::disable_irq(irq_num);
-            platic::disable_irq(irq_num);
+            if irq_num < MSI_IRQ_BASE {
+                platic::disable_irq(irq_num);
+            }
         }
     }
 
+    /// Sets the trigger mode and polarity of the given IRQ.
+    ///
+    /// It is only supported by the IRQs from the platform interrupt controller.
+    fn set_trigger(irq_num: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+        irq_num != TIMER_IRQ && irq_num != IPI_IRQ && platic::set_trigger(irq_num, mode, polarity)
+    }
+
+    /// Sets the priority of the given IRQ.
+    ///
+    /// It is not supported by the interrupt controllers.
+    fn set_priority(_irq_num: usize, _priority: u8) -> bool {
+        false
+    }
+
+    /// Sets the CPUs that the given IRQ can be delivered to.
+    ///
+    /// It is only supported by the IRQs from the platform interrupt controller,
+    /// and only the first 4 CPUs can be used.
+    fn set_affinity(irq_num: usize, cpu_mask: CpuMask) -> bool {
+        irq_num != TIMER_IRQ && irq_num != IPI_IRQ && eiointc::set_affinity(irq_num, cpu_mask)
+    }
+
     /// Registers an IRQ handler for the given IRQ.
     fn register(irq_num: usize, handler: IrqHandler) -> bool {
         if IRQ_HANDLER_TABLE.register_handler(irq_num, handler) {
@@ -50,6 +129,25 @@ impl IrqIf for IrqIfImpl {
         false
     }
 
+    /// Registers an IRQ handler with a context pointer for the given IRQ.
+    fn register_with_context(irq_num: usize, handler: IrqContextHandler, context: *mut ()) -> bool {
+        if IRQ_HANDLER_TABLE.register_handler_with_context(irq_num, handler, context) {
+            Self::set_enable(irq_num, true);
+            return true;
+        }
+        false
+    }
+
+    /// Registers a boxed closure as the IRQ handler for the given IRQ.
+    #[cfg(feature = "alloc")]
+    fn register_boxed(irq_num: usize, handler: BoxedIrqHandler) -> bool {
+        if IRQ_HANDLER_TABLE.register_boxed_handler(irq_num, handler) {
+            Self::set_enable(irq_num, true);
+            return true;
+        }
+        false
+    }
+
     /// Unregisters the IRQ handler for the given IRQ.
     ///
     /// It also disables the IRQ if the unregistration succeeds. It returns the
@@ -59,29 +157,114 @@ impl IrqIf for IrqIfImpl {
         IRQ_HANDLER_TABLE.unregister_handler(irq)
     }
 
+    /// Adds a handler to the shared IRQ line.
+    fn register_shared(irq_num: usize, handler: SharedIrqHandler, context: *mut ()) -> bool {
+        if IRQ_HANDLER_TABLE.register_shared_handler(irq_num, handler, context) {
+            Self::set_enable(irq_num, true);
+            return true;
+        }
+        false
+    }
+
+    /// Removes the handler registered with the given context pointer from the
+    /// shared IRQ line.
+    fn unregister_shared(irq_num: usize, context: *mut ()) -> bool {
+        if !IRQ_HANDLER_TABLE.unregister_shared_handler(irq_num, context) {
+            return false;
+        }
+        if !IRQ_HANDLER_TABLE.has_handler(irq_num) {
+            Self::set_enable(irq_num, false);
+        }
+        true
+    }
+
+    /// Allocates an MSI vector that is delivered to the given CPU.
+    ///
+    /// MSIs are translated by the PCH-MSI into EIOINTC vectors, which can only
+    /// be routed to the first 4 CPUs.
+    fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+        let irq_num = MSI_IRQ_BASE + MSI_VECTORS.alloc(MSI_VECTOR_COUNT)?;
+        if cpu_id >= CpuMask::MAX_CPUS || !eiointc::set_affinity(irq_num, CpuMask::one(cpu_id)) {
+            MSI_VECTORS.free(irq_num - MSI_IRQ_BASE);
+            return None;
+        }
+        let message = MsiMessage {
+            address: pa!(PCH_MSI_PADDR),
+            data: irq_num as u32,
+        };
+        Some((irq_num, message))
+    }
+
+    /// Frees an MSI vector allocated by [`alloc_msi`](IrqIf::alloc_msi).
+    fn free_msi(irq_num: usize) -> bool {
+        irq_num
+            .checked_sub(MSI_IRQ_BASE)
+            .is_some_and(|idx| MSI_VECTORS.free(idx))
+    }
+
     /// Handles the IRQ.
     ///
     /// It is called by the common interrupt handler. It should look up in the
     /// IRQ handler table and calls the corresponding handler. If necessary, it
     /// also acknowledges the interrupt controller after handling.
     fn handle(irq: usize) {
-        if irq == crate::config::devices::TIMER_IRQ {
-            ticlr::clear_timer_interrupt();
-        } else if irq == PLATIC_IRQ {
-            if let Some(irq) = eiointc::claim_irq() {
-                IRQ_HANDLER_TABLE.handle(irq);
-                eiointc::complete_irq(irq);
+        if irq == PLATIC_IRQ {
+            match eiointc::claim_irq() {
+                Some(irq) => {
+                    let handled = IRQ_HANDLER_TABLE.handle(irq);
+                    #[cfg(feature = "irq-stats")]
+                    IRQ_STATS.record(irq, current_cpu_id(), handled);
+                    if !handled {
+                        warn!("Unhandled IRQ {irq}");
+                    }
+                    eiointc::complete_irq(irq);
+                }
+                None => {
+                    #[cfg(feature = "irq-stats")]
+                    IRQ_STATS.record_spurious(current_cpu_id());
+                }
             }
             return;
         }
         trace!("IRQ {irq}");
-        if !IRQ_HANDLER_TABLE.handle(irq) {
+        let mut called = false;
+        if irq == TIMER_IRQ {
+            ticlr::clear_timer_interrupt();
+            // Expired software timers are run before the registered timer
+            // handler.
+            called = axplat::time::handle_timer_irq(current_cpu_id());
+        } else if irq == IPI_IRQ {
+            let status = iocsr_read_w(LA_IOCSR_IPI_STATUS);
+            iocsr_write_w(LA_IOCSR_IPI_CLEAR, status);
+            // Cross-CPU calls are run before the registered IPI handler.
+            called = axplat::ipi::handle_calls(current_cpu_id());
+        }
+        let handled = IRQ_HANDLER_TABLE.handle(irq) || called;
+        #[cfg(feature = "irq-stats")]
+        IRQ_STATS.record(irq, current_cpu_id(), handled);
+        if !handled {
             warn!("Unhandled IRQ {irq}");
         }
     }
 
     /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
-    fn send_ipi(_irq_num: usize, _target: IpiTarget) {
-        todo!()
+    ///
+    /// The IPI is always [`IPI_IRQ`], as it is the only one of LoongArch.
+    fn send_ipi(_irq_num: usize, target: IpiTarget) {
+        for cpu_id in target.cpu_mask().iter() {
+            send_ipi_single(cpu_id, ACTION_IPI);
+        }
+    }
+
+    /// Takes a snapshot of the interrupt statistics.
+    #[cfg(feature = "irq-stats")]
+    fn irq_stats(f: &mut dyn FnMut(usize, usize, IrqCounters)) {
+        IRQ_STATS.for_each(f);
+    }
+
+    /// Returns the number of spurious interrupts on the given CPU.
+    #[cfg(feature = "irq-stats")]
+    fn spurious_irq_count(cpu_id: usize) -> u64 {
+        IRQ_STATS.spurious(cpu_id)
     }
 }
@@ -1,6 +1,7 @@
 // Ref: https://elixir.bootlin.com/linux/v6.16/source/drivers/irqchip/irq-loongson-eiointc.c
 
-use loongArch64::iocsr::{iocsr_read_d, iocsr_write_d, iocsr_write_w};
+use axplat::irq::CpuMask;
+use loongArch64::iocsr::{iocsr_read_d, iocsr_read_w, iocsr_write_d, iocsr_write_w};
 
 const LOONGARCH_IOCSR_MISC_FUNC: usize = 0x420;
 const IOCSR_MISC_FUNC_EXT_IOI_EN: u64 = 1 << 48;
@@ -16,6 +17,9 @@ const VEC_REG_COUNT: usize = 4;
 const VEC_COUNT_PER_REG: usize = 64;
 const VEC_COUNT: usize = VEC_REG_COUNT * VEC_COUNT_PER_REG;
 
+/// Number of cores in an EIOINTC node.
+const CORES_PER_NODE: usize = 4;
+
 pub fn init() {
     // TODO: support smp
     let misc = iocsr_read_d(LOONGARCH_IOCSR_MISC_FUNC);
@@ -72,3 +76,21 @@ pub fn complete_irq(irq: usize) {
     let (offset, bit) = split_bit(irq);
     iocsr_write_d(EIOINTC_REG_ISR + offset, bit);
 }
+
+/// Routes the given IRQ to the cores in `cpu_mask`.
+///
+/// Only the cores in node 0 are supported. If there are multiple cores, the
+/// IRQ is rotated among them (bounce).
+pub fn set_affinity(irq: usize, cpu_mask: CpuMask) -> bool {
+    let core_map = cpu_mask.bits();
+    if irq >= VEC_COUNT || core_map == 0 || core_map >> CORES_PER_NODE != 0 {
+        return false;
+    }
+    // Each vector has a route byte, with the node map in the upper 4 bits and
+    // the core map in the lower 4 bits.
+    let addr = EIOINTC_REG_ROUTE + irq / 4 * 4;
+    let shift = irq % 4 * 8;
+    let route = (iocsr_read_w(addr) & !(0xf << shift)) | ((core_map as u32) << shift);
+    iocsr_write_w(addr, route);
+    true
+}
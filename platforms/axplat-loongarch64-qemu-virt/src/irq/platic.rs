@@ -1,5 +1,7 @@
 // Ref: https://elixir.bootlin.com/linux/v6.16/source/drivers/irqchip/irq-loongson-pch-pic.c
 
+use axplat::irq::{Polarity, TriggerMode};
+
 use crate::config::{devices::PLATIC_PADDR, plat::PHYS_VIRT_OFFSET};
 
 const PIC_COUNT_PER_REG: usize = 32;
@@ -49,3 +51,20 @@ pub fn disable_irq(irq: usize) {
     let addr = PCH_PIC_MASK + offset;
     write_w(addr, read_w(addr) | bit);
 }
+
+/// Sets the trigger mode and polarity of the given IRQ.
+pub fn set_trigger(irq: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+    if irq >= PIC_COUNT_PER_REG * PIC_REG_COUNT {
+        return false;
+    }
+    let (offset, bit) = split_bit(irq);
+    for (reg, set) in [
+        (PCH_PIC_EDGE, mode == TriggerMode::Edge),
+        (PCH_PIC_POL, polarity == Polarity::Low),
+    ] {
+        let addr = reg + offset;
+        let old = read_w(addr);
+        write_w(addr, if set { old | bit } else { old & !bit });
+    }
+    true
+}
@@ -21,6 +21,7 @@ pub mod config {
 }
 
 mod boot;
+mod boot_info;
 mod console;
 mod init;
 #[cfg(feature = "irq")]
@@ -1,3 +1,4 @@
+use axplat::fdt::{MemConfig, PlatformMem};
 use axplat::mem::{MemIf, PhysAddr, RawRange, VirtAddr, pa, va};
 
 use crate::config::devices::MMIO_RANGES;
@@ -5,6 +6,43 @@ use crate::config::plat::{
     HIGH_MEMORY_BASE, LOW_MEMORY_BASE, LOW_MEMORY_SIZE, PHYS_BOOT_OFFSET, PHYS_MEMORY_SIZE,
     PHYS_VIRT_OFFSET,
 };
+#[cfg(feature = "pstore")]
+use crate::config::plat::{PSTORE_PADDR, PSTORE_SIZE};
+
+/// Memory used by the boot information and the device tree.
+const BOOT_INFO_RANGE: RawRange = (0, 0x200000);
+
+/// Maximum number of memory ranges of each kind discovered from the device
+/// tree.
+const MAX_MEM_RANGES: usize = 32;
+
+const HIGH_MEMORY_SIZE: usize = PHYS_MEMORY_SIZE.saturating_sub(LOW_MEMORY_SIZE);
+
+/// RAM ranges used if the device tree is not available.
+const RAM_RANGES: &[RawRange] = if HIGH_MEMORY_SIZE == 0 {
+    &[(LOW_MEMORY_BASE, PHYS_MEMORY_SIZE)]
+} else {
+    &[
+        (LOW_MEMORY_BASE, LOW_MEMORY_SIZE),
+        (HIGH_MEMORY_BASE, HIGH_MEMORY_SIZE),
+    ]
+};
+
+/// Reserved ranges that are not described in the device tree.
+#[cfg(feature = "pstore")]
+const RESERVED_RANGES: &[RawRange] = &[BOOT_INFO_RANGE, (PSTORE_PADDR, PSTORE_SIZE)];
+#[cfg(not(feature = "pstore"))]
+const RESERVED_RANGES: &[RawRange] = &[BOOT_INFO_RANGE];
+
+/// Memory layout of the platform, which is discovered from the device tree
+/// passed by the bootloader.
+pub(crate) static MEM: PlatformMem<MAX_MEM_RANGES> = PlatformMem::new(MemConfig {
+    ram: RAM_RANGES,
+    reserved: RESERVED_RANGES,
+    mmio: &MMIO_RANGES,
+    excluded_ram: &[],
+    fdt_range: None,
+});
 
 struct MemIfImpl;
 
@@ -15,16 +53,7 @@ impl MemIf for MemIfImpl {
     /// All memory ranges except reserved ranges (including the kernel loaded
     /// range) are free for allocation.
     fn phys_ram_ranges() -> &'static [RawRange] {
-        const HIGH_MEMORY_SIZE: usize = PHYS_MEMORY_SIZE.saturating_sub(LOW_MEMORY_SIZE);
-
-        if HIGH_MEMORY_SIZE == 0 {
-            &[(LOW_MEMORY_BASE, PHYS_MEMORY_SIZE)]
-        } else {
-            &[
-                (LOW_MEMORY_BASE, LOW_MEMORY_SIZE),
-                (HIGH_MEMORY_BASE, HIGH_MEMORY_SIZE),
-            ]
-        }
+        MEM.ram_ranges()
     }
 
     /// Returns all reserved physical memory ranges on the platform.
@@ -35,12 +64,12 @@ impl MemIf for MemIfImpl {
     /// Note that the ranges returned should not include the range where the
     /// kernel is loaded.
     fn reserved_phys_ram_ranges() -> &'static [RawRange] {
-        &[(0, 0x200000)] // boot_info + fdt
+        MEM.reserved_ranges()
     }
 
     /// Returns all device memory (MMIO) ranges on the platform.
     fn mmio_ranges() -> &'static [RawRange] {
-        &MMIO_RANGES
+        MEM.mmio_ranges()
     }
 
     /// Translates a physical address to a virtual address.
@@ -1,12 +1,24 @@
 use axplat::mem::PhysAddr;
+use axplat::power::{CpuState, CpuStates};
+use loongArch64::iocsr::{iocsr_read_d, iocsr_read_w, iocsr_write_w};
 use loongArch64::ipi::{csr_mail_send, send_ipi_single};
 
-use crate::config::plat::{PHYS_BOOT_OFFSET, PHYS_VIRT_OFFSET};
+use crate::config::plat::{CPU_NUM, PHYS_BOOT_OFFSET, PHYS_VIRT_OFFSET};
 
 const ACTION_BOOT_CPU: u32 = 1;
 
+const LA_IOCSR_IPI_STATUS: usize = 0x1000;
+const LA_IOCSR_IPI_CLEAR: usize = 0x100c;
+const LA_IOCSR_MAIL_BUF0: usize = 0x1020;
+
+/// Power states of the CPUs.
+static CPU_STATES: CpuStates<CPU_NUM> = CpuStates::new();
+
 /// Starts the given secondary CPU with its boot stack.
+///
+/// It also works for a CPU that has been stopped by [`stop_current_cpu`].
 pub fn start_secondary_cpu(cpu_id: usize, stack_top: PhysAddr) {
+    set_cpu_state(cpu_id, CpuState::Pending);
     let entry = crate::boot::_start_secondary as usize - PHYS_VIRT_OFFSET + PHYS_BOOT_OFFSET;
     csr_mail_send(entry as _, cpu_id, 0);
 
@@ -15,3 +27,32 @@ pub fn start_secondary_cpu(cpu_id: usize, stack_top: PhysAddr) {
 
     send_ipi_single(cpu_id, ACTION_BOOT_CPU);
 }
+
+/// Records the power state of the given CPU.
+pub fn set_cpu_state(cpu_id: usize, state: CpuState) {
+    CPU_STATES.set(cpu_id, state);
+}
+
+/// Returns the power state of the given CPU.
+pub fn cpu_state(cpu_id: usize) -> CpuState {
+    CPU_STATES.get(cpu_id)
+}
+
+/// Parks the current CPU until it is started again by
+/// [`start_secondary_cpu`].
+///
+/// Like the firmware does at boot, it polls the IPI status with interrupts
+/// disabled, then jumps to the entry in the mailbox 0.
+pub fn stop_current_cpu() -> ! {
+    axcpu::asm::disable_irqs();
+    iocsr_write_w(LA_IOCSR_IPI_CLEAR, u32::MAX);
+    let cpu_id = loongArch64::register::cpuid::read().core_id();
+    set_cpu_state(cpu_id, CpuState::Off);
+
+    while iocsr_read_w(LA_IOCSR_IPI_STATUS) & ACTION_BOOT_CPU == 0 {
+        core::hint::spin_loop();
+    }
+    iocsr_write_w(LA_IOCSR_IPI_CLEAR, u32::MAX);
+    let entry = iocsr_read_d(LA_IOCSR_MAIL_BUF0) as usize;
+    unsafe { core::arch::asm!("jirl $zero, {}, 0", in(reg) entry, options(noreturn)) }
+}
@@ -1,8 +1,15 @@
 use axplat::mem::{pa, phys_to_virt};
-use axplat::power::PowerIf;
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
 
 use crate::config::devices::GED_PADDR;
 
+/// Offset of the reset register from the GED registers.
+const GED_REG_RESET: usize = 0x02;
+/// Value to write to the reset register to reset the system.
+const GED_RESET_VALUE: u8 = 0x42;
+
 struct PowerImpl;
 
 #[impl_plat_interface]
@@ -17,6 +24,35 @@ impl PowerIf for PowerImpl {
         crate::mp::start_secondary_cpu(cpu_id, pa!(stack_top_paddr));
     }
 
+    /// Powers off the current CPU core.
+    ///
+    /// The core is parked with interrupts disabled, until it is woken up again
+    /// by [`cpu_boot`](PowerIf::cpu_boot).
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        crate::mp::stop_current_cpu()
+    }
+
+    /// Returns the power state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        crate::mp::cpu_state(cpu_id)
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    ///
+    /// Only [`SuspendState::Standby`] is supported, which is entered by
+    /// `idle`.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        match state {
+            SuspendState::Standby => {
+                axcpu::asm::halt();
+                true
+            }
+            SuspendState::PowerDown => false,
+        }
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         let halt_addr = phys_to_virt(pa!(GED_PADDR)).as_mut_ptr();
@@ -29,4 +65,19 @@ impl PowerIf for PowerImpl {
             axcpu::asm::halt();
         }
     }
+
+    /// Resets (reboots) the whole system.
+    ///
+    /// Only cold reset is supported.
+    fn system_reset(_kind: ResetKind) -> ! {
+        let reset_addr = phys_to_virt(pa!(GED_PADDR + GED_REG_RESET)).as_mut_ptr();
+
+        info!("Resetting the system...");
+        unsafe { reset_addr.write_volatile(GED_RESET_VALUE) };
+        axcpu::asm::halt();
+        warn!("It should reset!");
+        loop {
+            axcpu::asm::halt();
+        }
+    }
 }
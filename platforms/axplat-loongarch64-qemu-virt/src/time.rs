@@ -1,11 +1,14 @@
-use axplat::time::TimeIf;
-use lazyinit::LazyInit;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axplat::time::{TickConverter, TimeIf};
 use loongArch64::time::Time;
 
-static NANOS_PER_TICK: LazyInit<u64> = LazyInit::new();
+/// Converts everything to zero until the frequency is read in `init_early`,
+/// so that the time can be read (e.g., by the logger) before that.
+static mut TICK_CONVERTER: TickConverter = TickConverter::zero();
 
 /// RTC wall time offset in nanoseconds at monotonic time base.
-static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
+static RTC_EPOCHOFFSET_NANOS: AtomicU64 = AtomicU64::new(0);
 
 pub(super) fn init_percpu() {
     #[cfg(feature = "irq")]
@@ -71,16 +74,44 @@ fn init_rtc() {
         .unwrap();
 
     if let Some(epoch_time_nanos) = date_time.timestamp_nanos_opt() {
-        unsafe {
-            RTC_EPOCHOFFSET_NANOS =
-                epoch_time_nanos as u64 - TimeIfImpl::ticks_to_nanos(TimeIfImpl::current_ticks());
-        }
+        RTC_EPOCHOFFSET_NANOS.store(
+            epoch_time_nanos as u64 - TimeIfImpl::ticks_to_nanos(TimeIfImpl::current_ticks()),
+            Ordering::Release,
+        );
+    }
+}
+
+/// Writes the given time in seconds since the epoch to the TOY counter of the
+/// RTC.
+#[cfg(feature = "rtc")]
+fn write_rtc(unix_secs: u64) {
+    use axplat::mem::{pa, phys_to_virt};
+    use chrono::{DateTime, Datelike, Timelike};
+
+    const SYS_TOY_WRITE0: usize = 0x24;
+    const SYS_TOY_WRITE1: usize = 0x28;
+
+    let Some(date_time) = DateTime::from_timestamp(unix_secs as i64, 0) else {
+        return;
+    };
+    // The same layout as the TOY counter read in `init_rtc`.
+    let toy_low = (date_time.month() << 26)
+        | (date_time.day() << 21)
+        | (date_time.hour() << 16)
+        | (date_time.minute() << 10)
+        | (date_time.second() << 4);
+    let toy_high = (date_time.year() - 1900) as u32;
+
+    let rtc_base_ptr = phys_to_virt(pa!(crate::config::devices::RTC_PADDR)).as_mut_ptr();
+    unsafe {
+        (rtc_base_ptr.add(SYS_TOY_WRITE0) as *mut u32).write_volatile(toy_low);
+        (rtc_base_ptr.add(SYS_TOY_WRITE1) as *mut u32).write_volatile(toy_high);
     }
 }
 
 pub(super) fn init_early() {
-    NANOS_PER_TICK
-        .init_once(axplat::time::NANOS_PER_SEC / loongArch64::time::get_timer_freq() as u64);
+    let freq = loongArch64::time::get_timer_freq() as u64;
+    unsafe { TICK_CONVERTER = TickConverter::new(freq) };
 
     #[cfg(feature = "rtc")]
     init_rtc();
@@ -97,17 +128,29 @@ impl TimeIf for TimeIfImpl {
 
     /// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
     fn epochoffset_nanos() -> u64 {
-        unsafe { RTC_EPOCHOFFSET_NANOS }
+        RTC_EPOCHOFFSET_NANOS.load(Ordering::Acquire)
+    }
+
+    /// Set epoch offset in nanoseconds, and write the new wall time back to
+    /// the TOY counter of the RTC.
+    fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+        RTC_EPOCHOFFSET_NANOS.store(epochoffset_nanos, Ordering::Release);
+
+        #[cfg(feature = "rtc")]
+        {
+            let wall_time_nanos = axplat::time::wall_time_nanos();
+            write_rtc(wall_time_nanos / axplat::time::NANOS_PER_SEC);
+        }
     }
 
     /// Converts hardware ticks to nanoseconds.
     fn ticks_to_nanos(ticks: u64) -> u64 {
-        ticks * *NANOS_PER_TICK
+        unsafe { TICK_CONVERTER }.ticks_to_nanos(ticks)
     }
 
     /// Converts nanoseconds to hardware ticks.
     fn nanos_to_ticks(nanos: u64) -> u64 {
-        nanos / *NANOS_PER_TICK
+        unsafe { TICK_CONVERTER }.nanos_to_ticks(nanos)
     }
 
     /// Set a one-shot timer.
@@ -121,8 +164,42 @@ impl TimeIf for TimeIfImpl {
 
         let ticks_now = Self::current_ticks();
         let ticks_deadline = Self::nanos_to_ticks(deadline_ns);
-        let init_value = ticks_deadline - ticks_now;
+        let init_value = ticks_deadline.saturating_sub(ticks_now);
         tcfg::set_init_val(init_value as _);
+        tcfg::set_periodic(false);
+        tcfg::set_en(true);
+    }
+
+    /// Set a periodic timer.
+    ///
+    /// The counter is reloaded with the period when it reaches zero, as the
+    /// periodic bit in TCFG is set.
+    #[cfg(feature = "irq")]
+    fn set_periodic_timer(period_ns: u64) -> bool {
+        use loongArch64::register::tcfg;
+
+        tcfg::set_init_val(Self::nanos_to_ticks(period_ns) as _);
+        tcfg::set_periodic(true);
         tcfg::set_en(true);
+        true
+    }
+
+    /// Cancel the pending timer on the current CPU.
+    #[cfg(feature = "irq")]
+    fn cancel_timer() {
+        loongArch64::register::tcfg::set_en(false);
     }
+
+    /// Set an RTC alarm at the specified wall time (in nanoseconds since
+    /// epoch).
+    ///
+    /// It is not supported yet.
+    #[cfg(feature = "irq")]
+    fn set_rtc_alarm(_wall_time_ns: u64) -> bool {
+        false
+    }
+
+    /// Cancel the pending RTC alarm, if any.
+    #[cfg(feature = "irq")]
+    fn cancel_rtc_alarm() {}
 }
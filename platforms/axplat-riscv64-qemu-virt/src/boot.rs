@@ -91,3 +91,28 @@ pub(crate) unsafe extern "C" fn _start_secondary() -> ! {
         entry = sym axplat::call_secondary_main,
     )
 }
+
+/// The entry point for the harts resuming from the non-retentive suspend.
+#[unsafe(naked)]
+#[unsafe(link_section = ".text.boot")]
+pub(crate) unsafe extern "C" fn _start_resume() -> ! {
+    // a0 = hartid
+    // a1 = saved context (physical address)
+    core::arch::naked_asm!("
+        mv      s0, a1                  // save the context
+        mv      sp, a1                  // use the space below the context as the stack
+
+        call    {init_mmu}              // setup boot page table and enabel MMU
+
+        li      s1, {phys_virt_offset}  // fix up virtual high address
+        add     sp, sp, s1
+
+        add     a0, s0, s1
+        la      a1, {entry}
+        add     a1, a1, s1
+        jr      a1                      // cpu_resume(ctx)",
+        phys_virt_offset = const PHYS_VIRT_OFFSET,
+        init_mmu = sym init_mmu,
+        entry = sym crate::power::cpu_resume,
+    )
+}
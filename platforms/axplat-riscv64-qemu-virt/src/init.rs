@@ -10,6 +10,11 @@ impl InitIf for InitIfImpl {
     fn init_early(_cpu_id: usize, _mbi: usize) {
         axcpu::init::init_trap();
         crate::console::init_early();
+        #[cfg(feature = "pstore")]
+        axplat::pstore::init(
+            axplat::mem::phys_to_virt(axplat::mem::pa!(crate::config::plat::PSTORE_PADDR)),
+            crate::config::plat::PSTORE_SIZE,
+        );
         crate::time::init_early();
     }
 
@@ -25,10 +30,16 @@ impl InitIf for InitIfImpl {
     /// initialization (e.g, logging, memory management), and finalized the rest of
     /// platform configuration and initialization.
     fn init_later(_cpu_id: usize, _arg: usize) {
+        // The device tree is parsed before the logger is ready.
+        crate::mem::MEM.log_warnings();
         #[cfg(feature = "irq")]
         {
             crate::irq::init();
-            crate::irq::init_percpu();
+            crate::irq::init_percpu(_cpu_id);
+            axplat::ipi::init(crate::config::devices::IPI_IRQ);
+            axplat::time::init_timer_irq(crate::config::devices::TIMER_IRQ);
+            crate::console::init_irq();
+            crate::time::init_irq();
         }
         crate::time::init_percpu();
     }
@@ -37,7 +48,7 @@ impl InitIf for InitIfImpl {
     #[cfg(feature = "smp")]
     fn init_later_secondary(_cpu_id: usize) {
         #[cfg(feature = "irq")]
-        crate::irq::init_percpu();
+        crate::irq::init_percpu(_cpu_id);
         crate::time::init_percpu();
     }
 }
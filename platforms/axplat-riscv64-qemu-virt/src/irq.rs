@@ -1,13 +1,24 @@
 use crate::config::{
-    devices::PLIC_PADDR,
+    devices::{APLIC_PADDR, PLIC_PADDR},
     plat::{CPU_NUM, PHYS_VIRT_OFFSET},
 };
-use axplat::irq::{HandlerTable, IpiTarget, IrqHandler, IrqIf};
-use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "alloc")]
+use axplat::irq::BoxedIrqHandler;
+use axplat::irq::{
+    CpuMask, HandlerTable, IpiTarget, IrqContextHandler, IrqHandler, IrqIf, MsiMessage,
+    MsiVectorAllocator, Polarity, SharedIrqHandler, TriggerMode,
+};
+#[cfg(feature = "irq-stats")]
+use axplat::irq::{IrqCounters, IrqStats};
+use axplat::mem::pa;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicU64, Ordering};
 use plic::{Mode, PLIC};
-use riscv::register::sie;
+use riscv::register::{sie, sip};
 use sbi_rt::HartMask;
 
+mod aplic;
+mod imsic;
+
 /// `Interrupt` bit in `scause`
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
@@ -25,19 +36,113 @@ static TIMER_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
 
 static IPI_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
 
-/// The maximum number of IRQs.
+/// The maximum number of IRQs from the PLIC.
 pub const MAX_IRQ_COUNT: usize = 1024;
 
-static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
+/// The first IRQ of MSIs, where the IRQ number is it plus the IMSIC interrupt
+/// identity.
+///
+/// The wired IRQs forwarded by the APLIC keep their source numbers.
+const MSI_IRQ_BASE: usize = MAX_IRQ_COUNT;
+
+/// The number of device-side IRQs, including MSIs.
+const DEVICE_IRQ_COUNT: usize = MSI_IRQ_BASE + imsic::NUM_IDS;
+
+static IRQ_HANDLER_TABLE: HandlerTable<DEVICE_IRQ_COUNT> = HandlerTable::new();
+
+#[cfg(feature = "irq-stats")]
+static IRQ_STATS: IrqStats<DEVICE_IRQ_COUNT, CPU_NUM> = IrqStats::new();
+
+/// IMSIC interrupt identities of MSIs, indexed from [`aplic::NUM_SOURCES`].
+static MSI_VECTORS: MsiVectorAllocator<{ imsic::NUM_IDS - aplic::NUM_SOURCES }> =
+    MsiVectorAllocator::new();
+
+/// Statistics of the CPU-side IRQs, indexed by [`LOCAL_IRQS`].
+#[cfg(feature = "irq-stats")]
+static LOCAL_IRQ_STATS: IrqStats<2, CPU_NUM> = IrqStats::new();
+
+#[cfg(feature = "irq-stats")]
+const LOCAL_IRQS: [usize; 2] = [S_TIMER, S_SOFT];
 
 static PLIC: PLIC<CPU_NUM> = unsafe { PLIC::new(PHYS_VIRT_OFFSET + PLIC_PADDR, [2; CPU_NUM]) };
 
+// CPU masks of IRQ affinities and IPIs can hold at most `CpuMask::MAX_CPUS` CPUs.
+const _: () = assert!(CPU_NUM <= CpuMask::MAX_CPUS);
+
+// Wired IRQs are forwarded to the IMSIC by the APLIC, instead of the PLIC.
+const _: () = assert!(
+    !imsic::present() || APLIC_PADDR != 0,
+    "`aplic-paddr` must be set with `imsic-paddr`"
+);
+
+/// The maximum priority supported by the PLIC. Priority 0 means "never
+/// interrupt".
+const PLIC_MAX_PRIORITY: u32 = 7;
+
+/// Per-IRQ states, which are applied to the PLIC when the IRQ is enabled.
+static IRQ_ENABLED: [AtomicBool; MAX_IRQ_COUNT] = [const { AtomicBool::new(false) }; MAX_IRQ_COUNT];
+static IRQ_PRIORITY: [AtomicU8; MAX_IRQ_COUNT] = [const { AtomicU8::new(6) }; MAX_IRQ_COUNT];
+static IRQ_AFFINITY: [AtomicU64; MAX_IRQ_COUNT] =
+    [const { AtomicU64::new(CpuMask::full(CPU_NUM).bits()) }; MAX_IRQ_COUNT];
+
+/// Hart ID of the current CPU.
+///
+/// It is a per-CPU variable with `smp`, so the kernel must initialize the
+/// per-CPU data area before [`init_later`](axplat::init::init_later).
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static HART_ID: usize = 0;
+
+#[cfg(not(feature = "smp"))]
+static HART_ID: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+fn current_hart_id() -> usize {
+    #[cfg(feature = "smp")]
+    return unsafe { HART_ID.read_current_raw() };
+    #[cfg(not(feature = "smp"))]
+    return HART_ID.load(Ordering::Relaxed);
+}
+
+/// Initializes the PLIC.
+///
+/// The PLIC is replaced by the APLIC if the platform has an IMSIC, which
+/// forwards wired IRQs to the IMSIC as MSIs.
 pub(crate) fn init() {
+    if imsic::present() {
+        aplic::init();
+        return;
+    }
     for hart in 0..(CPU_NUM as u32) {
         PLIC.set_threshold(hart, Mode::Supervisor, 0);
     }
 }
 
+/// Applies the enable state, priority and affinity of the given device-side
+/// IRQ to the PLIC.
+///
+/// With the APLIC, the IRQ is delivered to the first hart of the affinity, and
+/// the priority is ignored.
+fn update_plic_irq(irq: usize) {
+    let enabled = IRQ_ENABLED[irq].load(Ordering::Acquire);
+    let affinity = CpuMask::from_bits(IRQ_AFFINITY[irq].load(Ordering::Acquire));
+    if imsic::present() {
+        if (1..aplic::NUM_SOURCES).contains(&irq) {
+            aplic::set_source(irq, enabled, affinity.first().unwrap_or(0));
+        }
+        return;
+    }
+    if enabled {
+        PLIC.set_priority(irq as _, IRQ_PRIORITY[irq].load(Ordering::Acquire) as _);
+    }
+    for hart in 0..CPU_NUM {
+        if enabled && affinity.contains(hart) {
+            PLIC.enable(hart as _, Mode::Supervisor, irq as _);
+        } else {
+            PLIC.disable(hart as _, Mode::Supervisor, irq as _);
+        }
+    }
+}
+
 macro_rules! with_cause {
     ($cause: expr, @S_TIMER => $timer_op: expr, @S_SOFT => $ipi_op: expr, @S_EXT => $ext_op: expr, @EX_IRQ => $plic_op: expr $(,)?) => {
         match $cause {
@@ -57,7 +162,18 @@ macro_rules! with_cause {
     };
 }
 
-pub(super) fn init_percpu() {
+pub(super) fn init_percpu(hart_id: usize) {
+    #[cfg(feature = "smp")]
+    unsafe {
+        HART_ID.write_current_raw(hart_id)
+    };
+    #[cfg(not(feature = "smp"))]
+    HART_ID.store(hart_id, Ordering::Relaxed);
+
+    if imsic::present() {
+        imsic::init_percpu();
+    }
+
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
         sie::set_ssoft();
@@ -86,20 +202,49 @@ impl IrqIf for IrqIfImpl {
             @S_SOFT => {},
             @S_EXT => {},
             @EX_IRQ => {
-                if enabled {
-                    PLIC.set_priority(irq as _, 6);
-                    for hart in 0..(CPU_NUM as u32) {
-                        PLIC.enable(hart, Mode::Supervisor, irq as _);
-                    }
-                } else {
-                    for hart in 0..(CPU_NUM as u32) {
-                        PLIC.disable(hart, Mode::Supervisor, irq as _);
-                    }
+                if irq < MAX_IRQ_COUNT {
+                    IRQ_ENABLED[irq].store(enabled, Ordering::Release);
+                    update_plic_irq(irq);
                 }
             }
         );
     }
 
+    /// Sets the trigger mode and polarity of the given IRQ.
+    ///
+    /// It is not supported, as the trigger mode of the PLIC gateways is fixed.
+    fn set_trigger(_irq: usize, _mode: TriggerMode, _polarity: Polarity) -> bool {
+        false
+    }
+
+    /// Sets the priority of the given device-side IRQ.
+    ///
+    /// `priority` is scaled to the PLIC priorities `1..=7`.
+    fn set_priority(irq: usize, priority: u8) -> bool {
+        if irq & INTC_IRQ_BASE != 0 || irq >= MAX_IRQ_COUNT {
+            return false;
+        }
+        let plic_priority = 1 + priority as u32 * (PLIC_MAX_PRIORITY - 1) / u8::MAX as u32;
+        IRQ_PRIORITY[irq].store(plic_priority as u8, Ordering::Release);
+        update_plic_irq(irq);
+        true
+    }
+
+    /// Sets the harts that the given device-side IRQ can be delivered to.
+    fn set_affinity(irq: usize, cpu_mask: CpuMask) -> bool {
+        let all_harts = CpuMask::full(CPU_NUM).bits();
+        if irq & INTC_IRQ_BASE != 0
+            || irq >= MAX_IRQ_COUNT
+            || cpu_mask.is_empty()
+            || cpu_mask.bits() & !all_harts != 0
+        {
+            return false;
+        }
+        IRQ_AFFINITY[irq].store(cpu_mask.bits(), Ordering::Release);
+        update_plic_irq(irq);
+        true
+    }
+
     /// Registers an IRQ handler for the given IRQ.
     ///
     /// It also enables the IRQ if the registration succeeds. It returns `false` if
@@ -133,6 +278,31 @@ impl IrqIf for IrqIfImpl {
         )
     }
 
+    /// Registers an IRQ handler with a context pointer for the given IRQ.
+    ///
+    /// Only device-side IRQs are supported.
+    fn register_with_context(irq: usize, handler: IrqContextHandler, context: *mut ()) -> bool {
+        if irq & INTC_IRQ_BASE == 0
+            && IRQ_HANDLER_TABLE.register_handler_with_context(irq, handler, context)
+        {
+            Self::set_enable(irq, true);
+            return true;
+        }
+        false
+    }
+
+    /// Registers a boxed closure as the IRQ handler for the given IRQ.
+    ///
+    /// Only device-side IRQs are supported.
+    #[cfg(feature = "alloc")]
+    fn register_boxed(irq: usize, handler: BoxedIrqHandler) -> bool {
+        if irq & INTC_IRQ_BASE == 0 && IRQ_HANDLER_TABLE.register_boxed_handler(irq, handler) {
+            Self::set_enable(irq, true);
+            return true;
+        }
+        false
+    }
+
     /// Unregisters the IRQ handler for the given IRQ.
     ///
     /// It also disables the IRQ if the unregistration succeeds. It returns the
@@ -160,10 +330,61 @@ impl IrqIf for IrqIfImpl {
                 warn!("External IRQ should be got from PLIC, not scause");
                 None
             },
-            @EX_IRQ => IRQ_HANDLER_TABLE.unregister_handler(irq).inspect(|_| Self::set_enable(irq, false))
+            @EX_IRQ => {
+                Self::set_enable(irq, false);
+                IRQ_HANDLER_TABLE.unregister_handler(irq)
+            }
         )
     }
 
+    /// Adds a handler to the shared IRQ line.
+    ///
+    /// Only device-side IRQs are supported.
+    fn register_shared(irq: usize, handler: SharedIrqHandler, context: *mut ()) -> bool {
+        if irq & INTC_IRQ_BASE == 0
+            && IRQ_HANDLER_TABLE.register_shared_handler(irq, handler, context)
+        {
+            Self::set_enable(irq, true);
+            return true;
+        }
+        false
+    }
+
+    /// Removes the handler registered with the given context pointer from the
+    /// shared IRQ line.
+    fn unregister_shared(irq: usize, context: *mut ()) -> bool {
+        if !IRQ_HANDLER_TABLE.unregister_shared_handler(irq, context) {
+            return false;
+        }
+        if !IRQ_HANDLER_TABLE.has_handler(irq) {
+            Self::set_enable(irq, false);
+        }
+        true
+    }
+
+    /// Allocates an MSI vector that is delivered to the given hart.
+    ///
+    /// It is backed by the IMSIC, and returns `None` if there is no IMSIC. The
+    /// interrupt identities are enabled on all harts, so
+    /// [`set_enable`](IrqIf::set_enable) has no effect on MSIs.
+    fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+        if !imsic::present() || cpu_id >= CPU_NUM {
+            return None;
+        }
+        let id = aplic::NUM_SOURCES + MSI_VECTORS.alloc(imsic::NUM_IDS - aplic::NUM_SOURCES)?;
+        let message = MsiMessage {
+            address: pa!(imsic::file_paddr(cpu_id)),
+            data: id as u32,
+        };
+        Some((MSI_IRQ_BASE + id, message))
+    }
+
+    /// Frees an MSI vector allocated by [`alloc_msi`](IrqIf::alloc_msi).
+    fn free_msi(irq: usize) -> bool {
+        irq.checked_sub(MSI_IRQ_BASE + aplic::NUM_SOURCES)
+            .is_some_and(|idx| MSI_VECTORS.free(idx))
+    }
+
     /// Handles the IRQ.
     ///
     /// It is called by the common interrupt handler. It should look up in the
@@ -174,27 +395,69 @@ impl IrqIf for IrqIfImpl {
             irq,
             @S_TIMER => {
                 trace!("IRQ: timer");
+                // Expired software timers are run before the registered timer
+                // handler.
+                let timed = axplat::time::handle_timer_irq(current_hart_id());
                 let handler = TIMER_HANDLER.load(Ordering::Acquire);
                 if !handler.is_null() {
                     // SAFETY: The handler is guaranteed to be a valid function pointer.
                     unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler)(irq) };
                 }
+                let handled = timed || !handler.is_null();
+                #[cfg(feature = "irq-stats")]
+                LOCAL_IRQ_STATS.record(0, current_hart_id(), handled);
+                if !handled {
+                    debug!("Unhandled timer IRQ");
+                }
             },
             @S_SOFT => {
                 trace!("IRQ: IPI");
+                // Clear the pending bit before running the cross-CPU calls, so
+                // that calls queued later raise the IPI again.
+                unsafe { sip::clear_ssoft() };
+                let called = axplat::ipi::handle_calls(current_hart_id());
                 let handler = IPI_HANDLER.load(Ordering::Acquire);
                 if !handler.is_null() {
                     // SAFETY: The handler is guaranteed to be a valid function pointer.
                     unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler)(irq) };
                 }
+                let handled = called || !handler.is_null();
+                #[cfg(feature = "irq-stats")]
+                LOCAL_IRQ_STATS.record(1, current_hart_id(), handled);
+                if !handled {
+                    debug!("Unhandled IPI");
+                }
             },
             @S_EXT => {
-                // TODO: hart
-                let irq = PLIC.claim(0, Mode::Supervisor);
-                if !IRQ_HANDLER_TABLE.handle(irq as _) {
+                let hart = current_hart_id();
+                let irq = if imsic::present() {
+                    match imsic::claim() {
+                        Some(id) if id < aplic::NUM_SOURCES => id,
+                        Some(id) => MSI_IRQ_BASE + id,
+                        None => 0,
+                    }
+                } else {
+                    PLIC.claim(hart as _, Mode::Supervisor) as usize
+                };
+                if irq == 0 {
+                    // No pending IRQ, e.g., it has been claimed by another hart.
+                    #[cfg(feature = "irq-stats")]
+                    IRQ_STATS.record_spurious(hart);
+                    return;
+                }
+                let handled = IRQ_HANDLER_TABLE.handle(irq);
+                #[cfg(feature = "irq-stats")]
+                IRQ_STATS.record(irq, hart, handled);
+                if !handled {
                     debug!("Unhandled IRQ {irq}");
                 }
-                PLIC.complete(0, Mode::Supervisor, irq);
+                if irq < MSI_IRQ_BASE {
+                    if imsic::present() {
+                        aplic::complete(irq);
+                    } else {
+                        PLIC.complete(hart as _, Mode::Supervisor, irq as _);
+                    }
+                }
             },
             @EX_IRQ => {
                 unreachable!("Device-side IRQs should be handled by triggering the External Interrupt.");
@@ -203,30 +466,29 @@ impl IrqIf for IrqIfImpl {
     }
 
     /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
+    ///
+    /// All target harts are sent by a single SBI call.
     fn send_ipi(_irq_num: usize, target: IpiTarget) {
-        match target {
-            IpiTarget::Current { cpu_id } => {
-                let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << cpu_id, 0));
-                if res.is_err() {
-                    warn!("send_ipi failed: {:?}", res);
-                }
-            }
-            IpiTarget::Other { cpu_id } => {
-                let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << cpu_id, 0));
-                if res.is_err() {
-                    warn!("send_ipi failed: {:?}", res);
-                }
-            }
-            IpiTarget::AllExceptCurrent { cpu_id, cpu_num } => {
-                for i in 0..cpu_num {
-                    if i != cpu_id {
-                        let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << i, 0));
-                        if res.is_err() {
-                            warn!("send_ipi_all_others failed: {:?}", res);
-                        }
-                    }
-                }
-            }
+        let cpu_mask = target.cpu_mask();
+        if cpu_mask.is_empty() {
+            return;
         }
+        let res = sbi_rt::send_ipi(HartMask::from_mask_base(cpu_mask.bits() as usize, 0));
+        if res.is_err() {
+            warn!("send_ipi failed: {:?}", res);
+        }
+    }
+
+    /// Takes a snapshot of the interrupt statistics.
+    #[cfg(feature = "irq-stats")]
+    fn irq_stats(f: &mut dyn FnMut(usize, usize, IrqCounters)) {
+        LOCAL_IRQ_STATS.for_each(&mut |idx, cpu_id, counters| f(LOCAL_IRQS[idx], cpu_id, counters));
+        IRQ_STATS.for_each(f);
+    }
+
+    /// Returns the number of spurious interrupts on the given CPU.
+    #[cfg(feature = "irq-stats")]
+    fn spurious_irq_count(cpu_id: usize) -> u64 {
+        IRQ_STATS.spurious(cpu_id)
     }
 }
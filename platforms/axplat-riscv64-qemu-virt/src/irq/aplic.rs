@@ -0,0 +1,63 @@
+// Ref: https://github.com/riscv/riscv-aia (Advanced Platform-Level Interrupt Controller)
+
+use axplat::mem::{pa, phys_to_virt};
+
+use crate::config::devices::APLIC_PADDR;
+
+/// Number of wired interrupt sources, where source 0 is reserved.
+///
+/// Source `n` is delivered as the IMSIC interrupt identity `n`.
+pub const NUM_SOURCES: usize = 96;
+
+// Domain registers of the supervisor-level interrupt domain.
+const DOMAINCFG: usize = 0x0000;
+const SOURCECFG_BASE: usize = 0x0004;
+const SETIENUM: usize = 0x1edc;
+const CLRIENUM: usize = 0x1fdc;
+const SETIPNUM_LE: usize = 0x2000;
+const TARGET_BASE: usize = 0x3004;
+
+const DOMAINCFG_IE: u32 = 1 << 8;
+const DOMAINCFG_DM_MSI: u32 = 1 << 2;
+
+/// Source mode "active-high level", which is what the devices of QEMU virt
+/// use.
+const SOURCECFG_SM_LEVEL1: u32 = 6;
+
+fn write_reg(offset: usize, value: u32) {
+    let base = phys_to_virt(pa!(APLIC_PADDR)).as_mut_ptr();
+    unsafe { (base.add(offset) as *mut u32).write_volatile(value) };
+}
+
+/// Initializes the supervisor-level interrupt domain in MSI delivery mode.
+///
+/// The sources are delegated to this domain, and the MSI address is set, by
+/// the SBI firmware. All sources are active-high level and disabled.
+pub fn init() {
+    write_reg(DOMAINCFG, DOMAINCFG_DM_MSI);
+    for source in 1..NUM_SOURCES {
+        write_reg(CLRIENUM, source as u32);
+        write_reg(SOURCECFG_BASE + (source - 1) * 4, SOURCECFG_SM_LEVEL1);
+    }
+    write_reg(DOMAINCFG, DOMAINCFG_IE | DOMAINCFG_DM_MSI);
+}
+
+/// Routes the given source to the interrupt file of the given hart, and
+/// enables or disables it.
+pub fn set_source(source: usize, enabled: bool, hart_id: usize) {
+    // The target of a source in MSI delivery mode is its hart index in bits
+    // 18..32 and its interrupt identity in bits 0..11.
+    write_reg(
+        TARGET_BASE + (source - 1) * 4,
+        ((hart_id as u32) << 18) | source as u32,
+    );
+    write_reg(if enabled { SETIENUM } else { CLRIENUM }, source as u32);
+}
+
+/// Completes the handling of the given source.
+///
+/// The pending bit of a level-sensitive source is cleared when its MSI is
+/// sent. Writing `setipnum_le` sets it again if the source is still asserted.
+pub fn complete(source: usize) {
+    write_reg(SETIPNUM_LE, source as u32);
+}
@@ -0,0 +1,58 @@
+// Ref: https://github.com/riscv/riscv-aia (Incoming MSI Controller)
+
+use core::arch::asm;
+
+use crate::config::devices::IMSIC_PADDR;
+
+/// Number of interrupt identities in use. Identity 0 is reserved.
+///
+/// Identities `1..aplic::NUM_SOURCES` are the wired IRQs forwarded by the
+/// APLIC, and MSIs use the identities `aplic::NUM_SOURCES..NUM_IDS`.
+pub const NUM_IDS: usize = 256;
+
+/// Size of the supervisor-level interrupt file of a hart.
+const FILE_SIZE: usize = 0x1000;
+
+// Interrupt file registers, accessed indirectly by `siselect` and `sireg`.
+const ISELECT_EIDELIVERY: usize = 0x70;
+const ISELECT_EITHRESHOLD: usize = 0x72;
+const ISELECT_EIE0: usize = 0xc0;
+
+/// Returns `true` if the platform has an IMSIC.
+pub const fn present() -> bool {
+    IMSIC_PADDR != 0
+}
+
+fn write_ireg(select: usize, value: usize) {
+    // `siselect` is CSR 0x150, and `sireg` is CSR 0x151.
+    unsafe { asm!("csrw 0x150, {0}", "csrw 0x151, {1}", in(reg) select, in(reg) value) };
+}
+
+/// Initializes the interrupt file of the current hart.
+///
+/// All identities are enabled, since MSIs are masked by the devices.
+pub fn init_percpu() {
+    write_ireg(ISELECT_EITHRESHOLD, 0);
+    // On RV64, `eie0`, `eie2`, ... each hold 64 identities.
+    for i in 0..NUM_IDS / 64 {
+        write_ireg(ISELECT_EIE0 + i * 2, if i == 0 { !1 } else { !0 });
+    }
+    write_ireg(ISELECT_EIDELIVERY, 1);
+}
+
+/// Claims the highest-priority pending identity of the current hart.
+pub fn claim() -> Option<usize> {
+    let topei: usize;
+    // `stopei` is CSR 0x15c. Writing it claims the top identity.
+    unsafe { asm!("csrrw {0}, 0x15c, zero", out(reg) topei) };
+    match topei >> 16 {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Returns the physical address of the interrupt file of the given hart, which
+/// is the MSI address of the hart.
+pub const fn file_paddr(hart_id: usize) -> usize {
+    IMSIC_PADDR + hart_id * FILE_SIZE
+}
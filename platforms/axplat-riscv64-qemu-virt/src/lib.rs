@@ -6,6 +6,7 @@ extern crate log;
 extern crate axplat;
 
 mod boot;
+mod boot_info;
 mod console;
 mod init;
 #[cfg(feature = "irq")]
@@ -1,9 +1,38 @@
+use axplat::fdt::{MemConfig, PlatformMem};
 use axplat::mem::{MemIf, PhysAddr, RawRange, VirtAddr, pa, va};
 
 use crate::config::devices::MMIO_RANGES;
 use crate::config::plat::{
     KERNEL_BASE_PADDR, PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE, PHYS_VIRT_OFFSET,
 };
+#[cfg(feature = "pstore")]
+use crate::config::plat::{PSTORE_PADDR, PSTORE_SIZE};
+
+/// Maximum number of memory ranges of each kind discovered from the device
+/// tree.
+const MAX_MEM_RANGES: usize = 32;
+
+/// Reserved ranges that are not described in the device tree.
+#[cfg(feature = "pstore")]
+const RESERVED_RANGES: &[RawRange] = &[(PSTORE_PADDR, PSTORE_SIZE)];
+#[cfg(not(feature = "pstore"))]
+const RESERVED_RANGES: &[RawRange] = &[];
+
+/// Memory layout of the platform, which is discovered from the device tree
+/// passed by the bootloader.
+pub(crate) static MEM: PlatformMem<MAX_MEM_RANGES> = PlatformMem::new(MemConfig {
+    // We can't directly use `PHYS_MEMORY_BASE` here, because it may has been used by sbi.
+    ram: &[(
+        KERNEL_BASE_PADDR,
+        PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE - KERNEL_BASE_PADDR,
+    )],
+    reserved: RESERVED_RANGES,
+    mmio: &MMIO_RANGES,
+    // The memory below the kernel may have been used by SBI, even if it is
+    // not reported in the device tree.
+    excluded_ram: &[(0, KERNEL_BASE_PADDR)],
+    fdt_range: None,
+});
 
 struct MemIfImpl;
 
@@ -14,12 +43,7 @@ impl MemIf for MemIfImpl {
     /// All memory ranges except reserved ranges (including the kernel loaded
     /// range) are free for allocation.
     fn phys_ram_ranges() -> &'static [RawRange] {
-        // TODO: paser dtb to get the available memory ranges
-        // We can't directly use `PHYS_MEMORY_BASE` here, because it may has been used by sbi.
-        &[(
-            KERNEL_BASE_PADDR,
-            PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE - KERNEL_BASE_PADDR,
-        )]
+        MEM.ram_ranges()
     }
 
     /// Returns all reserved physical memory ranges on the platform.
@@ -30,12 +54,12 @@ impl MemIf for MemIfImpl {
     /// Note that the ranges returned should not include the range where the
     /// kernel is loaded.
     fn reserved_phys_ram_ranges() -> &'static [RawRange] {
-        &[]
+        MEM.reserved_ranges()
     }
 
     /// Returns all device memory (MMIO) ranges on the platform.
     fn mmio_ranges() -> &'static [RawRange] {
-        &MMIO_RANGES
+        MEM.mmio_ranges()
     }
 
     /// Translates a physical address to a virtual address.
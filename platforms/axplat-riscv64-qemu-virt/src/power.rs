@@ -1,4 +1,107 @@
-use axplat::power::PowerIf;
+use axplat::mem::{va, virt_to_phys};
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
+
+/// Hart context saved before the non-retentive suspend.
+///
+/// Layout: `ra`, `sp`, `gp`, `tp`, `s0`-`s11`, `satp`, `stvec`, `sscratch`,
+/// `sie`, `sstatus`.
+#[repr(C, align(16))]
+pub(crate) struct SuspendContext([usize; 22]);
+
+/// Calls the SBI `hart_suspend` with the physical address of the saved
+/// context as the opaque parameter.
+unsafe extern "C" fn suspend_finisher(ctx: *mut SuspendContext, resume_addr: usize) -> usize {
+    let ctx_paddr = virt_to_phys(va!(ctx as usize)).as_usize();
+    sbi_rt::hart_suspend(sbi_rt::NonRetentive, resume_addr, ctx_paddr).error
+}
+
+/// Saves the hart context to `ctx`, then suspends the current hart.
+///
+/// It returns either from the finisher directly (if the hart did not lose its
+/// context), or from [`cpu_resume`] after the hart is resumed.
+#[unsafe(naked)]
+unsafe extern "C" fn suspend_enter(ctx: *mut SuspendContext, resume_addr: usize) -> usize {
+    core::arch::naked_asm!("
+        sd      ra, 0(a0)
+        sd      sp, 8(a0)
+        sd      gp, 16(a0)
+        sd      tp, 24(a0)
+        sd      s0, 32(a0)
+        sd      s1, 40(a0)
+        sd      s2, 48(a0)
+        sd      s3, 56(a0)
+        sd      s4, 64(a0)
+        sd      s5, 72(a0)
+        sd      s6, 80(a0)
+        sd      s7, 88(a0)
+        sd      s8, 96(a0)
+        sd      s9, 104(a0)
+        sd      s10, 112(a0)
+        sd      s11, 120(a0)
+        csrr    t0, satp
+        sd      t0, 128(a0)
+        csrr    t0, stvec
+        sd      t0, 136(a0)
+        csrr    t0, sscratch
+        sd      t0, 144(a0)
+        csrr    t0, sie
+        sd      t0, 152(a0)
+        csrr    t0, sstatus
+        sd      t0, 160(a0)
+
+        mv      s0, a0
+        call    {finisher}              // finisher(ctx, resume_addr)
+
+        ld      ra, 0(s0)               // returned without losing the context
+        ld      s0, 32(s0)
+        ret",
+        finisher = sym suspend_finisher,
+    )
+}
+
+/// Restores the hart context saved before the non-retentive suspend, and
+/// returns to the caller of `cpu_suspend`.
+///
+/// It is called by [`_start_resume`](crate::boot::_start_resume) with the
+/// virtual address of the context, after the MMU is enabled.
+#[unsafe(naked)]
+pub(crate) unsafe extern "C" fn cpu_resume(ctx: *const SuspendContext) -> ! {
+    core::arch::naked_asm!(
+        "
+        ld      t0, 128(a0)
+        csrw    satp, t0
+        sfence.vma
+        ld      t0, 136(a0)
+        csrw    stvec, t0
+        ld      t0, 144(a0)
+        csrw    sscratch, t0
+        ld      t0, 152(a0)
+        csrw    sie, t0
+        ld      t0, 160(a0)
+        csrw    sstatus, t0
+
+        ld      ra, 0(a0)
+        ld      sp, 8(a0)
+        ld      gp, 16(a0)
+        ld      tp, 24(a0)
+        ld      s0, 32(a0)
+        ld      s1, 40(a0)
+        ld      s2, 48(a0)
+        ld      s3, 56(a0)
+        ld      s4, 64(a0)
+        ld      s5, 72(a0)
+        ld      s6, 80(a0)
+        ld      s7, 88(a0)
+        ld      s8, 96(a0)
+        ld      s9, 104(a0)
+        ld      s10, 112(a0)
+        ld      s11, 120(a0)
+        li      a0, 0                   // return from `suspend_enter` with success
+        ret"
+    )
+}
 
 struct PowerImpl;
 
@@ -20,6 +123,56 @@ impl PowerIf for PowerImpl {
         sbi_rt::hart_start(cpu_id, entry.as_usize(), stack_top_paddr);
     }
 
+    /// Powers off the current CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        if let Err(e) = sbi_rt::hart_stop().into_result() {
+            error!("failed to stop the current hart: {:?}", e);
+        }
+        loop {
+            axcpu::asm::halt();
+        }
+    }
+
+    /// Returns the power state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        // See the "Hart States" section of the SBI HSM extension.
+        match sbi_rt::hart_get_status(cpu_id).into_result() {
+            Ok(0 | 4) => CpuState::On, // STARTED, SUSPENDED
+            Ok(1) => CpuState::Off,    // STOPPED
+            Ok(2 | 3 | 5 | 6) => CpuState::Pending,
+            _ => CpuState::Unknown,
+        }
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    ///
+    /// It uses the default retentive or non-retentive suspend type of the SBI
+    /// HSM extension, or `wfi` for [`SuspendState::Standby`] if the extension
+    /// is not available.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        let hsm_available = sbi_rt::probe_extension(sbi_rt::Hsm).is_available();
+        let error = match state {
+            SuspendState::Standby if !hsm_available => {
+                axcpu::asm::halt();
+                return true;
+            }
+            SuspendState::Standby => sbi_rt::hart_suspend(sbi_rt::Retentive, 0, 0).error,
+            SuspendState::PowerDown if !hsm_available => return false,
+            SuspendState::PowerDown => {
+                let mut ctx = SuspendContext([0; 22]);
+                let entry = virt_to_phys(va!(crate::boot::_start_resume as usize));
+                unsafe { suspend_enter(&mut ctx, entry.as_usize()) }
+            }
+        };
+        if error != 0 {
+            warn!("failed to suspend the current hart: {:#x}", error);
+            return false;
+        }
+        true
+    }
+
     /// Shutdown the whole system.
     fn system_off() -> ! {
         info!("Shutting down...");
@@ -29,4 +182,17 @@ impl PowerIf for PowerImpl {
             axcpu::asm::halt();
         }
     }
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> ! {
+        info!("Resetting the system...");
+        match kind {
+            ResetKind::Cold => sbi_rt::system_reset(sbi_rt::ColdReboot, sbi_rt::NoReason),
+            ResetKind::Warm => sbi_rt::system_reset(sbi_rt::WarmReboot, sbi_rt::NoReason),
+        };
+        warn!("It should reset!");
+        loop {
+            axcpu::asm::halt();
+        }
+    }
 }
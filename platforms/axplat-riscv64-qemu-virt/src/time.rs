@@ -1,10 +1,13 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use riscv::register::time;
 
-use axplat::time::{NANOS_PER_SEC, TimeIf};
+use axplat::time::{TickConverter, TimeIf};
 
-const NANOS_PER_TICK: u64 = NANOS_PER_SEC / crate::config::devices::TIMER_FREQUENCY as u64;
+const TICK_CONVERTER: TickConverter =
+    TickConverter::new(crate::config::devices::TIMER_FREQUENCY as u64);
 /// RTC wall time offset in nanoseconds at monotonic time base.
-static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
+static RTC_EPOCHOFFSET_NANOS: AtomicU64 = AtomicU64::new(0);
 
 pub(super) fn init_early() {
     #[cfg(feature = "rtc")]
@@ -22,10 +25,61 @@ pub(super) fn init_early() {
         let epoch_time_nanos =
             Rtc::new(phys_to_virt(GOLDFISH_BASE).as_usize()).get_unix_timestamp() * 1_000_000_000;
 
-        unsafe {
-            RTC_EPOCHOFFSET_NANOS =
-                epoch_time_nanos - TimeIfImpl::ticks_to_nanos(TimeIfImpl::current_ticks());
-        }
+        RTC_EPOCHOFFSET_NANOS.store(
+            epoch_time_nanos - TimeIfImpl::ticks_to_nanos(TimeIfImpl::current_ticks()),
+            Ordering::Release,
+        );
+    }
+}
+
+/// Registers of the Goldfish RTC alarm.
+#[cfg(all(feature = "irq", feature = "rtc"))]
+mod goldfish_alarm {
+    use axplat::mem::{pa, phys_to_virt};
+
+    const ALARM_LOW: usize = 0x08;
+    const ALARM_HIGH: usize = 0x0c;
+    const IRQ_ENABLED: usize = 0x10;
+    const CLEAR_ALARM: usize = 0x14;
+    const CLEAR_INTERRUPT: usize = 0x1c;
+
+    fn write_reg(offset: usize, value: u32) {
+        let base = phys_to_virt(pa!(crate::config::devices::RTC_PADDR));
+        let reg = (base + offset).as_mut_ptr().cast::<u32>();
+        unsafe { reg.write_volatile(value) };
+    }
+
+    /// Sets the alarm at the given RTC time in nanoseconds.
+    ///
+    /// The alarm is armed by writing the low half, and fires at once if the
+    /// time has passed.
+    pub fn set(time_ns: u64) {
+        write_reg(ALARM_HIGH, (time_ns >> 32) as u32);
+        write_reg(ALARM_LOW, time_ns as u32);
+        write_reg(IRQ_ENABLED, 1);
+    }
+
+    /// Cancels the pending alarm, and clears the interrupt.
+    pub fn cancel() {
+        write_reg(IRQ_ENABLED, 0);
+        write_reg(CLEAR_ALARM, 1);
+        write_reg(CLEAR_INTERRUPT, 1);
+    }
+
+    /// Handles the RTC interrupt, by disabling the alarm and calling
+    /// [`axplat::time::notify_alarm`].
+    pub fn irq_handler(_irq: usize) {
+        cancel();
+        axplat::time::notify_alarm();
+    }
+}
+
+/// Registers the RTC interrupt handler for alarms.
+#[cfg(feature = "irq")]
+pub(super) fn init_irq() {
+    #[cfg(feature = "rtc")]
+    if crate::config::devices::RTC_PADDR != 0 {
+        axplat::irq::register(crate::config::devices::RTC_IRQ, goldfish_alarm::irq_handler);
     }
 }
 
@@ -45,17 +99,34 @@ impl TimeIf for TimeIfImpl {
 
     /// Converts hardware ticks to nanoseconds.
     fn ticks_to_nanos(ticks: u64) -> u64 {
-        ticks * NANOS_PER_TICK
+        TICK_CONVERTER.ticks_to_nanos(ticks)
     }
 
     /// Converts nanoseconds to hardware ticks.
     fn nanos_to_ticks(nanos: u64) -> u64 {
-        nanos / NANOS_PER_TICK
+        TICK_CONVERTER.nanos_to_ticks(nanos)
     }
 
     /// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
     fn epochoffset_nanos() -> u64 {
-        unsafe { RTC_EPOCHOFFSET_NANOS }
+        RTC_EPOCHOFFSET_NANOS.load(Ordering::Acquire)
+    }
+
+    /// Set epoch offset in nanoseconds, and write the new wall time back to
+    /// the Goldfish RTC.
+    fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+        RTC_EPOCHOFFSET_NANOS.store(epochoffset_nanos, Ordering::Release);
+
+        #[cfg(feature = "rtc")]
+        if crate::config::devices::RTC_PADDR != 0 {
+            use axplat::mem::{pa, phys_to_virt};
+
+            let rtc = riscv_goldfish::Rtc::new(
+                phys_to_virt(pa!(crate::config::devices::RTC_PADDR)).as_usize(),
+            );
+            let wall_time_nanos = axplat::time::wall_time_nanos();
+            rtc.set_unix_timestamp(wall_time_nanos / axplat::time::NANOS_PER_SEC);
+        }
     }
 
     /// Set a one-shot timer.
@@ -65,4 +136,42 @@ impl TimeIf for TimeIfImpl {
     fn set_oneshot_timer(deadline_ns: u64) {
         sbi_rt::set_timer(Self::nanos_to_ticks(deadline_ns));
     }
+
+    /// Set a periodic timer.
+    ///
+    /// It is not supported, as the SBI timer only has the one-shot mode.
+    #[cfg(feature = "irq")]
+    fn set_periodic_timer(_period_ns: u64) -> bool {
+        false
+    }
+
+    /// Cancel the pending timer on the current CPU.
+    ///
+    /// The deadline is set to the maximum, which also clears the pending timer
+    /// interrupt.
+    #[cfg(feature = "irq")]
+    fn cancel_timer() {
+        sbi_rt::set_timer(u64::MAX);
+    }
+
+    /// Set an RTC alarm at the specified wall time (in nanoseconds since
+    /// epoch), by the Goldfish RTC alarm.
+    #[cfg(feature = "irq")]
+    fn set_rtc_alarm(_wall_time_ns: u64) -> bool {
+        #[cfg(feature = "rtc")]
+        if crate::config::devices::RTC_PADDR != 0 {
+            goldfish_alarm::set(_wall_time_ns);
+            return true;
+        }
+        false
+    }
+
+    /// Cancel the pending RTC alarm, if any.
+    #[cfg(feature = "irq")]
+    fn cancel_rtc_alarm() {
+        #[cfg(feature = "rtc")]
+        if crate::config::devices::RTC_PADDR != 0 {
+            goldfish_alarm::cancel();
+        }
+    }
 }
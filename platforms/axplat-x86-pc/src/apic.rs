@@ -1,17 +1,35 @@
 //! Advanced Programmable Interrupt Controller (APIC) support.
 
 use core::mem::MaybeUninit;
+#[cfg(feature = "irq")]
+use core::sync::atomic::{AtomicU32, Ordering};
 
+#[cfg(feature = "irq")]
+use axplat::irq::{CpuMask, MsiMessage, MsiVectorAllocator, Polarity, TriggerMode};
 use axplat::mem::{PhysAddr, pa, phys_to_virt};
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 use x2apic::ioapic::IoApic;
+#[cfg(feature = "irq")]
+use x2apic::ioapic::IrqFlags;
 use x2apic::lapic::{LocalApic, LocalApicBuilder, xapic_base};
 use x86_64::instructions::port::Port;
+#[cfg(feature = "irq")]
+use x86_64::registers::model_specific::Msr;
 
 use self::vectors::*;
+#[cfg(feature = "irq")]
+use crate::config::plat::CPU_NUM;
 
 pub(super) mod vectors {
+    /// The vector of IO APIC pin 0. Pin `n` is mapped to vector `0x20 + n`.
+    pub const IO_APIC_VECTOR_BASE: u8 = 0x20;
+    /// The number of IO APIC pins.
+    #[cfg(feature = "irq")]
+    pub const IO_APIC_PIN_COUNT: u8 = 24;
+    /// Vectors from it to [`APIC_TIMER_VECTOR`] are allocated for MSIs.
+    #[cfg(feature = "irq")]
+    pub const MSI_VECTOR_BASE: u8 = 0x40;
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
@@ -19,23 +37,175 @@ pub(super) mod vectors {
 
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
+/// The address of MSIs, where bits 12..20 are the destination APIC ID.
+#[cfg(feature = "irq")]
+const MSI_ADDRESS_BASE: usize = 0xFEE0_0000;
+#[cfg(feature = "irq")]
+const MSI_VECTOR_COUNT: usize = (APIC_TIMER_VECTOR - MSI_VECTOR_BASE) as usize;
+
 static mut LOCAL_APIC: MaybeUninit<LocalApic> = MaybeUninit::uninit();
 static mut IS_X2APIC: bool = false;
 static IO_APIC: LazyInit<SpinNoIrq<IoApic>> = LazyInit::new();
+#[cfg(feature = "irq")]
+static MSI_VECTORS: MsiVectorAllocator<MSI_VECTOR_COUNT> = MsiVectorAllocator::new();
+
+/// The local APIC IDs of the CPUs, indexed by the CPU IDs given by the kernel.
+///
+/// Each CPU records its own APIC ID when its local APIC is initialized.
+#[cfg(feature = "irq")]
+static APIC_IDS: [AtomicU32; CPU_NUM] = [const { AtomicU32::new(u32::MAX) }; CPU_NUM];
+
+// CPU masks of IRQ affinities and IPIs can hold at most `CpuMask::MAX_CPUS` CPUs.
+#[cfg(feature = "irq")]
+const _: () = assert!(CPU_NUM <= CpuMask::MAX_CPUS);
+
+/// Records the local APIC ID of the current CPU.
+#[cfg(feature = "irq")]
+fn record_apic_id(cpu_id: usize) {
+    if let Some(id) = APIC_IDS.get(cpu_id) {
+        id.store(crate::current_cpu_id() as u32, Ordering::Release);
+    }
+}
+
+/// Returns the local APIC ID of the CPU with the given ID, or `None` if the
+/// CPU has not initialized its local APIC.
+#[cfg(feature = "irq")]
+fn apic_id(cpu_id: usize) -> Option<u32> {
+    let id = APIC_IDS.get(cpu_id)?.load(Ordering::Acquire);
+    (id != u32::MAX).then_some(id)
+}
+
+/// Returns the IO APIC pin of the given vector, or `None` if it is not an IO
+/// APIC interrupt.
+#[cfg(feature = "irq")]
+fn io_apic_pin(vector: usize) -> Option<u8> {
+    (IO_APIC_VECTOR_BASE as usize..(IO_APIC_VECTOR_BASE + IO_APIC_PIN_COUNT) as usize)
+        .contains(&vector)
+        .then(|| (vector - IO_APIC_VECTOR_BASE as usize) as u8)
+}
 
 /// Enables or disables the given IRQ.
 #[cfg(feature = "irq")]
 pub fn set_enable(vector: usize, enabled: bool) {
-    // should not affect LAPIC interrupts
-    if vector < APIC_TIMER_VECTOR as _ {
+    // should not affect MSIs and LAPIC interrupts
+    if let Some(pin) = io_apic_pin(vector) {
         unsafe {
             if enabled {
-                IO_APIC.lock().enable_irq(vector as u8);
+                IO_APIC.lock().enable_irq(pin);
             } else {
-                IO_APIC.lock().disable_irq(vector as u8);
+                IO_APIC.lock().disable_irq(pin);
+            }
+        }
+    }
+}
+
+/// Sets the trigger mode and polarity of the given IRQ in the IO APIC.
+///
+/// Returns `false` if the IRQ is not an IO APIC interrupt.
+#[cfg(feature = "irq")]
+pub fn set_trigger(vector: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+    let Some(pin) = io_apic_pin(vector) else {
+        return false;
+    };
+    let mut io_apic = IO_APIC.lock();
+    unsafe {
+        let mut entry = io_apic.table_entry(pin);
+        let mut flags = entry.flags();
+        flags.set(IrqFlags::LEVEL_TRIGGERED, mode == TriggerMode::Level);
+        flags.set(IrqFlags::LOW_ACTIVE, polarity == Polarity::Low);
+        entry.set_flags(flags);
+        io_apic.set_table_entry(pin, entry);
+    }
+    true
+}
+
+/// Routes the given IRQ to the first CPU in `cpu_mask`, by the IO APIC
+/// (physical destination mode).
+///
+/// Returns `false` if the IRQ is not an IO APIC interrupt, or the CPU cannot
+/// be addressed by the IO APIC.
+#[cfg(feature = "irq")]
+pub fn set_affinity(vector: usize, cpu_mask: CpuMask) -> bool {
+    let Some(apic_id) = cpu_mask
+        .first()
+        .and_then(apic_id)
+        .and_then(|id| u8::try_from(id).ok())
+    else {
+        return false;
+    };
+    let Some(pin) = io_apic_pin(vector) else {
+        return false;
+    };
+    let mut io_apic = IO_APIC.lock();
+    unsafe {
+        let mut entry = io_apic.table_entry(pin);
+        entry.set_dest(apic_id);
+        io_apic.set_table_entry(pin, entry);
+    }
+    true
+}
+
+/// Allocates an MSI vector delivered to the CPU with the given ID.
+///
+/// The message is in physical destination mode with fixed delivery, and is
+/// edge-triggered. Returns `None` if the APIC ID of the CPU is unknown or does
+/// not fit in the message address, or no vectors are available.
+#[cfg(feature = "irq")]
+pub fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+    let apic_id = u8::try_from(apic_id(cpu_id)?).ok()?;
+    let vector = MSI_VECTOR_BASE as usize + MSI_VECTORS.alloc(MSI_VECTOR_COUNT)?;
+    let message = MsiMessage {
+        address: pa!(MSI_ADDRESS_BASE | ((apic_id as usize) << 12)),
+        data: vector as u32,
+    };
+    Some((vector, message))
+}
+
+/// Frees an MSI vector allocated by [`alloc_msi`].
+#[cfg(feature = "irq")]
+pub fn free_msi(vector: usize) -> bool {
+    vector
+        .checked_sub(MSI_VECTOR_BASE as usize)
+        .is_some_and(|idx| MSI_VECTORS.free(idx))
+}
+
+/// Sends an IPI to the CPUs in `cpu_mask`.
+///
+/// The CPUs that have not initialized their local APICs are skipped. With
+/// x2APIC, it sends one IPI to each cluster of 16 CPUs in the logical
+/// (cluster) destination mode. Otherwise, it sends the IPIs one by one.
+#[cfg(feature = "irq")]
+pub fn send_ipi_mask(vector: u8, cpu_mask: CpuMask) {
+    let apic_ids = cpu_mask.iter().filter_map(apic_id);
+    if !unsafe { IS_X2APIC } {
+        // The xAPIC destination is in ICR bits 56-63, see `raw_apic_id`.
+        for apic_id in apic_ids {
+            unsafe { local_apic().send_ipi(vector, raw_apic_id(apic_id as u8)) };
+        }
+        return;
+    }
+    // The logical x2APIC ID is (`apic_id[19:4]` << 16) | (1 << `apic_id[3:0]`),
+    // so the CPUs are grouped by `apic_id[19:4]`.
+    let mut clusters = [(0u32, 0u16); CpuMask::MAX_CPUS];
+    let mut count = 0;
+    for apic_id in apic_ids {
+        let (cluster, member) = (apic_id >> 4, 1 << (apic_id & 0xf));
+        match clusters[..count].iter_mut().find(|(c, _)| *c == cluster) {
+            Some((_, members)) => *members |= member,
+            None => {
+                clusters[count] = (cluster, member);
+                count += 1;
             }
         }
     }
+    const IA32_X2APIC_ICR: u32 = 0x830;
+    const ICR_DEST_LOGICAL: u64 = 1 << 11;
+    const ICR_LEVEL_ASSERT: u64 = 1 << 14;
+    for &(cluster, members) in &clusters[..count] {
+        let dest = ((cluster as u64) << 16) | members as u64;
+        let icr = (dest << 32) | ICR_LEVEL_ASSERT | ICR_DEST_LOGICAL | vector as u64;
+        unsafe { Msr::new(IA32_X2APIC_ICR).write(icr) };
+    }
 }
 
 #[cfg(any(feature = "smp", feature = "irq"))]
@@ -45,7 +215,25 @@ pub fn local_apic<'a>() -> &'a mut LocalApic {
     unsafe { LOCAL_APIC.assume_init_mut() }
 }
 
-#[cfg(feature = "smp")]
+/// Returns the current count of the local APIC timer.
+#[cfg(feature = "irq")]
+pub fn timer_current_count() -> u32 {
+    const IA32_X2APIC_CUR_COUNT: u32 = 0x839;
+    const XAPIC_CUR_COUNT: usize = 0x390;
+    unsafe {
+        if IS_X2APIC {
+            Msr::new(IA32_X2APIC_CUR_COUNT).read() as u32
+        } else {
+            let base_vaddr = phys_to_virt(pa!(xapic_base() as usize));
+            (base_vaddr + XAPIC_CUR_COUNT)
+                .as_ptr()
+                .cast::<u32>()
+                .read_volatile()
+        }
+    }
+}
+
+#[cfg(any(feature = "smp", feature = "irq"))]
 pub fn raw_apic_id(id_u8: u8) -> u32 {
     if unsafe { IS_X2APIC } {
         id_u8 as u32
@@ -61,7 +249,7 @@ fn cpu_has_x2apic() -> bool {
     }
 }
 
-pub fn init_primary() {
+pub fn init_primary(_cpu_id: usize) {
     info!("Initialize Local APIC...");
 
     unsafe {
@@ -91,26 +279,45 @@ pub fn init_primary() {
         #[allow(static_mut_refs)]
         LOCAL_APIC.write(lapic);
     }
+    #[cfg(feature = "irq")]
+    record_apic_id(_cpu_id);
 
     info!("Initialize IO APIC...");
-    let io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    let mut io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    // Map the pins to vectors, with all of them masked.
+    unsafe { io_apic.init(IO_APIC_VECTOR_BASE) };
     IO_APIC.init_once(SpinNoIrq::new(io_apic));
 }
 
 #[cfg(feature = "smp")]
-pub fn init_secondary() {
+pub fn init_secondary(_cpu_id: usize) {
     unsafe { local_apic().enable() };
+    #[cfg(feature = "irq")]
+    record_apic_id(_cpu_id);
 }
 
 #[cfg(feature = "irq")]
 mod irq_impl {
-    use axplat::irq::{HandlerTable, IpiTarget, IrqHandler, IrqIf};
+    #[cfg(feature = "alloc")]
+    use axplat::irq::BoxedIrqHandler;
+    use axplat::irq::{
+        CpuMask, HandlerTable, IpiTarget, IrqContextHandler, IrqHandler, IrqIf, MsiMessage,
+        Polarity, SharedIrqHandler, TriggerMode,
+    };
+    #[cfg(feature = "irq-stats")]
+    use axplat::irq::{IrqCounters, IrqStats};
+
+    #[cfg(feature = "irq-stats")]
+    use crate::config::plat::CPU_NUM;
 
     /// The maximum number of IRQs.
     const MAX_IRQ_COUNT: usize = 256;
 
     static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+    #[cfg(feature = "irq-stats")]
+    static IRQ_STATS: IrqStats<MAX_IRQ_COUNT, CPU_NUM> = IrqStats::new();
+
     struct IrqIfImpl;
 
     #[impl_plat_interface]
@@ -120,6 +327,26 @@ mod irq_impl {
             super::set_enable(vector, enabled);
         }
 
+        /// Sets the trigger mode and polarity of the given IRQ.
+        fn set_trigger(vector: usize, mode: TriggerMode, polarity: Polarity) -> bool {
+            super::set_trigger(vector, mode, polarity)
+        }
+
+        /// Sets the priority of the given IRQ.
+        ///
+        /// It is not supported, as the priority of an APIC interrupt is
+        /// determined by its vector.
+        fn set_priority(_vector: usize, _priority: u8) -> bool {
+            false
+        }
+
+        /// Sets the CPUs that the given IRQ can be delivered to.
+        ///
+        /// Only the first CPU in `cpu_mask` is used.
+        fn set_affinity(vector: usize, cpu_mask: CpuMask) -> bool {
+            super::set_affinity(vector, cpu_mask)
+        }
+
         /// Registers an IRQ handler for the given IRQ.
         ///
         /// It also enables the IRQ if the registration succeeds. It returns `false` if
@@ -132,6 +359,29 @@ mod irq_impl {
             false
         }
 
+        /// Registers an IRQ handler with a context pointer for the given IRQ.
+        fn register_with_context(
+            vector: usize,
+            handler: IrqContextHandler,
+            context: *mut (),
+        ) -> bool {
+            if IRQ_HANDLER_TABLE.register_handler_with_context(vector, handler, context) {
+                Self::set_enable(vector, true);
+                return true;
+            }
+            false
+        }
+
+        /// Registers a boxed closure as the IRQ handler for the given IRQ.
+        #[cfg(feature = "alloc")]
+        fn register_boxed(vector: usize, handler: BoxedIrqHandler) -> bool {
+            if IRQ_HANDLER_TABLE.register_boxed_handler(vector, handler) {
+                Self::set_enable(vector, true);
+                return true;
+            }
+            false
+        }
+
         /// Unregisters the IRQ handler for the given IRQ.
         ///
         /// It also disables the IRQ if the unregistration succeeds. It returns the
@@ -141,6 +391,40 @@ mod irq_impl {
             IRQ_HANDLER_TABLE.unregister_handler(vector)
         }
 
+        /// Adds a handler to the shared IRQ line.
+        fn register_shared(vector: usize, handler: SharedIrqHandler, context: *mut ()) -> bool {
+            if IRQ_HANDLER_TABLE.register_shared_handler(vector, handler, context) {
+                Self::set_enable(vector, true);
+                return true;
+            }
+            false
+        }
+
+        /// Removes the handler registered with the given context pointer from the
+        /// shared IRQ line.
+        fn unregister_shared(vector: usize, context: *mut ()) -> bool {
+            if !IRQ_HANDLER_TABLE.unregister_shared_handler(vector, context) {
+                return false;
+            }
+            if !IRQ_HANDLER_TABLE.has_handler(vector) {
+                Self::set_enable(vector, false);
+            }
+            true
+        }
+
+        /// Allocates an MSI vector that is delivered to the given CPU.
+        ///
+        /// Where `cpu_id` is the logical CPU ID. MSIs are not routed by the
+        /// IO APIC, so [`set_enable`](IrqIf::set_enable) has no effect on them.
+        fn alloc_msi(cpu_id: usize) -> Option<(usize, MsiMessage)> {
+            super::alloc_msi(cpu_id)
+        }
+
+        /// Frees an MSI vector allocated by [`alloc_msi`](IrqIf::alloc_msi).
+        fn free_msi(vector: usize) -> bool {
+            super::free_msi(vector)
+        }
+
         /// Handles the IRQ.
         ///
         /// It is called by the common interrupt handler. It should look up in the
@@ -148,7 +432,22 @@ mod irq_impl {
         /// also acknowledges the interrupt controller after handling.
         fn handle(vector: usize) {
             trace!("IRQ {}", vector);
-            if !IRQ_HANDLER_TABLE.handle(vector) {
+            if vector == super::vectors::APIC_SPURIOUS_VECTOR as usize {
+                // Spurious interrupts must not be acknowledged.
+                #[cfg(feature = "irq-stats")]
+                IRQ_STATS.record_spurious(crate::current_cpu_id());
+                return;
+            }
+            // Cross-CPU calls are run before the registered IPI handler.
+            let called = axplat::ipi::ipi_irq() == Some(vector)
+                && axplat::ipi::handle_calls(crate::current_cpu_id());
+            // Expired software timers are run before the registered timer handler.
+            let timed = axplat::time::timer_irq() == Some(vector)
+                && axplat::time::handle_timer_irq(crate::current_cpu_id());
+            let handled = IRQ_HANDLER_TABLE.handle(vector) || called || timed;
+            #[cfg(feature = "irq-stats")]
+            IRQ_STATS.record(vector, crate::current_cpu_id(), handled);
+            if !handled {
                 warn!("Unhandled IRQ {vector}");
             }
             unsafe { super::local_apic().end_of_interrupt() };
@@ -157,27 +456,30 @@ mod irq_impl {
         /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
         fn send_ipi(irq_num: usize, target: IpiTarget) {
             match target {
-                IpiTarget::Current { cpu_id } => {
-                    unsafe {
-                        super::local_apic().send_ipi_self(cpu_id as _);
-                    };
+                IpiTarget::Current { .. } => {
+                    unsafe { super::local_apic().send_ipi_self(irq_num as _) };
                 }
-                IpiTarget::Other { cpu_id } => {
-                    unsafe {
-                        super::local_apic().send_ipi(irq_num as _, cpu_id as _);
-                    };
-                }
-                IpiTarget::AllExceptCurrent {
-                    cpu_id: _,
-                    cpu_num: _,
-                } => {
+                IpiTarget::AllExceptCurrent { .. } => {
                     use x2apic::lapic::IpiAllShorthand;
                     unsafe {
                         super::local_apic()
                             .send_ipi_all(irq_num as _, IpiAllShorthand::AllExcludingSelf);
                     };
                 }
+                _ => super::send_ipi_mask(irq_num as _, target.cpu_mask()),
             }
         }
+
+        /// Takes a snapshot of the interrupt statistics.
+        #[cfg(feature = "irq-stats")]
+        fn irq_stats(f: &mut dyn FnMut(usize, usize, IrqCounters)) {
+            IRQ_STATS.for_each(f);
+        }
+
+        /// Returns the number of spurious interrupts on the given CPU.
+        #[cfg(feature = "irq-stats")]
+        fn spurious_irq_count(cpu_id: usize) -> u64 {
+            IRQ_STATS.spurious(cpu_id)
+        }
     }
 }
@@ -0,0 +1,76 @@
+use axplat::boot::{BootIf, BootInfo, FramebufferInfo, MemRegion, MemRegionKind};
+use axplat::mem::{pa, phys_to_virt};
+use multiboot::information::{MemoryType, Multiboot};
+
+/// Finds the ACPI RSDP in the legacy BIOS areas.
+///
+/// The RSDP is on a 16-byte boundary in the first 1 KiB of the EBDA (Extended
+/// BIOS Data Area), or in the BIOS ROM area (`0xE0000..0x100000`).
+fn find_acpi_rsdp() -> Option<usize> {
+    const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+    const RSDP_V1_SIZE: usize = 20;
+
+    // The segment of the EBDA is stored at 0x40E in the BIOS data area.
+    let ebda_base = unsafe { phys_to_virt(pa!(0x40e)).as_ptr_of::<u16>().read_unaligned() };
+    let areas = [((ebda_base as usize) << 4, 0x400), (0xe_0000, 0x2_0000)];
+    for (start, size) in areas {
+        if start == 0 {
+            continue;
+        }
+        for paddr in (start..start + size).step_by(16) {
+            let ptr = phys_to_virt(pa!(paddr)).as_ptr();
+            let rsdp = unsafe { core::slice::from_raw_parts(ptr, RSDP_V1_SIZE) };
+            if rsdp.starts_with(RSDP_SIGNATURE)
+                && rsdp.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+            {
+                return Some(paddr);
+            }
+        }
+    }
+    None
+}
+
+struct BootIfImpl;
+
+#[impl_plat_interface]
+impl BootIf for BootIfImpl {
+    /// Parses the boot information from the multiboot information structure.
+    fn parse_boot_info(mbi: usize) -> BootInfo {
+        let mut info = BootInfo::new(mbi);
+        info.acpi_rsdp = find_acpi_rsdp().map(|paddr| pa!(paddr));
+
+        let mut mm = crate::mem::MultibootMemory;
+        let Some(mb) = (unsafe { Multiboot::from_ptr(mbi as _, &mut mm) }) else {
+            return info;
+        };
+        info.multiboot = Some(pa!(mbi));
+        info.cmdline = mb.command_line().filter(|s| !s.is_empty());
+        if let Some(initrd) = mb.modules().and_then(|mut modules| modules.next()) {
+            info.initrd = Some((initrd.start as usize, (initrd.end - initrd.start) as usize));
+        }
+        if let Some(fb) = mb.framebuffer_table() {
+            info.framebuffer = Some(FramebufferInfo {
+                paddr: pa!(fb.addr as usize),
+                width: fb.width,
+                height: fb.height,
+                pitch: fb.pitch,
+                bpp: fb.bpp,
+            });
+        }
+        for r in mb.memory_regions().into_iter().flatten() {
+            let kind = match r.memory_type() {
+                MemoryType::Available => MemRegionKind::Usable,
+                MemoryType::ACPI => MemRegionKind::AcpiReclaimable,
+                MemoryType::NVS => MemRegionKind::AcpiNvs,
+                MemoryType::Defect => MemRegionKind::Bad,
+                _ => MemRegionKind::Reserved,
+            };
+            info.add_memory_region(MemRegion {
+                paddr: pa!(r.base_address() as usize),
+                size: r.length() as usize,
+                kind,
+            });
+        }
+        info
+    }
+}
@@ -1,25 +1,139 @@
 //! Uart 16550 serial port.
 
-use axplat::console::ConsoleIf;
+#[cfg(feature = "irq")]
+use axplat::console::{ByteRing, TxRing};
+use axplat::console::{ConsoleIf, Transmitter};
 use kspin::SpinNoIrq;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
 
-static COM1: SpinNoIrq<SerialPort> = unsafe { SpinNoIrq::new(SerialPort::new(0x3f8)) };
+const COM1_PORT: u16 = 0x3f8;
+
+static COM1: SpinNoIrq<SerialPort> = unsafe { SpinNoIrq::new(SerialPort::new(COM1_PORT)) };
+
+/// Bytes received by the UART interrupt, which are read before the ones still
+/// in the UART.
+#[cfg(feature = "irq")]
+static RX_RING: ByteRing<256> = ByteRing::new();
+
+/// Bytes to be sent by the UART interrupt.
+#[cfg(feature = "irq")]
+static TX_RING: TxRing<4096> = TxRing::new();
+
+/// The transmitter of COM1, accessed by its registers.
+struct Com1Tx;
+
+impl Com1Tx {
+    #[cfg(feature = "irq")]
+    const IER: u16 = COM1_PORT + 1;
+    const LSR: u16 = COM1_PORT + 5;
+    /// Transmitter holding register empty.
+    const THRE: u8 = 1 << 5;
+    /// Interrupt when the transmitter holding register is empty.
+    #[cfg(feature = "irq")]
+    const IER_THRE: u8 = 1 << 1;
+
+    /// Enables or disables the interrupt when the transmitter is empty.
+    #[cfg(feature = "irq")]
+    fn set_irq_enabled(&mut self, enabled: bool) {
+        let mut ier = Port::<u8>::new(Self::IER);
+        unsafe {
+            let val = ier.read();
+            ier.write(if enabled {
+                val | Self::IER_THRE
+            } else {
+                val & !Self::IER_THRE
+            });
+        }
+    }
+
+    /// Writes a byte, as [`SerialPort::send`] does.
+    #[cfg(feature = "irq")]
+    fn write(&mut self, c: u8) {
+        let mut buffered = false;
+        match c {
+            8 | 0x7F => {
+                for c in [8, b' ', 8] {
+                    buffered |= TX_RING.write(self, c);
+                }
+            }
+            b'\n' => {
+                buffered |= TX_RING.write(self, b'\r');
+                buffered |= TX_RING.write(self, b'\n');
+            }
+            c => buffered = TX_RING.write(self, c),
+        }
+        if buffered {
+            self.set_irq_enabled(true);
+        }
+    }
+}
+
+impl Transmitter for Com1Tx {
+    fn is_ready(&mut self) -> bool {
+        unsafe { Port::<u8>::new(Self::LSR).read() & Self::THRE != 0 }
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe { Port::<u8>::new(COM1_PORT).write(byte) };
+    }
+}
 
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
-    COM1.lock().send(c)
+    #[cfg(feature = "irq")]
+    {
+        let _com1 = COM1.lock();
+        Com1Tx.write(c);
+    }
+    #[cfg(not(feature = "irq"))]
+    COM1.lock().send(c);
 }
 
 /// Reads a byte from the console, or returns [`None`] if no input is available.
 pub fn getchar() -> Option<u8> {
-    COM1.lock().try_receive().ok()
+    let mut com1 = COM1.lock();
+    #[cfg(feature = "irq")]
+    if let Some(c) = RX_RING.pop() {
+        return Some(c);
+    }
+    com1.try_receive().ok()
 }
 
 pub fn init() {
     COM1.lock().init();
 }
 
+/// Registers the UART interrupt handler, and starts buffering the output.
+#[cfg(feature = "irq")]
+pub fn init_irq() {
+    axplat::irq::register(crate::config::devices::UART_IRQ, irq_handler);
+    TX_RING.enable();
+}
+
+/// Handles the UART interrupt, by moving the received bytes into the ring
+/// buffer, and sending the buffered output.
+///
+/// The UART raises the interrupt when data is received, as enabled by
+/// [`init`], or when the transmitter is empty and there is buffered output.
+#[cfg(feature = "irq")]
+fn irq_handler(_vector: usize) {
+    let mut com1 = COM1.lock();
+    let mut received = false;
+    while let Ok(c) = com1.try_receive() {
+        // Drop the byte if the buffer is full.
+        RX_RING.push(c);
+        received = true;
+    }
+    if !TX_RING.drain(&mut Com1Tx) {
+        Com1Tx.set_irq_enabled(false);
+    }
+    drop(com1);
+    if received {
+        axplat::console::notify_input();
+    }
+}
+
 struct ConsoleIfImpl;
 
 #[impl_plat_interface]
@@ -47,10 +161,34 @@ impl ConsoleIf for ConsoleIfImpl {
         read_len
     }
 
+    /// Waits until all bytes written to the console are sent.
+    fn flush() {
+        #[cfg(feature = "irq")]
+        {
+            let _com1 = COM1.lock();
+            TX_RING.flush(&mut Com1Tx);
+            Com1Tx.set_irq_enabled(false);
+        }
+    }
+
+    /// Writes given bytes to the console synchronously, without taking any
+    /// lock.
+    fn write_bytes_nolock(bytes: &[u8]) {
+        #[cfg(feature = "irq")]
+        TX_RING.flush(&mut Com1Tx);
+        for &c in bytes {
+            if c == b'\n' {
+                Com1Tx.send_blocking(b'\r');
+            }
+            Com1Tx.send_blocking(c);
+        }
+    }
+
     /// Returns the IRQ number for the console, if applicable.
+    ///
+    /// It is handled by the platform, which buffers the received bytes.
     #[cfg(feature = "irq")]
     fn irq_number() -> Option<u32> {
-        // TODO: ?
-        None
+        Some(crate::config::devices::UART_IRQ as _)
     }
 }
@@ -12,8 +12,14 @@ impl InitIf for InitIfImpl {
     fn init_early(_cpu_id: usize, mbi: usize) {
         axcpu::init::init_trap();
         crate::console::init();
+        #[cfg(feature = "pstore")]
+        axplat::pstore::init(
+            axplat::mem::phys_to_virt(axplat::mem::pa!(crate::config::plat::PSTORE_PADDR)),
+            crate::config::plat::PSTORE_SIZE,
+        );
         crate::time::init_early();
         crate::mem::init(mbi);
+        crate::power::init();
     }
 
     /// Initializes the platform at the early stage for secondary cores.
@@ -27,15 +33,22 @@ impl InitIf for InitIfImpl {
     /// This function should be called after the kernel has done part of its
     /// initialization (e.g, logging, memory management), and finalized the rest of
     /// platform configuration and initialization.
-    fn init_later(_cpu_id: usize, _arg: usize) {
-        crate::apic::init_primary();
+    fn init_later(cpu_id: usize, _arg: usize) {
+        crate::apic::init_primary(cpu_id);
         crate::time::init_primary();
+        #[cfg(feature = "irq")]
+        {
+            axplat::ipi::init(crate::config::devices::IPI_IRQ);
+            axplat::time::init_timer_irq(crate::config::devices::TIMER_IRQ);
+            crate::console::init_irq();
+            crate::time::init_irq();
+        }
     }
 
     /// Initializes the platform at the later stage for secondary cores.
     #[cfg(feature = "smp")]
-    fn init_later_secondary(_cpu_id: usize) {
-        crate::apic::init_secondary();
+    fn init_later_secondary(cpu_id: usize) {
+        crate::apic::init_secondary(cpu_id);
         crate::time::init_secondary();
     }
 }
@@ -7,6 +7,7 @@ extern crate axplat;
 
 mod apic;
 mod boot;
+mod boot_info;
 mod console;
 mod init;
 mod mem;
@@ -16,6 +17,10 @@ mod time;
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "irq")]
+pub use self::time::lapic_timer_frequency;
+pub use self::time::tsc_frequency;
+
 pub mod config {
     //! Platform configuration module.
     //!
@@ -40,6 +45,8 @@ fn current_cpu_id() -> usize {
 
 unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
+        #[cfg(feature = "smp")]
+        mp::set_cpu_state(current_cpu_id(), axplat::power::CpuState::On);
         axplat::call_main(current_cpu_id(), mbi);
     }
 }
@@ -47,6 +54,7 @@ unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
 unsafe extern "C" fn rust_entry_secondary(_magic: usize) {
     #[cfg(feature = "smp")]
     if _magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
+        mp::set_cpu_state(current_cpu_id(), axplat::power::CpuState::On);
         axplat::call_secondary_main(current_cpu_id());
     }
 }
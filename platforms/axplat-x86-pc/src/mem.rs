@@ -7,13 +7,15 @@ use multiboot::information::{MemoryManagement, MemoryType, Multiboot, PAddr};
 
 use crate::config::devices::MMIO_RANGES;
 use crate::config::plat::PHYS_VIRT_OFFSET;
+#[cfg(feature = "pstore")]
+use crate::config::plat::{PSTORE_PADDR, PSTORE_SIZE};
 
 const MAX_REGIONS: usize = 16;
 
 static RAM_REGIONS: LazyInit<Vec<RawRange, MAX_REGIONS>> = LazyInit::new();
 
 pub fn init(multiboot_info_ptr: usize) {
-    let mut mm = MemIfImpl;
+    let mut mm = MultibootMemory;
     let info = unsafe { Multiboot::from_ptr(multiboot_info_ptr as _, &mut mm).unwrap() };
 
     let mut regions = Vec::new();
@@ -27,11 +29,12 @@ pub fn init(multiboot_info_ptr: usize) {
     RAM_REGIONS.init_once(regions);
 }
 
-struct MemIfImpl;
+/// Provides access to the multiboot information in physical memory.
+pub(crate) struct MultibootMemory;
 
-impl MemoryManagement for MemIfImpl {
+impl MemoryManagement for MultibootMemory {
     unsafe fn paddr_to_slice(&self, addr: PAddr, size: usize) -> Option<&'static [u8]> {
-        let ptr = Self::phys_to_virt(pa!(addr as usize)).as_ptr();
+        let ptr = MemIfImpl::phys_to_virt(pa!(addr as usize)).as_ptr();
         Some(unsafe { core::slice::from_raw_parts(ptr, size) })
     }
 
@@ -42,6 +45,8 @@ impl MemoryManagement for MemIfImpl {
     unsafe fn deallocate(&mut self, _addr: PAddr) {}
 }
 
+struct MemIfImpl;
+
 #[impl_plat_interface]
 impl MemIf for MemIfImpl {
     /// Returns all physical memory (RAM) ranges on the platform.
@@ -51,9 +56,14 @@ impl MemIf for MemIfImpl {
 
     /// Returns all reserved physical memory ranges on the platform.
     ///
-    /// Lower 1MiB memory is reserved and not allocatable.
+    /// Lower 1MiB memory and the persistent log (with the `pstore` feature)
+    /// are reserved and not allocatable.
     fn reserved_phys_ram_ranges() -> &'static [RawRange] {
-        &[(0, 0x100000)]
+        #[cfg(feature = "pstore")]
+        const RESERVED_RANGES: &[RawRange] = &[(0, 0x100000), (PSTORE_PADDR, PSTORE_SIZE)];
+        #[cfg(not(feature = "pstore"))]
+        const RESERVED_RANGES: &[RawRange] = &[(0, 0x100000)];
+        RESERVED_RANGES
     }
 
     /// Returns all device memory (MMIO) ranges on the platform.
@@ -1,11 +1,17 @@
 //! Multi-processor booting.
 
 use axplat::mem::{PAGE_SIZE_4K, PhysAddr, pa};
+use axplat::power::{CpuState, CpuStates};
 use axplat::time::{Duration, busy_wait};
 
+use crate::config::plat::CPU_NUM;
+
 const START_PAGE_IDX: u8 = 6;
 const START_PAGE_PADDR: PhysAddr = pa!(START_PAGE_IDX as usize * PAGE_SIZE_4K);
 
+/// Power states of the CPUs, indexed by the CPU IDs given by the kernel.
+static CPU_STATES: CpuStates<CPU_NUM> = CpuStates::new();
+
 core::arch::global_asm!(
     include_str!("ap_start.S"),
     start_page_paddr = const START_PAGE_PADDR.as_usize(),
@@ -33,10 +39,15 @@ unsafe fn setup_startup_page(stack_top: PhysAddr) {
 }
 
 /// Starts the given secondary CPU with its boot stack.
-pub fn start_secondary_cpu(apic_id: usize, stack_top: PhysAddr) {
+///
+/// It also works for a CPU that has been stopped by [`stop_current_cpu`], as
+/// the INIT IPI resets it.
+pub fn start_secondary_cpu(cpu_id: usize, stack_top: PhysAddr) {
+    set_cpu_state(cpu_id, CpuState::Pending);
     unsafe { setup_startup_page(stack_top) };
 
-    let apic_id = super::apic::raw_apic_id(apic_id as u8);
+    // The CPU ID is its initial APIC ID, see `crate::current_cpu_id`.
+    let apic_id = super::apic::raw_apic_id(cpu_id as u8);
     let lapic = super::apic::local_apic();
 
     // INIT-SIPI-SIPI Sequence
@@ -47,3 +58,22 @@ pub fn start_secondary_cpu(apic_id: usize, stack_top: PhysAddr) {
     busy_wait(Duration::from_micros(200)); // 200us
     unsafe { lapic.send_sipi(START_PAGE_IDX, apic_id) };
 }
+
+/// Records the power state of the given CPU.
+pub fn set_cpu_state(cpu_id: usize, state: CpuState) {
+    CPU_STATES.set(cpu_id, state);
+}
+
+/// Returns the power state of the given CPU.
+pub fn cpu_state(cpu_id: usize) -> CpuState {
+    CPU_STATES.get(cpu_id)
+}
+
+/// Stops the current CPU until it receives an INIT IPI.
+pub fn stop_current_cpu() -> ! {
+    axcpu::asm::disable_irqs();
+    set_cpu_state(crate::current_cpu_id(), CpuState::Off);
+    loop {
+        axcpu::asm::halt();
+    }
+}
@@ -1,8 +1,175 @@
 //! Power management.
 
-use axplat::power::PowerIf;
+use axplat::mem::{pa, phys_to_virt};
+#[cfg(feature = "smp")]
+use axplat::power::CpuState;
+use axplat::power::{PowerIf, ResetKind, SuspendState};
+use axplat::time::{Duration, busy_wait};
+use lazyinit::LazyInit;
 use x86_64::instructions::port::PortWriteOnly;
 
+/// The reset register described in the ACPI FADT, with format (`port`,
+/// `value`).
+///
+/// Only the reset register in the system I/O space is supported, since the
+/// memory-mapped one may not be mapped by the kernel.
+static ACPI_RESET_REG: LazyInit<Option<(u16, u8)>> = LazyInit::new();
+
+/// Returns the bytes of an ACPI table (or a part of it) in physical memory.
+unsafe fn acpi_bytes(paddr: usize, len: usize) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(phys_to_virt(pa!(paddr)).as_ptr(), len) }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Finds the reset register in the ACPI FADT (Fixed ACPI Description Table).
+///
+/// See the [ACPI specification](https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fadt)
+/// for the table layouts.
+fn find_acpi_reset_reg() -> Option<(u16, u8)> {
+    const SDT_HEADER_SIZE: usize = 36;
+    const FADT_FLAGS: usize = 112;
+    const FADT_RESET_REG: usize = 116;
+    const FADT_RESET_VALUE: usize = 128;
+    const FADT_RESET_REG_SUP: u32 = 1 << 10;
+    const GAS_SYSTEM_IO: u8 = 1;
+
+    let rsdp = unsafe { acpi_bytes(axplat::boot::boot_info().acpi_rsdp?.as_usize(), 36) };
+    // Use XSDT for ACPI 2.0+, otherwise RSDT.
+    let (sdt_paddr, entry_size) = match (rsdp[15], read_u64(rsdp, 24)) {
+        (2.., xsdt) if xsdt != 0 => (xsdt as usize, 8),
+        _ => (read_u32(rsdp, 16) as usize, 4),
+    };
+    let sdt_len = read_u32(unsafe { acpi_bytes(sdt_paddr, SDT_HEADER_SIZE) }, 4) as usize;
+    let sdt = unsafe { acpi_bytes(sdt_paddr, sdt_len) };
+
+    for offset in (SDT_HEADER_SIZE..sdt_len).step_by(entry_size) {
+        let table_paddr = match entry_size {
+            8 => read_u64(sdt, offset) as usize,
+            _ => read_u32(sdt, offset) as usize,
+        };
+        let header = unsafe { acpi_bytes(table_paddr, SDT_HEADER_SIZE) };
+        if &header[..4] != b"FACP" {
+            continue;
+        }
+        let fadt_len = read_u32(header, 4) as usize;
+        if fadt_len <= FADT_RESET_VALUE {
+            return None;
+        }
+        let fadt = unsafe { acpi_bytes(table_paddr, fadt_len) };
+        if read_u32(fadt, FADT_FLAGS) & FADT_RESET_REG_SUP == 0
+            || fadt[FADT_RESET_REG] != GAS_SYSTEM_IO
+        {
+            return None;
+        }
+        let port = read_u64(fadt, FADT_RESET_REG + 4) as u16;
+        return Some((port, fadt[FADT_RESET_VALUE]));
+    }
+    None
+}
+
+/// Looks up the ACPI reset register.
+///
+/// It must be called while the ACPI tables are still accessible through the
+/// early page table.
+pub fn init() {
+    ACPI_RESET_REG.init_once(find_acpi_reset_reg());
+}
+
+/// Resets the system by the ACPI reset register, the reset control register
+/// (port `0xcf9`), or the keyboard controller, in order.
+///
+/// See <https://wiki.osdev.org/Reboot> for more information.
+fn reset(kind: ResetKind) -> ! {
+    info!("Resetting the system...");
+
+    // The behavior of the ACPI reset register is platform-defined, which is
+    // usually a cold reset.
+    if kind == ResetKind::Cold
+        && let Some(Some((port, value))) = ACPI_RESET_REG.get()
+    {
+        unsafe { PortWriteOnly::new(*port).write(*value) };
+        busy_wait(Duration::from_millis(50));
+    }
+
+    // Bit 1: system reset, bit 2: reset CPU, bit 3: full (cold) reset.
+    let cf9_value: u8 = match kind {
+        ResetKind::Cold => 0x0e,
+        ResetKind::Warm => 0x06,
+    };
+    unsafe {
+        let mut cf9 = PortWriteOnly::new(0xcf9);
+        cf9.write(0x02u8);
+        cf9.write(cf9_value);
+    }
+    busy_wait(Duration::from_millis(50));
+
+    // Pulse the reset line by the keyboard controller.
+    unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+    busy_wait(Duration::from_millis(50));
+
+    warn!("It should reset!");
+    loop {
+        axcpu::asm::halt();
+    }
+}
+
+/// Returns the MWAIT hint for the given state, or `None` if MWAIT (with
+/// interrupts as break events) is not supported.
+///
+/// [`SuspendState::Standby`] is C1, and [`SuspendState::PowerDown`] is the
+/// deepest C-state enumerated by CPUID. The latter falls back to C1 if the
+/// local APIC timer may stop in deep C-states (i.e., no ARAT).
+fn mwait_hint(state: SuspendState) -> Option<u32> {
+    let cpuid = raw_cpuid::CpuId::new();
+    if !cpuid.get_feature_info()?.has_monitor_mwait() {
+        return None;
+    }
+    let mwait = cpuid.get_monitor_mwait_info()?;
+    if !mwait.extensions_supported() || !mwait.interrupts_as_break_event() {
+        return None;
+    }
+    let arat = cpuid
+        .get_thermal_power_info()
+        .is_some_and(|info| info.has_arat());
+    if state == SuspendState::Standby || !arat {
+        return Some(0);
+    }
+    // Number of sub-states of C1..C7. The hint is ((Cn - 1) << 4 | sub-state).
+    let sub_states = [
+        mwait.supported_c1_states(),
+        mwait.supported_c2_states(),
+        mwait.supported_c3_states(),
+        mwait.supported_c4_states(),
+        mwait.supported_c5_states(),
+        mwait.supported_c6_states(),
+        mwait.supported_c7_states(),
+    ];
+    let (cstate, &count) = sub_states.iter().enumerate().rfind(|(_, n)| **n != 0)?;
+    Some(((cstate as u32) << 4) | (count as u32 - 1))
+}
+
+/// Waits in the C-state given by the MWAIT hint, until an interrupt arrives
+/// (even if interrupts are disabled).
+fn mwait(hint: u32) {
+    let monitor = 0u64;
+    unsafe {
+        core::arch::asm!(
+            "monitor",
+            in("rax") &raw const monitor,
+            in("ecx") 0,
+            in("edx") 0,
+        );
+        core::arch::asm!("mwait", in("eax") hint, in("ecx") 1);
+    }
+}
+
 struct PowerImpl;
 
 #[impl_plat_interface]
@@ -14,10 +181,41 @@ impl PowerIf for PowerImpl {
     /// CPU cores on the platform).
     #[cfg(feature = "smp")]
     fn cpu_boot(cpu_id: usize, stack_top_paddr: usize) {
-        use axplat::mem::pa;
         crate::mp::start_secondary_cpu(cpu_id, pa!(stack_top_paddr))
     }
 
+    /// Powers off the current CPU core.
+    ///
+    /// The core is halted with interrupts disabled, until it is woken up by
+    /// the INIT-SIPI-SIPI sequence in [`cpu_boot`](PowerIf::cpu_boot).
+    #[cfg(feature = "smp")]
+    fn cpu_off() -> ! {
+        crate::mp::stop_current_cpu()
+    }
+
+    /// Returns the power state of the given CPU core.
+    #[cfg(feature = "smp")]
+    fn cpu_state(cpu_id: usize) -> CpuState {
+        crate::mp::cpu_state(cpu_id)
+    }
+
+    /// Suspends the calling CPU core in the given low-power state.
+    ///
+    /// It is done by MWAIT C-states. If MWAIT is not supported,
+    /// [`SuspendState::Standby`] falls back to `hlt`, where the pending
+    /// interrupt is handled before return.
+    fn cpu_suspend(state: SuspendState) -> bool {
+        match mwait_hint(state) {
+            Some(hint) => mwait(hint),
+            None if state == SuspendState::Standby => {
+                x86_64::instructions::interrupts::enable_and_hlt();
+                x86_64::instructions::interrupts::disable();
+            }
+            None => return false,
+        }
+        true
+    }
+
     /// Shutdown the whole system (in QEMU).
     ///
     /// See <https://wiki.osdev.org/Shutdown> for more information.
@@ -25,12 +223,12 @@ impl PowerIf for PowerImpl {
         info!("Shutting down...");
 
         // For real hardware platforms, using port `0x604` to shutdown does not
-        // work. Therefore we use port `0x64` to reboot the system instead.
+        // work. Therefore we reboot the system instead.
         if cfg!(feature = "reboot-on-system-off") {
             axplat::console_println!("System will reboot, press any key to continue ...");
             while super::console::getchar().is_none() {}
             axplat::console_println!("Rebooting ...");
-            unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+            reset(ResetKind::Cold);
         } else {
             unsafe { PortWriteOnly::new(0x604).write(0x2000u16) };
         }
@@ -41,4 +239,9 @@ impl PowerIf for PowerImpl {
             axcpu::asm::halt();
         }
     }
+
+    /// Resets (reboots) the whole system.
+    fn system_reset(kind: ResetKind) -> ! {
+        reset(kind)
+    }
 }
@@ -1,35 +1,228 @@
 //! Time management.
 //!
-//! Currently, the TSC is used as the clock source.
+//! Currently, the TSC is used as the clock source, and the LAPIC timer is used
+//! for timer interrupts.
+//!
+//! Their frequencies are read from CPUID leaf 0x15 if present, or calibrated
+//! against the PIT (Programmable Interval Timer) otherwise.
+
+use core::sync::atomic::{AtomicU64, Ordering};
 
-use axplat::time::TimeIf;
+use axplat::time::{TickConverter, TimeIf};
 use raw_cpuid::CpuId;
+use x86_64::instructions::port::Port;
 
-#[cfg(feature = "irq")]
-use int_ratio::Ratio;
+/// Frequency of the PIT input clock in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
 
-#[cfg(feature = "irq")]
-const LAPIC_TICKS_PER_SEC: u64 = 1_000_000_000; // TODO: need to calibrate
+/// Duration of the calibration against the PIT in milliseconds.
+const CALIBRATION_MILLIS: u64 = 10;
+
+static mut TSC: TickConverter = TickConverter::zero();
 
 #[cfg(feature = "irq")]
-static mut NANOS_TO_LAPIC_TICKS_RATIO: Ratio = Ratio::zero();
+static mut LAPIC_TIMER: TickConverter = TickConverter::zero();
 
 static mut INIT_TICK: u64 = 0;
-static mut CPU_FREQ_MHZ: u64 = crate::config::devices::TIMER_FREQUENCY as u64 / 1_000_000;
 
 /// RTC wall time offset in nanoseconds at monotonic time base.
-static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
+static RTC_EPOCHOFFSET_NANOS: AtomicU64 = AtomicU64::new(0);
 
-pub fn init_early() {
+/// Returns the TSC frequency in Hz.
+pub fn tsc_frequency() -> u64 {
+    unsafe { TSC }.frequency()
+}
+
+/// Returns the LAPIC timer frequency in Hz, or 0 before it is calibrated in
+/// [`init_later`](axplat::init::init_later).
+#[cfg(feature = "irq")]
+pub fn lapic_timer_frequency() -> u64 {
+    unsafe { LAPIC_TIMER }.frequency()
+}
+
+/// Maximum number of PIT status reads while waiting for the calibration,
+/// which takes about 1µs each, so it gives up after about one second.
+const PIT_WAIT_LIMIT: usize = 1_000_000;
+
+/// Returns the number of ticks of a counter in [`CALIBRATION_MILLIS`], timed
+/// by the PIT channel 2, or `None` if the PIT does not respond.
+fn measure_with_pit(mut read_counter: impl FnMut() -> u64) -> Option<u64> {
+    let mut gate = Port::<u8>::new(0x61);
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel2 = Port::<u8>::new(0x42);
+    let count = PIT_FREQUENCY * CALIBRATION_MILLIS / 1_000;
+    unsafe {
+        // Disable the speaker, and stop channel 2 by its gate.
+        let val = gate.read() & !0x03;
+        gate.write(val);
+        // Channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count).
+        command.write(0b1011_0000);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+        // Start counting, and wait until the output goes high. The output is
+        // low until then, so it is already high if the PIT is absent.
+        gate.write(val | 0x01);
+        let start = read_counter();
+        let done = gate.read() & 0x20 == 0
+            && (0..PIT_WAIT_LIMIT).any(|_| {
+                core::hint::spin_loop();
+                gate.read() & 0x20 != 0
+            });
+        let end = read_counter();
+        gate.write(val);
+        done.then(|| end.wrapping_sub(start))
+    }
+}
+
+/// Returns the TSC frequency in Hz.
+///
+/// CPUID leaf 0x16 is not used, as it reports the processor base frequency,
+/// which may differ from the TSC frequency.
+fn detect_tsc_frequency() -> u64 {
     if let Some(freq) = CpuId::new()
-        .get_processor_frequency_info()
-        .map(|info| info.processor_base_frequency())
-        && freq > 0
+        .get_tsc_info()
+        .and_then(|info| info.tsc_frequency())
     {
-        unsafe { CPU_FREQ_MHZ = freq as u64 }
+        return freq;
+    }
+    measure_with_pit(|| unsafe { core::arch::x86_64::_rdtsc() })
+        .map(|ticks| ticks * 1_000 / CALIBRATION_MILLIS)
+        .unwrap_or(crate::config::devices::TIMER_FREQUENCY as u64)
+}
+
+/// Returns the LAPIC timer frequency in Hz, with the divider set to 1.
+///
+/// The LAPIC timer runs at the core crystal clock if it is reported by CPUID
+/// leaf 0x15. Otherwise, it is calibrated against the PIT, or against the TSC
+/// if the PIT does not respond.
+#[cfg(feature = "irq")]
+fn detect_lapic_timer_frequency() -> u64 {
+    if let Some(info) = CpuId::new().get_tsc_info()
+        && info.nominal_frequency() > 0
+    {
+        return info.nominal_frequency() as u64;
+    }
+    let lapic = super::apic::local_apic();
+    let read_counter = || (u32::MAX - super::apic::timer_current_count()) as u64;
+    unsafe {
+        lapic.set_timer_mode(x2apic::lapic::TimerMode::OneShot);
+        lapic.set_timer_initial(u32::MAX);
+    }
+    let ticks = measure_with_pit(read_counter).unwrap_or_else(|| {
+        let rdtsc = || unsafe { core::arch::x86_64::_rdtsc() };
+        let start = read_counter();
+        let deadline = rdtsc() + unsafe { TSC }.nanos_to_ticks(CALIBRATION_MILLIS * 1_000_000);
+        while rdtsc() < deadline {
+            core::hint::spin_loop();
+        }
+        read_counter() - start
+    });
+    unsafe { lapic.set_timer_initial(0) };
+    ticks * 1_000 / CALIBRATION_MILLIS
+}
+
+/// The alarm of the CMOS RTC.
+#[cfg(all(feature = "irq", feature = "rtc"))]
+mod cmos_alarm {
+    use x86_64::instructions::port::Port;
+
+    const SECONDS_ALARM: u8 = 0x01;
+    const MINUTES_ALARM: u8 = 0x03;
+    const HOURS_ALARM: u8 = 0x05;
+    const STATUS_B: u8 = 0x0b;
+    const STATUS_C: u8 = 0x0c;
+
+    /// Alarm interrupt enable in status register B.
+    const B_AIE: u8 = 1 << 5;
+    /// Binary (not BCD) mode in status register B.
+    const B_BINARY: u8 = 1 << 2;
+    /// 24-hour mode in status register B.
+    const B_24H: u8 = 1 << 1;
+    /// Alarm flag in status register C.
+    const C_AF: u8 = 1 << 5;
+
+    const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+    fn read(reg: u8) -> u8 {
+        unsafe {
+            // Keep NMIs disabled while accessing the CMOS.
+            Port::<u8>::new(0x70).write(0x80 | reg);
+            Port::<u8>::new(0x71).read()
+        }
+    }
+
+    fn write(reg: u8, value: u8) {
+        unsafe {
+            Port::<u8>::new(0x70).write(0x80 | reg);
+            Port::<u8>::new(0x71).write(value);
+        }
+    }
+
+    /// Sets the alarm at the given time in seconds since epoch.
+    ///
+    /// The alarm matches the time of day, so it must be within a day. Returns
+    /// `false` otherwise.
+    pub fn set(unix_secs: u64) -> bool {
+        let now = x86_rtc::Rtc::new().get_unix_timestamp();
+        if unix_secs >= now + SECS_PER_DAY {
+            return false;
+        }
+        let secs = unix_secs.max(now + 1) % SECS_PER_DAY;
+        let (hour, minute, second) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+        let status_b = read(STATUS_B);
+        let encode = |v: u64| {
+            if status_b & B_BINARY != 0 {
+                v as u8
+            } else {
+                (((v / 10) << 4) | (v % 10)) as u8
+            }
+        };
+        let hour = if status_b & B_24H != 0 {
+            encode(hour)
+        } else {
+            // 12-hour mode, where bit 7 means PM.
+            let pm = if hour >= 12 { 0x80 } else { 0 };
+            encode((hour + 11) % 12 + 1) | pm
+        };
+        write(STATUS_B, status_b & !B_AIE);
+        write(SECONDS_ALARM, encode(second));
+        write(MINUTES_ALARM, encode(minute));
+        write(HOURS_ALARM, hour);
+        read(STATUS_C);
+        write(STATUS_B, status_b | B_AIE);
+        true
     }
 
-    axplat::console_println!("TSC frequency: {} MHz", unsafe { CPU_FREQ_MHZ });
+    /// Cancels the pending alarm, and clears the interrupt.
+    pub fn cancel() {
+        write(STATUS_B, read(STATUS_B) & !B_AIE);
+        read(STATUS_C);
+    }
+
+    /// Handles the RTC interrupt, by disabling the alarm and calling
+    /// [`axplat::time::notify_alarm`].
+    pub fn irq_handler(_vector: usize) {
+        // Reading status register C acknowledges the interrupt.
+        if read(STATUS_C) & C_AF != 0 {
+            cancel();
+            axplat::time::notify_alarm();
+        }
+    }
+}
+
+/// Registers the RTC interrupt handler for alarms.
+#[cfg(feature = "irq")]
+pub fn init_irq() {
+    #[cfg(feature = "rtc")]
+    axplat::irq::register(crate::config::devices::RTC_IRQ, cmos_alarm::irq_handler);
+}
+
+pub fn init_early() {
+    let freq = detect_tsc_frequency();
+    unsafe { TSC = TickConverter::new(freq) };
+
+    axplat::console_println!("TSC frequency: {} MHz", freq / 1_000_000);
 
     unsafe {
         INIT_TICK = core::arch::x86_64::_rdtsc();
@@ -42,9 +235,10 @@ pub fn init_early() {
         // Get the current time in microseconds since the epoch (1970-01-01) from the x86 RTC.
         // Subtract the timer ticks to get the actual time when ArceOS was booted.
         let eopch_time_nanos = Rtc::new().get_unix_timestamp() * 1_000_000_000;
-        unsafe {
-            RTC_EPOCHOFFSET_NANOS = eopch_time_nanos - axplat::time::ticks_to_nanos(INIT_TICK);
-        }
+        RTC_EPOCHOFFSET_NANOS.store(
+            eopch_time_nanos - axplat::time::ticks_to_nanos(unsafe { INIT_TICK }),
+            Ordering::Release,
+        );
     }
 }
 
@@ -53,15 +247,13 @@ pub fn init_primary() {
     unsafe {
         use x2apic::lapic::{TimerDivide, TimerMode};
         let lapic = super::apic::local_apic();
-        lapic.set_timer_mode(TimerMode::OneShot);
         lapic.set_timer_divide(TimerDivide::Div1);
         lapic.enable_timer();
 
-        // TODO: calibrate with HPET
-        NANOS_TO_LAPIC_TICKS_RATIO = Ratio::new(
-            LAPIC_TICKS_PER_SEC as u32,
-            axplat::time::NANOS_PER_SEC as u32,
-        );
+        let freq = detect_lapic_timer_frequency();
+        info!("LAPIC timer frequency: {} kHz", freq / 1_000);
+        LAPIC_TIMER = TickConverter::new(freq);
+        lapic.set_timer_mode(TimerMode::OneShot);
     }
 }
 
@@ -69,7 +261,9 @@ pub fn init_primary() {
 pub fn init_secondary() {
     #[cfg(feature = "irq")]
     unsafe {
-        crate::apic::local_apic().enable_timer();
+        let lapic = crate::apic::local_apic();
+        lapic.set_timer_divide(x2apic::lapic::TimerDivide::Div1);
+        lapic.enable_timer();
     }
 }
 
@@ -84,18 +278,30 @@ impl TimeIf for TimeIfImpl {
 
     /// Converts hardware ticks to nanoseconds.
     fn ticks_to_nanos(ticks: u64) -> u64 {
-        ticks * 1_000 / unsafe { CPU_FREQ_MHZ }
+        unsafe { TSC }.ticks_to_nanos(ticks)
     }
 
     /// Converts nanoseconds to hardware ticks.
     fn nanos_to_ticks(nanos: u64) -> u64 {
-        nanos * unsafe { CPU_FREQ_MHZ } / 1_000
+        unsafe { TSC }.nanos_to_ticks(nanos)
     }
 
     /// Return epoch offset in nanoseconds (wall time offset to monotonic
     /// clock start).
     fn epochoffset_nanos() -> u64 {
-        unsafe { RTC_EPOCHOFFSET_NANOS }
+        RTC_EPOCHOFFSET_NANOS.load(Ordering::Acquire)
+    }
+
+    /// Set epoch offset in nanoseconds, and write the new wall time back to
+    /// the CMOS RTC.
+    fn set_epochoffset_nanos(epochoffset_nanos: u64) {
+        RTC_EPOCHOFFSET_NANOS.store(epochoffset_nanos, Ordering::Release);
+
+        #[cfg(feature = "rtc")]
+        {
+            let wall_time_nanos = axplat::time::wall_time_nanos();
+            x86_rtc::Rtc::new().set_unix_timestamp(wall_time_nanos / axplat::time::NANOS_PER_SEC);
+        }
     }
 
     /// Set a one-shot timer.
@@ -104,16 +310,68 @@ impl TimeIf for TimeIfImpl {
     /// deadline (in nanoseconds).
     #[cfg(feature = "irq")]
     fn set_oneshot_timer(deadline_ns: u64) {
+        use x2apic::lapic::TimerMode;
+
         let lapic = super::apic::local_apic();
         let now_ns = Self::ticks_to_nanos(Self::current_ticks());
         unsafe {
+            lapic.set_timer_mode(TimerMode::OneShot);
             if now_ns < deadline_ns {
-                let apic_ticks = NANOS_TO_LAPIC_TICKS_RATIO.mul_trunc(deadline_ns - now_ns);
-                assert!(apic_ticks <= u32::MAX as u64);
-                lapic.set_timer_initial(apic_ticks.max(1) as u32);
+                // A deadline beyond the 32-bit counter fires early, at the
+                // longest timeout, where the handler re-arms the timer.
+                let apic_ticks = { LAPIC_TIMER }.nanos_to_ticks(deadline_ns - now_ns);
+                lapic.set_timer_initial(apic_ticks.clamp(1, u32::MAX as u64) as u32);
             } else {
                 lapic.set_timer_initial(1);
             }
         }
     }
+
+    /// Set a periodic timer.
+    ///
+    /// The LAPIC timer is switched to the periodic mode, where the initial
+    /// count is reloaded each time it reaches zero. Returns `false` if the
+    /// period does not fit in the initial count.
+    #[cfg(feature = "irq")]
+    fn set_periodic_timer(period_ns: u64) -> bool {
+        use x2apic::lapic::TimerMode;
+
+        let lapic = super::apic::local_apic();
+        unsafe {
+            let apic_ticks = { LAPIC_TIMER }.nanos_to_ticks(period_ns);
+            if apic_ticks > u32::MAX as u64 {
+                return false;
+            }
+            lapic.set_timer_mode(TimerMode::Periodic);
+            lapic.set_timer_initial(apic_ticks.max(1) as u32);
+        }
+        true
+    }
+
+    /// Cancel the pending timer on the current CPU.
+    ///
+    /// Writing zero to the initial count stops the LAPIC timer.
+    #[cfg(feature = "irq")]
+    fn cancel_timer() {
+        unsafe { super::apic::local_apic().set_timer_initial(0) };
+    }
+
+    /// Set an RTC alarm at the specified wall time (in nanoseconds since
+    /// epoch), by the CMOS RTC alarm.
+    ///
+    /// It must be within a day, as the alarm only matches the time of day.
+    #[cfg(feature = "irq")]
+    fn set_rtc_alarm(_wall_time_ns: u64) -> bool {
+        #[cfg(feature = "rtc")]
+        return cmos_alarm::set(_wall_time_ns.div_ceil(axplat::time::NANOS_PER_SEC));
+        #[cfg(not(feature = "rtc"))]
+        return false;
+    }
+
+    /// Cancel the pending RTC alarm, if any.
+    #[cfg(feature = "irq")]
+    fn cancel_rtc_alarm() {
+        #[cfg(feature = "rtc")]
+        cmos_alarm::cancel();
+    }
 }